@@ -0,0 +1,112 @@
+//! Benchmarks for layout computation and lowering. Run with `cargo bench`.
+
+extern crate test;
+
+use test::Bencher;
+
+use crate::hir;
+use crate::layout_of::{layout_of_with, LayoutCx, DEFAULT};
+use crate::lower::lower_root_expr_with;
+use crate::{parse, parse_ty};
+
+const DEPTH: usize = 5;
+
+/// A list of lists of ... of `U64`s, `depth` levels deep.
+fn deep_list(depth: usize) -> String {
+    (0..depth).fold("U64".to_owned(), |elem, _| {
+        format!("µL. Box[< Nil of {{}} | Cons of {{ hd : {}, tl : L }} >]", elem)
+    })
+}
+
+/// A tree of trees of ... of `U64`s, `depth` levels deep.
+fn deep_tree(depth: usize) -> String {
+    (0..depth).fold("U64".to_owned(), |elem, _| {
+        format!("µT. Box[< Leaf of {{}} | Node of {{ l : T, v : {}, r : T }} >]", elem)
+    })
+}
+
+/// A list of `len` `U64`s.
+fn long_list(len: usize) -> String {
+    let list = deep_list(1);
+    let node = format!("< Nil of {{}} | Cons of {{ hd : U64, tl : {} }} >", list);
+    let nil = format!("fold [{}] box (<Nil = {{}}> as {})", list, node);
+    (0..len).fold(nil, |tl, hd| {
+        format!("fold [{}] box (<Cons = {{ hd = {}, tl = {} }}> as {})", list, hd, tl, node)
+    })
+}
+
+/// `len` nested `let`s.
+fn long_let_chain(len: usize) -> String {
+    (0..len).rev().fold(format!("x{} : U64", len - 1), |body, i| {
+        format!("let x{} : U64 = {} in {}", i, i, body)
+    })
+}
+
+/// A record of records of ... of `U64`s, `depth` levels deep.
+fn nested_record(depth: usize) -> String {
+    (0..depth).fold("0".to_owned(), |inner, i| format!("{{ a = {}, b = {} }}", inner, i))
+}
+
+/// What lowering used to do for each record expression: compute its type from
+/// the expressions of its fields, and intern that type again to look up its
+/// layout.
+fn layout_records_by_ty(layouts: &mut LayoutCx, expr: &hir::Expr) {
+    if let hir::Expr::Record(fields) = expr {
+        layouts.layout_of(&expr.ty());
+        for field in fields.values() {
+            layout_records_by_ty(layouts, field);
+        }
+    }
+}
+
+#[bench]
+fn layout_deep_list(b: &mut Bencher) {
+    let ty = parse_ty(&deep_list(DEPTH));
+    b.iter(|| layout_of_with(ty.clone(), DEFAULT));
+}
+
+#[bench]
+fn layout_deep_list_warm(b: &mut Bencher) {
+    let ty = parse_ty(&deep_list(DEPTH));
+    let mut layouts = LayoutCx::new(DEFAULT);
+    b.iter(|| layouts.layout_of(&ty));
+}
+
+#[bench]
+fn layout_deep_tree(b: &mut Bencher) {
+    let ty = parse_ty(&deep_tree(DEPTH));
+    b.iter(|| layout_of_with(ty.clone(), DEFAULT));
+}
+
+#[bench]
+fn layout_deep_tree_warm(b: &mut Bencher) {
+    let ty = parse_ty(&deep_tree(DEPTH));
+    let mut layouts = LayoutCx::new(DEFAULT);
+    b.iter(|| layouts.layout_of(&ty));
+}
+
+#[bench]
+fn lower_long_list(b: &mut Bencher) {
+    let expr = parse(&long_list(100));
+    b.iter(|| lower_root_expr_with(expr.clone(), DEFAULT));
+}
+
+#[bench]
+fn lower_long_let_chain(b: &mut Bencher) {
+    let expr = parse(&long_let_chain(500));
+    b.iter(|| lower_root_expr_with(expr.clone(), DEFAULT));
+}
+
+#[bench]
+fn lower_nested_record(b: &mut Bencher) {
+    let expr = parse(&nested_record(200));
+    b.iter(|| lower_root_expr_with(expr.clone(), DEFAULT));
+}
+
+/// The layout lookups that [`lower_nested_record`] used to do, to compare it
+/// against.
+#[bench]
+fn lower_nested_record_by_ty(b: &mut Bencher) {
+    let expr = parse(&nested_record(200));
+    b.iter(|| layout_records_by_ty(&mut LayoutCx::new(DEFAULT), &expr));
+}
@@ -0,0 +1,510 @@
+//! C headers for the lowered layouts of types, for FFI.
+//!
+//! Records become structs and untagged unions become unions. A recursive type
+//! is its body's struct or union, or a `typedef` if its body is a pointer.
+//! Every struct and union is declared up front, so that pointers to them can
+//! be used anywhere.
+//!
+//! Structs have explicit padding, and structs and unions are aligned like
+//! their layouts. Packed and compact records, whose fields aren't where C
+//! would put them, are packed, and so are types whose size isn't a multiple of
+//! their alignment, which C can't align. `_Static_assert`s check each size,
+//! alignment, and field offset. Empty structs are a GNU C extension.
+//!
+//! Each tagged variant type gets an `enum` of its variants, a function that
+//! reads the tag to tell which variant a value is, and a function per variant
+//! that constructs it.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::hir;
+use crate::int_ty::IntTy;
+use crate::layout::{
+    Layout, TagLayout, TagPath, TaggedLayout, ValueProj, VariantLayout, VariantRepr,
+};
+use crate::layout_of::{is_nicheable, LayoutCx};
+use crate::lir::{self, RecordRepr, Shape, Size};
+use crate::lower::lower_layout;
+use crate::name::Name;
+
+/// The enclosing recursive types, innermost last, with their C types and
+/// shapes.
+type Env = Vec<(String, Shape)>;
+
+#[derive(Default)]
+struct Ctxt {
+    /// The C type of each lowered type, by the type and the C types of the
+    /// enclosing recursive types.
+    names: HashMap<(String, Vec<String>), String>,
+    /// The number of named types so far.
+    count: usize,
+    forward_decls: Vec<String>,
+    typedefs: Vec<String>,
+    defs: Vec<String>,
+    defined: HashSet<String>,
+    /// Whether any struct or union is packed.
+    packed: bool,
+    /// Structs and unions behind pointers. They are defined last, so that
+    /// they can contain the types that point to them.
+    pending: Vec<(lir::Ty, Env, String)>,
+    accessors: Vec<String>,
+}
+
+fn key(ty: &lir::Ty, env: &Env) -> (String, Vec<String>) {
+    (ty.to_string(), env.iter().map(|(c_ty, _)| c_ty.clone()).collect())
+}
+
+fn shapes(env: &Env) -> Vec<Shape> {
+    env.iter().map(|(_, shape)| *shape).collect()
+}
+
+fn push(env: &Env, c_ty: &str, shape: Shape) -> Env {
+    let mut env = env.clone();
+    env.push((c_ty.to_owned(), shape));
+    env
+}
+
+/// A C header that declares the lowered layout of `ty`, with accessors for
+/// its variant types.
+pub fn c_header(ty: &hir::Ty, layouts: &mut LayoutCx) -> String {
+    let layout = layouts.layout_of(ty);
+    let lowered = lower_layout(layout.clone());
+
+    let mut cx = Ctxt::default();
+    let root = cx.c_ty(&lowered, &vec![], true);
+    cx.accessors(&layout, &mut vec![]);
+    let mut idx = 0;
+    while let Some((ty, env, c_ty)) = cx.pending.get(idx).cloned() {
+        cx.define(&c_ty, &ty, &env);
+        idx += 1;
+    }
+
+    let mut header = String::new();
+    writeln!(header, "/* {} (layout strategy `{}`) */", ty, layouts.strategy().name()).unwrap();
+    writeln!(header, "/* The root type is `{}`, with {}. */", root, lowered.shape()).unwrap();
+    header.push_str(
+        "\n#pragma once\n\n#include <stddef.h>\n#include <stdint.h>\n#include <string.h>\n",
+    );
+    for section in [&cx.forward_decls, &cx.typedefs] {
+        if !section.is_empty() {
+            writeln!(header, "\n{}", section.join("\n")).unwrap();
+        }
+    }
+    // Packed structs can have fields whose types are aligned more than their
+    // offsets, which GCC warns about.
+    if cx.packed {
+        header.push_str("\n#pragma GCC diagnostic push\n");
+        header.push_str("#if defined(__GNUC__) && !defined(__clang__)\n");
+        header.push_str("#pragma GCC diagnostic ignored \"-Wpacked-not-aligned\"\n#endif\n");
+    }
+    for def in &cx.defs {
+        writeln!(header, "\n{}", def).unwrap();
+    }
+    if cx.packed {
+        header.push_str("\n#pragma GCC diagnostic pop\n");
+    }
+    for accessor in &cx.accessors {
+        writeln!(header, "\n{}", accessor).unwrap();
+    }
+    header
+}
+
+impl Ctxt {
+    /// The C type of `ty`. Its struct or union is defined right away if
+    /// `define` is set, and at the end otherwise.
+    fn c_ty(&mut self, ty: &lir::Ty, env: &Env, define: bool) -> String {
+        match ty {
+            lir::Ty::Int(int_ty) => c_int_ty(*int_ty),
+            lir::Ty::Ptr(pointee) => pointer_to(&self.c_ty(pointee, env, false)),
+            lir::Ty::Record(..) | lir::Ty::UntaggedUnion(_) => {
+                let c_ty = match self.names.get(&key(ty, env)) {
+                    Some(c_ty) => c_ty.clone(),
+                    None => {
+                        let c_ty = self.declare(ty);
+                        self.names.insert(key(ty, env), c_ty.clone());
+                        c_ty
+                    }
+                };
+                if define {
+                    self.define(&c_ty, ty, env);
+                } else if !self.defined.contains(&c_ty) {
+                    self.pending.push((ty.clone(), env.clone(), c_ty.clone()));
+                }
+                c_ty
+            }
+            lir::Ty::Recursive(body) => {
+                let shape = ty.try_shape_inside(&shapes(env)).unwrap();
+                let c_ty = match self.names.get(&key(ty, env)) {
+                    Some(c_ty) => c_ty.clone(),
+                    // The body is named after the recursive type.
+                    None if is_aggregate(body) => {
+                        let c_ty = self.declare(body);
+                        self.names.insert(key(ty, env), c_ty.clone());
+                        self.names.insert(key(body, &push(env, &c_ty, shape)), c_ty.clone());
+                        c_ty
+                    }
+                    None => {
+                        let name = format!("t{}", self.count);
+                        self.count += 1;
+                        self.names.insert(key(ty, env), name.clone());
+                        let target = self.c_ty(body, &push(env, &name, shape), false);
+                        self.typedefs.push(format!("typedef {};", declarator(&target, &name)));
+                        name
+                    }
+                };
+                if is_aggregate(body) {
+                    self.c_ty(body, &push(env, &c_ty, shape), define);
+                }
+                c_ty
+            }
+            lir::Ty::RecurId(id) => env[env.len() - 1 - id.index() as usize].0.clone(),
+        }
+    }
+
+    /// Name a struct or union, and declare it up front.
+    fn declare(&mut self, ty: &lir::Ty) -> String {
+        let keyword = match ty {
+            lir::Ty::UntaggedUnion(_) => "union",
+            _ => "struct",
+        };
+        let c_ty = format!("{} t{}", keyword, self.count);
+        self.count += 1;
+        self.forward_decls.push(format!("{};", c_ty));
+        c_ty
+    }
+
+    /// Define a struct or union, after the types of its fields.
+    fn define(&mut self, c_ty: &str, ty: &lir::Ty, env: &Env) {
+        if !self.defined.insert(c_ty.to_owned()) {
+            return;
+        }
+        let shapes = shapes(env);
+        let shape_of = |ty: &lir::Ty| ty.try_shape_inside(&shapes).unwrap();
+        let (fields, offsets): (Vec<_>, _) = match ty {
+            lir::Ty::Record(fields, repr) => {
+                let field_shapes = fields.iter().map(|(_, t)| shape_of(t));
+                let (offsets, _) = Shape::record(field_shapes, *repr);
+                (fields.iter().map(|(n, t)| (n, t)).collect(), offsets)
+            }
+            lir::Ty::UntaggedUnion(variants) => {
+                (variants.iter().collect(), vec![Size::ZERO; variants.len()])
+            }
+            _ => unreachable!("not a struct or union: {}", ty),
+        };
+        let shape = shape_of(ty);
+
+        let mut body = String::new();
+        let mut asserts = vec![];
+        let mut end = 0;
+        for ((name, field_ty), offset) in fields.into_iter().zip(offsets) {
+            let c_field_ty = self.c_ty(field_ty, env, true);
+            if offset.bytes() > end {
+                writeln!(body, "    uint8_t _pad{}[{}];", end, offset.bytes() - end).unwrap();
+            }
+            writeln!(body, "    {};", declarator(&c_field_ty, &c_ident(name))).unwrap();
+            if let lir::Ty::Record(..) = ty {
+                let field = c_ident(name);
+                asserts.push(format!(
+                    "_Static_assert(offsetof({}, {}) == {}, \"offset of {}.{}\");",
+                    c_ty,
+                    field,
+                    offset.bytes(),
+                    c_ty,
+                    field
+                ));
+            }
+            end = end.max(offset.bytes() + shape_of(field_ty).size.bytes());
+        }
+        if shape.size.bytes() > end {
+            writeln!(body, "    uint8_t _pad{}[{}];", end, shape.size.bytes() - end).unwrap();
+        }
+        if !body.is_empty() {
+            body.insert(0, '\n');
+        }
+        // Compact and packed records put fields where C wouldn't. C also rounds
+        // sizes up to the alignment, so types whose size isn't a multiple of it
+        // can only be packed, with an alignment of one.
+        let (size, align) = (shape.size.bytes(), shape.align.bytes());
+        let aligned = size % align == 0;
+        let packed =
+            !aligned || matches!(ty, lir::Ty::Record(_, RecordRepr::Compact | RecordRepr::Packed));
+        let c_align = if aligned { align } else { 1 };
+        let mut attrs = vec![];
+        if packed {
+            self.packed = true;
+            attrs.push("packed".to_owned());
+        }
+        if c_align > 1 {
+            attrs.push(format!("aligned({})", c_align));
+        }
+        let attrs = if attrs.is_empty() {
+            String::new()
+        } else {
+            format!(" __attribute__(({}))", attrs.join(", "))
+        };
+
+        let mut def = format!("/* {} */\n", shape);
+        let (keyword, name) = c_ty.split_once(' ').unwrap();
+        writeln!(def, "{}{} {} {{{}}};", keyword, attrs, name, body).unwrap();
+        writeln!(def, "_Static_assert(sizeof({}) == {}, \"size of {}\");", c_ty, size, c_ty)
+            .unwrap();
+        writeln!(
+            def,
+            "_Static_assert(_Alignof({}) == {}, \"alignment of {}\");",
+            c_ty, c_align, c_ty
+        )
+        .unwrap();
+        for assert in asserts {
+            writeln!(def, "{}", assert).unwrap();
+        }
+        def.pop();
+        self.defs.push(def);
+    }
+
+    /// Add the accessors of the variant types in `layout`, following
+    /// [`lower_layout()`].
+    fn accessors(&mut self, layout: &Layout, env: &mut Env) {
+        match layout {
+            Layout::Int(..) | Layout::RecurId { .. } => {}
+            Layout::Ptr { pointee, niches: _ } => self.accessors(pointee, env),
+            Layout::Aggregate { fields, repr: _ } => {
+                for (_, field) in fields {
+                    self.accessors(field, env);
+                }
+            }
+            Layout::Variant(VariantLayout::Single { field }) => self.accessors(field, env),
+            Layout::Variant(VariantLayout::Tagged(tagged)) => {
+                self.variant_accessors(layout, tagged, env);
+                let variants: Vec<_> = match &tagged.tag {
+                    TagLayout::Direct { .. } => tagged.variants.values().collect(),
+                    TagLayout::Niche { .. } => {
+                        is_nicheable(&tagged.variants).into_field().into_iter().collect()
+                    }
+                    TagLayout::MultiNiche { field, .. } => {
+                        std::iter::once(&**field).chain(tagged.variants.values()).collect()
+                    }
+                    TagLayout::PointerTag { .. } => tagged
+                        .variants
+                        .values()
+                        .map(|variant| match variant {
+                            Layout::Ptr { pointee, niches: _ } => pointee,
+                            _ => variant,
+                        })
+                        .collect(),
+                };
+                for variant in variants {
+                    self.accessors(variant, env);
+                }
+            }
+            Layout::Recursive(body) => {
+                let lowered = lower_layout(layout.clone());
+                let c_ty = self.c_ty(&lowered, env, false);
+                let shape = lowered.try_shape_inside(&shapes(env)).unwrap();
+                env.push((c_ty, shape));
+                self.accessors(body, env);
+                env.pop();
+            }
+        }
+    }
+
+    /// An `enum` of the variants of `layout`, a function that reads which
+    /// variant a value is, and a function per variant that constructs it.
+    fn variant_accessors(&mut self, layout: &Layout, tagged: &TaggedLayout, env: &Env) {
+        let prefix = format!("v{}", self.accessors.len());
+        let c_ty = self.c_ty(&lower_layout(layout.clone()), env, false);
+        let TaggedLayout { tag, variants } = tagged;
+        let constant = |variant: &Name| format!("{}_{}", prefix.to_uppercase(), variant);
+
+        let names: Vec<_> = variants.keys().map(Name::to_string).collect();
+        let mut code = format!(
+            "/* < {} > as `{}` (tag layout: {}) */\n",
+            names.join(" | "),
+            c_ty,
+            tag.descr()
+        );
+        let constants: Vec<_> = variants.keys().map(constant).collect();
+        writeln!(code, "enum {} {{ {} }};", prefix, constants.join(", ")).unwrap();
+
+        // Which variant a value is.
+        let (tag_ty, path, values, holder) = match tag {
+            TagLayout::Direct { ty, values, niches: _, placement: _ } => {
+                (*ty, TagPath::singleton(ValueProj::Tag), values, None)
+            }
+            TagLayout::Niche { path, ty, values } => {
+                let holder = variants.keys().find(|&v| !values.contains_key(v));
+                (*ty, path.clone(), values, holder)
+            }
+            TagLayout::MultiNiche { holder, path, ty, values, .. } => {
+                (*ty, path.clone(), values, Some(holder))
+            }
+            TagLayout::PointerTag { bits: _, values } => {
+                (IntTy::U64, TagPath::empty(), values, None)
+            }
+        };
+        let param = declarator(&format!("{} const", c_ty), "*value");
+        writeln!(code, "\nstatic inline enum {} {}_variant({}) {{", prefix, prefix, param).unwrap();
+        writeln!(code, "    {} tag;", c_int_ty(tag_ty)).unwrap();
+        writeln!(code, "    memcpy(&tag, &(*value){}, sizeof tag);", c_path(&path)).unwrap();
+        if let TagLayout::PointerTag { bits, values: _ } = tag {
+            writeln!(code, "    tag &= {};", (1u64 << bits) - 1).unwrap();
+        }
+        code.push_str("    switch (tag) {\n");
+        for (variant, value) in values {
+            writeln!(code, "    case {}: return {};", value, constant(variant)).unwrap();
+        }
+        match holder {
+            Some(holder) => writeln!(code, "    default: return {};", constant(holder)).unwrap(),
+            None => code.push_str("    default: __builtin_unreachable();\n"),
+        }
+        code.push_str("    }\n}\n");
+
+        // Construct each variant.
+        for (variant, field) in variants {
+            // Zero-sized fields are left out.
+            let field_ty = |cx: &mut Self| cx.c_ty(&lower_layout(field.clone()), env, false);
+            let mut params = vec![];
+            let mut body = String::new();
+            let set_tag = |body: &mut String, value: u64| {
+                writeln!(body, "    {} tag = {};", c_int_ty(tag_ty), value).unwrap();
+                writeln!(body, "    memcpy(&value{}, &tag, sizeof tag);", c_path(&path)).unwrap();
+            };
+            let data = format!("value.data.{}", c_ident(variant));
+            match tag {
+                TagLayout::Direct { ty: _, values, niches: _, placement: _ } => {
+                    if !field.is_zst() {
+                        params.push(declarator(&field_ty(self), "field"));
+                        writeln!(body, "    {} = field;", data).unwrap();
+                    }
+                    writeln!(body, "    value.tag = {};", values[variant]).unwrap();
+                }
+                TagLayout::Niche { values, .. } => match values.get(variant) {
+                    Some(&value) => set_tag(&mut body, value),
+                    None => {
+                        params.push(declarator(&field_ty(self), "field"));
+                        body.push_str("    value = field;\n");
+                    }
+                },
+                // The holder's data is split into the tag and the rest.
+                TagLayout::MultiNiche { holder, hoisted, field: tag_field, .. }
+                    if variant == holder =>
+                {
+                    let tag_ty = self.c_ty(&lower_layout((**tag_field).clone()), env, false);
+                    match (hoisted, field) {
+                        (Some(hoisted), Layout::Aggregate { fields: rest, repr: _ }) => {
+                            let mut fields = vec![(hoisted, tag_ty, "value.tag".to_owned())];
+                            for (name, layout) in rest {
+                                let c_field_ty =
+                                    self.c_ty(&lower_layout(layout.clone()), env, false);
+                                fields.push((
+                                    name,
+                                    c_field_ty,
+                                    format!("{}.{}", data, c_ident(name)),
+                                ));
+                            }
+                            fields.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+                            for (name, c_field_ty, place) in fields {
+                                let param = format!("field_{}", name);
+                                params.push(declarator(&c_field_ty, &param));
+                                writeln!(body, "    {} = {};", place, param).unwrap();
+                            }
+                        }
+                        (Some(_), _) => unreachable!("hoisted field of a non-aggregate"),
+                        (None, _) => {
+                            params.push(declarator(&tag_ty, "field"));
+                            body.push_str("    value.tag = field;\n");
+                        }
+                    }
+                }
+                TagLayout::MultiNiche { values, .. } => {
+                    if !field.is_zst() {
+                        params.push(declarator(&field_ty(self), "field"));
+                        writeln!(body, "    {} = field;", data).unwrap();
+                    }
+                    set_tag(&mut body, values[variant]);
+                }
+                TagLayout::PointerTag { bits: _, values } => {
+                    writeln!(body, "    uint64_t bits = {};", values[variant]).unwrap();
+                    if !field.is_zst() {
+                        params.push(declarator(&field_ty(self), "field"));
+                        body.push_str("    uint64_t ptr;\n");
+                        body.push_str("    memcpy(&ptr, &field, sizeof ptr);\n");
+                        body.push_str("    bits |= ptr;\n");
+                    }
+                    body.push_str("    memcpy(&value, &bits, sizeof value);\n");
+                }
+            }
+            let params = if params.is_empty() { "void".to_owned() } else { params.join(", ") };
+            let function = format!("{}_make_{}({})", prefix, variant, params);
+            writeln!(code, "\nstatic inline {} {{", declarator(&c_ty, &function)).unwrap();
+            writeln!(code, "    {};", declarator(&c_ty, "value")).unwrap();
+            code.push_str("    memset(&value, 0, sizeof value);\n");
+            code.push_str(&body);
+            code.push_str("    return value;\n}\n");
+        }
+        code.pop();
+        self.accessors.push(code);
+    }
+}
+
+fn is_aggregate(ty: &lir::Ty) -> bool {
+    matches!(ty, lir::Ty::Record(..) | lir::Ty::UntaggedUnion(_))
+}
+
+fn c_int_ty(ty: IntTy) -> String {
+    format!("uint{}_t", ty.shape().size.bytes() * 8)
+}
+
+fn pointer_to(c_ty: &str) -> String {
+    if c_ty.ends_with('*') {
+        format!("{}*", c_ty)
+    } else {
+        format!("{} *", c_ty)
+    }
+}
+
+/// Declare `name` with type `c_ty`, like `uint8_t x` or `struct t0 *x`.
+fn declarator(c_ty: &str, name: &str) -> String {
+    if c_ty.ends_with('*') {
+        format!("{}{}", c_ty, name)
+    } else {
+        format!("{} {}", c_ty, name)
+    }
+}
+
+/// The member accesses along `path`, outermost first, like `.data.Some.x`.
+fn c_path(path: &TagPath) -> String {
+    let mut projs = path.clone().rfold(vec![], |mut projs, proj| {
+        projs.push(match proj {
+            ValueProj::Field(name) => format!(".{}", c_ident(&name)),
+            ValueProj::Variant { repr: VariantRepr::Wrapper, name } => {
+                format!(".data.{}", c_ident(&name))
+            }
+            ValueProj::Variant { repr: VariantRepr::Transparent, name: _ } => String::new(),
+            ValueProj::Tag => ".tag".to_owned(),
+        });
+        projs
+    });
+    // `rfold()` starts at the innermost projection.
+    projs.reverse();
+    projs.concat()
+}
+
+/// `name` as a C identifier: prefixed if it starts with a digit, and
+/// suffixed if it's a keyword.
+fn c_ident(name: &Name) -> String {
+    const KEYWORDS: &[&str] = &[
+        "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else",
+        "enum", "extern", "float", "for", "goto", "if", "inline", "int", "long", "register",
+        "restrict", "return", "short", "signed", "sizeof", "static", "struct", "switch", "typedef",
+        "union", "unsigned", "void", "volatile", "while",
+    ];
+    let name = name.to_string();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else if KEYWORDS.contains(&name.as_str()) {
+        format!("{}_", name)
+    } else {
+        name
+    }
+}
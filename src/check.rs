@@ -0,0 +1,250 @@
+//! Checking that a layout strategy's encoding of a finite type round-trips.
+//!
+//! Every value of the type is lowered and evaluated, and the resulting
+//! machine value is decoded again. The encoding must be injective, and
+//! decoding must give back the original value. The machine values must also
+//! still be distinct once they're stored in memory, where union members
+//! overlap, and reading them back must give the same bytes again. Finally,
+//! the bytecode VM must store the same values.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result};
+
+use crate::decode::{decode_value, DecodeError};
+use crate::eval::eval_root_expr;
+use crate::hir;
+use crate::int_ty::IntTy;
+use crate::layout_of::LayoutCx;
+use crate::lir;
+use crate::lower::{lower_layout, lower_root_expr_in};
+use crate::memory::{Memory, MemoryError};
+use crate::util::Map;
+use crate::vm::{compile, VmError};
+
+/// Types with more values than this aren't checked.
+pub const MAX_VALUES: usize = 1 << 17;
+
+#[derive(Debug)]
+pub enum CheckError {
+    /// The type has infinitely many values, or more than [`MAX_VALUES`].
+    TooManyValues,
+    NotInjective {
+        values: [hir::Value; 2],
+        encoded: lir::Value,
+    },
+    DecodeFailed {
+        value: hir::Value,
+        encoded: lir::Value,
+        error: DecodeError,
+    },
+    WrongDecode {
+        value: hir::Value,
+        encoded: lir::Value,
+        decoded: hir::Value,
+    },
+    StoreFailed {
+        value: hir::Value,
+        encoded: lir::Value,
+        error: MemoryError,
+    },
+    /// Two values are stored as the same bytes, listed in `bytes`.
+    NotInjectiveInMemory {
+        values: [hir::Value; 2],
+        bytes: String,
+    },
+    /// Reading `value`'s bytes back, following the layout, gives another
+    /// machine value than `value` is encoded as, or fails.
+    WrongReadBack {
+        value: hir::Value,
+        bytes: String,
+        read_back: std::result::Result<lir::Value, MemoryError>,
+    },
+    /// The VM's result, at `addr` in `bytes`, isn't `encoded`.
+    VmMismatch {
+        value: hir::Value,
+        encoded: lir::Value,
+        addr: u64,
+        bytes: String,
+    },
+    VmFailed {
+        value: hir::Value,
+        error: VmError,
+    },
+}
+
+/// Returns the number of values of `ty`, which all round-trip.
+pub fn check_round_trip(
+    ty: &hir::Ty,
+    layouts: &mut LayoutCx,
+) -> std::result::Result<usize, CheckError> {
+    let values = all_values(ty).ok_or(CheckError::TooManyValues)?;
+    let layout = layouts.layout_of(ty);
+    let lir_ty = lower_layout(layout.clone());
+    let mut encodings: HashMap<lir::Value, hir::Value> = HashMap::new();
+    let mut stored: HashMap<Vec<(u64, Vec<u8>)>, hir::Value> = HashMap::new();
+    for value in &values {
+        let expr = lower_root_expr_in(value_to_expr(value, ty), layouts);
+        let program = compile(&expr, &lir_ty);
+        let encoded = eval_root_expr(expr);
+        if let Some(other) = encodings.insert(encoded.clone(), value.clone()) {
+            return Err(CheckError::NotInjective { values: [other, value.clone()], encoded });
+        }
+        match decode_value(&encoded, ty, &layout) {
+            Ok(decoded) if decoded == *value => {}
+            Ok(decoded) => {
+                return Err(CheckError::WrongDecode { value: value.clone(), encoded, decoded })
+            }
+            Err(error) => {
+                return Err(CheckError::DecodeFailed { value: value.clone(), encoded, error })
+            }
+        }
+        let mut memory = Memory::new();
+        let addr = match memory.encode(&encoded, &lir_ty) {
+            Ok(addr) => addr,
+            Err(error) => {
+                return Err(CheckError::StoreFailed { value: value.clone(), encoded, error })
+            }
+        };
+        match memory.decode(addr, &lir_ty, &layout) {
+            Ok(read_back) if read_back == encoded => {}
+            read_back => {
+                let bytes = memory.hex_bytes();
+                return Err(CheckError::WrongReadBack { value: value.clone(), bytes, read_back });
+            }
+        }
+        let contents = memory.contents();
+        if let Some(other) = stored.insert(contents, value.clone()) {
+            let values = [other, value.clone()];
+            return Err(CheckError::NotInjectiveInMemory { values, bytes: memory.hex_bytes() });
+        }
+        let mut vm_memory = Memory::new();
+        match program.run(&mut vm_memory) {
+            Ok(addr) if vm_memory.decode(addr, &lir_ty, &layout).as_ref() == Ok(&encoded) => {}
+            Ok(addr) => {
+                let bytes = vm_memory.hex_bytes();
+                return Err(CheckError::VmMismatch { value: value.clone(), encoded, addr, bytes });
+            }
+            Err(error) => return Err(CheckError::VmFailed { value: value.clone(), error }),
+        }
+    }
+    Ok(values.len())
+}
+
+/// All values of `ty`, or `None` if there are more than [`MAX_VALUES`].
+pub fn all_values(ty: &hir::Ty) -> Option<Vec<hir::Value>> {
+    let values = match ty {
+        hir::Ty::Int(int_ty) => {
+            if int_ty.max_value() > IntTy::U16.max_value() {
+                return None;
+            }
+            (0..=int_ty.max_value()).map(|n| hir::Value::Int(*int_ty, n)).collect()
+        }
+        hir::Ty::Box(boxed) => {
+            all_values(boxed)?.into_iter().map(|v| hir::Value::Box(Box::new(v))).collect()
+        }
+        hir::Ty::Record(fields) => {
+            let mut records = vec![Map::new()];
+            for (name, ty) in fields {
+                let field_values = all_values(ty)?;
+                if records.len().checked_mul(field_values.len())? > MAX_VALUES {
+                    return None;
+                }
+                records = records
+                    .into_iter()
+                    .flat_map(|record| {
+                        field_values.iter().map(move |v| {
+                            let mut record = record.clone();
+                            record.insert(name.clone(), v.clone());
+                            record
+                        })
+                    })
+                    .collect();
+            }
+            records.into_iter().map(hir::Value::Record).collect()
+        }
+        hir::Ty::Variant(variants) => {
+            let mut values = vec![];
+            for (variant, ty) in variants {
+                values.extend(all_values(ty)?.into_iter().map(|field| hir::Value::Variant {
+                    variant: variant.clone(),
+                    field: Box::new(field),
+                }));
+            }
+            values
+        }
+        // Recursive types have infinitely many values, unless they're
+        // uninhabited.
+        hir::Ty::Recursive(_) | hir::Ty::Named(_) => return None,
+        hir::Ty::Repr(inner, _) => return all_values(inner),
+    };
+    if values.len() > MAX_VALUES {
+        return None;
+    }
+    Some(values)
+}
+
+/// An expression that evaluates to `value`.
+pub fn value_to_expr(value: &hir::Value, ty: &hir::Ty) -> hir::Expr {
+    // Variant expressions need the attributes, to be laid out right.
+    match (value, ty.without_repr()) {
+        (hir::Value::Int(int_ty, n), _) => hir::Expr::Int(*int_ty, *n),
+        // Folding is not visible in values.
+        (_, hir::Ty::Recursive(_)) => hir::Expr::Fold {
+            ty: ty.clone(),
+            value: Box::new(value_to_expr(value, &ty.unfold().unwrap())),
+        },
+        (hir::Value::Box(boxed), hir::Ty::Box(ty)) => {
+            hir::Expr::Box(Box::new(value_to_expr(boxed, ty)))
+        }
+        (hir::Value::Record(fields), hir::Ty::Record(field_tys)) => hir::Expr::Record(
+            fields.iter().map(|(n, v)| (n.clone(), value_to_expr(v, &field_tys[n]))).collect(),
+        ),
+        (hir::Value::Variant { variant, field }, hir::Ty::Variant(variants)) => {
+            hir::Expr::Variant {
+                ty: ty.clone(),
+                variant: variant.clone(),
+                field: Box::new(value_to_expr(field, &variants[variant])),
+            }
+        }
+        _ => panic!("value {} doesn't have type {}", value, ty),
+    }
+}
+
+impl Display for CheckError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            CheckError::TooManyValues => {
+                write!(f, "type has infinitely many values, or more than {}", MAX_VALUES)
+            }
+            CheckError::NotInjective { values: [a, b], encoded } => {
+                write!(f, "{} and {} are both encoded as {}", a, b, encoded)
+            }
+            CheckError::DecodeFailed { value, encoded, error } => {
+                write!(f, "{} is encoded as {}, which fails to decode: {}", value, encoded, error)
+            }
+            CheckError::WrongDecode { value, encoded, decoded } => {
+                write!(f, "{} is encoded as {}, which decodes to {}", value, encoded, decoded)
+            }
+            CheckError::StoreFailed { value, encoded, error } => {
+                write!(f, "{} is encoded as {}, which can't be stored: {}", value, encoded, error)
+            }
+            CheckError::NotInjectiveInMemory { values: [a, b], bytes } => {
+                write!(f, "{} and {} are both stored as {}", a, b, bytes)
+            }
+            CheckError::WrongReadBack { value, bytes, read_back: Ok(read_back) } => {
+                write!(f, "{} is stored as {}, which reads back as {}", value, bytes, read_back)
+            }
+            CheckError::WrongReadBack { value, bytes, read_back: Err(error) } => {
+                write!(f, "{} is stored as {}, which can't be read back: {}", value, bytes, error)
+            }
+            CheckError::VmMismatch { value, encoded, addr, bytes } => write!(
+                f,
+                "{} is encoded as {}, but the VM stores {} at {:#x}",
+                value, encoded, bytes, addr
+            ),
+            CheckError::VmFailed { value, error } => {
+                write!(f, "the VM fails to construct {}: {}", value, error)
+            }
+        }
+    }
+}
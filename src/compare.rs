@@ -0,0 +1,128 @@
+//! Comparing the layouts of a type, and the heap use of a program, under each
+//! layout strategy.
+
+use std::fmt::{Display, Formatter, Result};
+
+use crate::eval::{eval_with_stats, HeapStats};
+use crate::hir;
+use crate::layout::{Layout, TagLayout, TagPath, ValueProj, VariantLayout};
+use crate::layout_of::{layout_of_with, LayoutCx, STRATEGIES};
+use crate::lir::{Shape, Size};
+use crate::lower::{lower_layout, lower_root_expr_in};
+use crate::util::display_table;
+
+pub struct Comparison {
+    pub rows: Vec<LayoutSummary>,
+}
+
+/// The properties of a layout that differ between strategies.
+pub struct LayoutSummary {
+    pub strategy: &'static str,
+    pub shape: Shape,
+    /// The size without padding.
+    pub packed_size: Size,
+    /// The kind and location of the outermost tag, if there is one.
+    pub tag: Option<(&'static str, String)>,
+    /// How many niche values are left for enclosing variant types; zero for
+    /// strategies that never store tags in niches.
+    pub niches: u64,
+}
+
+pub fn compare_layouts(ty: hir::Ty) -> Comparison {
+    let rows = STRATEGIES
+        .iter()
+        .map(|strategy| {
+            let layout = layout_of_with(ty.clone(), *strategy);
+            let shape = layout.shape();
+            let ty = lower_layout(layout.clone());
+            debug_assert_eq!(shape, ty.shape());
+            let tag = outer_tag(&layout).map(|tag| (tag.descr(), tag_location(tag)));
+            let niches =
+                if strategy.exposes_niches() { layout.max_niche_values_count() } else { 0 };
+            let packed_size = ty.packed_size();
+            LayoutSummary { strategy: strategy.name(), shape, packed_size, tag, niches }
+        })
+        .collect();
+    Comparison { rows }
+}
+
+pub struct HeapComparison {
+    pub rows: Vec<HeapSummary>,
+}
+
+pub struct HeapSummary {
+    pub strategy: &'static str,
+    /// The size of the program's value, outside of the heap.
+    pub size: Size,
+    pub stats: HeapStats,
+}
+
+pub fn compare_heap_use(expr: hir::Expr) -> HeapComparison {
+    let rows = STRATEGIES
+        .iter()
+        .map(|strategy| {
+            let mut layouts = LayoutCx::new(*strategy);
+            let ty = lower_layout(layouts.layout_of(&expr.ty()));
+            let (_, stats) = eval_with_stats(lower_root_expr_in(expr.clone(), &mut layouts), &ty);
+            HeapSummary { strategy: strategy.name(), size: ty.shape().size, stats }
+        })
+        .collect();
+    HeapComparison { rows }
+}
+
+fn outer_tag(layout: &Layout) -> Option<&TagLayout> {
+    match layout {
+        Layout::Variant(VariantLayout::Tagged(tagged)) => Some(&tagged.tag),
+        Layout::Recursive(body) => outer_tag(body),
+        _ => None,
+    }
+}
+
+fn tag_location(tag: &TagLayout) -> String {
+    match tag {
+        TagLayout::Direct { .. } => TagPath::singleton(ValueProj::Tag).to_string(),
+        TagLayout::Niche { path, .. } | TagLayout::MultiNiche { path, .. } => path.to_string(),
+        TagLayout::PointerTag { bits, values: _ } => format!("{{root}} (low {} bits)", bits),
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let header =
+            ["strategy", "size", "packed size", "stride", "tag", "tag location", "niches left"];
+        let mut rows = vec![header.map(String::from).to_vec()];
+        rows.extend(self.rows.iter().map(|row| {
+            let (kind, location) = match &row.tag {
+                Some((kind, location)) => (kind.to_string(), location.clone()),
+                None => (String::from("none"), String::from("-")),
+            };
+            vec![
+                row.strategy.to_owned(),
+                row.shape.size.to_string(),
+                row.packed_size.to_string(),
+                row.shape.stride().to_string(),
+                kind,
+                location,
+                row.niches.to_string(),
+            ]
+        }));
+        write!(f, "{}", display_table(&rows))
+    }
+}
+
+impl Display for HeapComparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let header = ["strategy", "size", "boxes", "allocated", "peak live"];
+        let mut rows = vec![header.map(String::from).to_vec()];
+        rows.extend(self.rows.iter().map(|row| {
+            vec![
+                row.strategy.to_owned(),
+                row.size.to_string(),
+                row.stats.allocations.to_string(),
+                Size::from_bytes(row.stats.bytes).to_string(),
+                Size::from_bytes(row.stats.peak_live).to_string(),
+            ]
+        }));
+        write!(f, "{}", display_table(&rows))
+    }
+}
@@ -0,0 +1,285 @@
+//! Decoding LIR machine values back to the HIR values they represent.
+
+use std::fmt::{Display, Formatter};
+
+use crate::hir::{self, TyInterner};
+use crate::layout::{
+    Layout, TagLayout, TagPath, TaggedLayout, ValueProj, VariantLayout, VariantRepr,
+};
+use crate::lir;
+use crate::name::Name;
+use crate::util::Map;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// `value` is not a value of the lowered `layout`.
+    Mismatch { value: lir::Value, layout: String },
+    /// A tag value that doesn't belong to any variant.
+    UnknownTag(u64),
+}
+
+/// Rebuild the HIR value of type `ty` that `value` represents, given the
+/// layout of `ty`.
+pub fn decode_value(
+    value: &lir::Value,
+    ty: &hir::Ty,
+    layout: &Layout,
+) -> Result<hir::Value, DecodeError> {
+    Ctxt { types: TyInterner::default(), recursive: vec![] }.decode(value, ty, layout)
+}
+
+struct Ctxt<'l> {
+    types: TyInterner,
+    /// The bodies of the enclosing [`Layout::Recursive`]s, innermost last.
+    recursive: Vec<&'l Layout>,
+}
+
+impl<'l> Ctxt<'l> {
+    fn decode(
+        &mut self,
+        value: &lir::Value,
+        ty: &hir::Ty,
+        layout: &'l Layout,
+    ) -> Result<hir::Value, DecodeError> {
+        let mismatch =
+            || DecodeError::Mismatch { value: value.clone(), layout: layout.to_string() };
+        match (layout, value, ty.without_repr()) {
+            (Layout::Int(int_ty, _), lir::Value::Int(value_ty, n), _) if int_ty == value_ty => {
+                Ok(hir::Value::Int(*int_ty, *n))
+            }
+            (Layout::Ptr { pointee, niches: _ }, lir::Value::Box(boxed), hir::Ty::Box(ty)) => {
+                Ok(hir::Value::Box(Box::new(self.decode(boxed, ty, pointee)?)))
+            }
+            (
+                Layout::Aggregate { fields: field_lyts, repr: _ },
+                lir::Value::Record(fields),
+                hir::Ty::Record(field_tys),
+            ) => {
+                let fields = field_lyts
+                    .iter()
+                    .map(|(n, l)| {
+                        let value = fields.get(n).ok_or_else(mismatch)?;
+                        let ty = field_tys.get(n).ok_or_else(mismatch)?;
+                        Ok((n.clone(), self.decode(value, ty, l)?))
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(hir::Value::Record(fields))
+            }
+            (Layout::Variant(VariantLayout::Single { field }), _, hir::Ty::Variant(variants)) => {
+                let (variant, field_ty) = self.only_inhabited(variants).ok_or_else(mismatch)?;
+                self.decode_variant(value, variant, field_ty, field)
+            }
+            (Layout::Variant(VariantLayout::Tagged(tagged)), _, hir::Ty::Variant(variants)) => {
+                self.decode_tagged(value, variants, tagged)
+            }
+            (Layout::Recursive(body), _, hir::Ty::Recursive(_)) => {
+                self.recursive.push(body);
+                let result = self.decode(value, &ty.unfold().unwrap(), body);
+                self.recursive.pop();
+                result
+            }
+            // The type has already been unfolded down to here, so it's the
+            // whole recursive type again.
+            (Layout::RecurId { id, unrolled: _ }, _, hir::Ty::Recursive(_)) => {
+                let idx = self
+                    .recursive
+                    .len()
+                    .checked_sub(id.index() as usize + 1)
+                    .ok_or_else(mismatch)?;
+                // The body's own back-references are relative to where it
+                // was defined.
+                let body = self.recursive[idx];
+                let inner = self.recursive.split_off(idx + 1);
+                let result = self.decode(value, &ty.unfold().unwrap(), body);
+                self.recursive.extend(inner);
+                result
+            }
+            _ => Err(mismatch()),
+        }
+    }
+
+    fn decode_tagged(
+        &mut self,
+        value: &lir::Value,
+        variants: &Map<Name, hir::Ty>,
+        tagged: &'l TaggedLayout,
+    ) -> Result<hir::Value, DecodeError> {
+        let TaggedLayout { tag, variants: variant_lyts } = tagged;
+        let mismatch = || DecodeError::Mismatch {
+            value: value.clone(),
+            layout: Layout::Variant(VariantLayout::Tagged(tagged.clone())).to_string(),
+        };
+        let variant_named = |tag_values: &Map<Name, u64>, tag_value| {
+            tag_values.iter().find(|&(_, &v)| v == tag_value).map(|(n, _)| n.clone())
+        };
+        let field_ty = |variant: &Name| variants.get(variant).ok_or_else(mismatch);
+        match tag {
+            TagLayout::Direct { ty: _, values, niches: _, placement: _ } => {
+                let data = select(value, "data").ok_or_else(mismatch)?;
+                let tag_value = int_value(select(value, "tag").ok_or_else(mismatch)?)?;
+                let variant =
+                    variant_named(values, tag_value).ok_or(DecodeError::UnknownTag(tag_value))?;
+                self.decode_variant(data, &variant, field_ty(&variant)?, &variant_lyts[&variant])
+            }
+            TagLayout::Niche { path, ty: _, values } => {
+                let dataful = match path.clone().split_outermost() {
+                    Some((ValueProj::Variant { repr: _, name }, _)) => name,
+                    _ => return Err(mismatch()),
+                };
+                // Pointers are never equal to niche values.
+                let variant = match value_at_path(value, path.clone()) {
+                    Some(lir::Value::Int(_, n)) => variant_named(values, *n),
+                    _ => None,
+                };
+                let variant = variant.unwrap_or(dataful);
+                self.decode_variant(value, &variant, field_ty(&variant)?, &variant_lyts[&variant])
+            }
+            TagLayout::MultiNiche { holder, hoisted, field, path, ty: _, values } => {
+                let data = select(value, "data").ok_or_else(mismatch)?;
+                let tag_value = match value_at_path(value, path.clone()) {
+                    Some(lir::Value::Int(_, n)) => variant_named(values, *n),
+                    _ => None,
+                };
+                match tag_value {
+                    Some(variant) => self.decode_variant(
+                        data,
+                        &variant,
+                        field_ty(&variant)?,
+                        &variant_lyts[&variant],
+                    ),
+                    None => {
+                        let tag = select(value, "tag").ok_or_else(mismatch)?;
+                        let holder_ty = field_ty(holder)?;
+                        let field = match hoisted {
+                            // Put the hoisted field back into the rest of
+                            // the holder's data.
+                            Some(hoisted) => {
+                                let field_tys = match holder_ty.without_repr() {
+                                    hir::Ty::Record(field_tys) => field_tys,
+                                    _ => return Err(mismatch()),
+                                };
+                                let hoisted_ty = field_tys.get(hoisted).ok_or_else(mismatch)?;
+                                let hoisted_value = self.decode(tag, hoisted_ty, field)?;
+                                match self.decode(data, holder_ty, &variant_lyts[holder])? {
+                                    hir::Value::Record(mut fields) => {
+                                        fields.insert(hoisted.clone(), hoisted_value);
+                                        hir::Value::Record(fields)
+                                    }
+                                    _ => return Err(mismatch()),
+                                }
+                            }
+                            None => self.decode(tag, holder_ty, field)?,
+                        };
+                        Ok(hir::Value::Variant { variant: holder.clone(), field: Box::new(field) })
+                    }
+                }
+            }
+            TagLayout::PointerTag { bits, values } => {
+                let (ptr, tag_value) = match value {
+                    lir::Value::Box(_) => (value.clone(), 0),
+                    lir::Value::TaggedBox(boxed, tag) => (lir::Value::Box(boxed.clone()), *tag),
+                    // A null pointer standing in for a zero-sized variant.
+                    lir::Value::Int(_, n) => (value.clone(), n & ((1 << bits) - 1)),
+                    lir::Value::Record(_) => return Err(mismatch()),
+                };
+                let variant =
+                    variant_named(values, tag_value).ok_or(DecodeError::UnknownTag(tag_value))?;
+                self.decode_variant(&ptr, &variant, field_ty(&variant)?, &variant_lyts[&variant])
+            }
+        }
+    }
+
+    /// Zero-sized variants' data isn't stored, so it's rebuilt from the layout.
+    fn decode_variant(
+        &mut self,
+        value: &lir::Value,
+        variant: &Name,
+        field_ty: &hir::Ty,
+        field_layout: &'l Layout,
+    ) -> Result<hir::Value, DecodeError> {
+        let field = if field_layout.is_zst() {
+            self.zst_value(field_ty, field_layout)
+        } else {
+            self.decode(value, field_ty, field_layout)?
+        };
+        Ok(hir::Value::Variant { variant: variant.clone(), field: Box::new(field) })
+    }
+
+    /// The only value of a zero-sized type.
+    fn zst_value(&mut self, ty: &hir::Ty, layout: &Layout) -> hir::Value {
+        match (layout, ty.without_repr()) {
+            (Layout::Aggregate { fields, repr: _ }, hir::Ty::Record(field_tys)) => {
+                hir::Value::Record(
+                    fields
+                        .iter()
+                        .map(|(n, l)| (n.clone(), self.zst_value(&field_tys[n], l)))
+                        .collect(),
+                )
+            }
+            (Layout::Variant(VariantLayout::Single { field }), hir::Ty::Variant(variants)) => {
+                let (variant, field_ty) = self.only_inhabited(variants).unwrap();
+                let field = self.zst_value(field_ty, field);
+                hir::Value::Variant { variant: variant.clone(), field: Box::new(field) }
+            }
+            (Layout::Recursive(body), hir::Ty::Recursive(_)) => {
+                self.zst_value(&ty.unfold().unwrap(), body)
+            }
+            _ => panic!("layout is not zero-sized: {}", layout),
+        }
+    }
+
+    /// The variant of a [`VariantLayout::Single`].
+    fn only_inhabited<'a>(
+        &mut self,
+        variants: &'a Map<Name, hir::Ty>,
+    ) -> Option<(&'a Name, &'a hir::Ty)> {
+        let mut inhabited = variants.iter().filter(|(_, t)| {
+            let id = self.types.intern(t);
+            !self.types.is_uninhabited(id)
+        });
+        inhabited.next()
+    }
+}
+
+fn select<'a>(value: &'a lir::Value, field: &str) -> Option<&'a lir::Value> {
+    match value {
+        lir::Value::Record(fields) => fields.get(&Name::from(field)),
+        _ => None,
+    }
+}
+
+fn int_value(value: &lir::Value) -> Result<u64, DecodeError> {
+    match value {
+        lir::Value::Int(_, n) => Ok(*n),
+        _ => Err(DecodeError::Mismatch { value: value.clone(), layout: "an integer".to_owned() }),
+    }
+}
+
+/// Like `lower::select_value_at_path`, but on values.
+fn value_at_path(value: &lir::Value, path: TagPath) -> Option<&lir::Value> {
+    match path.split_outermost() {
+        None => Some(value),
+        Some((ValueProj::Field(field), rest)) => match value {
+            lir::Value::Record(fields) => value_at_path(fields.get(&field)?, rest),
+            _ => None,
+        },
+        Some((ValueProj::Variant { repr: VariantRepr::Transparent, name: _ }, rest)) => {
+            value_at_path(value, rest)
+        }
+        Some((ValueProj::Variant { repr: VariantRepr::Wrapper, name: _ }, rest)) => {
+            value_at_path(select(value, "data")?, rest)
+        }
+        Some((ValueProj::Tag, rest)) => value_at_path(select(value, "tag")?, rest),
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Mismatch { value, layout } => {
+                write!(f, "value {} doesn't match layout {}", value, layout)
+            }
+            DecodeError::UnknownTag(tag) => write!(f, "tag value {} has no variant", tag),
+        }
+    }
+}
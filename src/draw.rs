@@ -0,0 +1,477 @@
+//! Drawing the bytes of a layout as a grid, in ASCII, SVG, or Graphviz DOT.
+//!
+//! There is one row per variant of the outermost variant type, and the rows
+//! overlay the same bytes. Variant types nested inside of a row are drawn as
+//! their tag and the union of their variants' data, unless their tag is
+//! stored in a niche; then only their dataful variant is drawn.
+
+use std::fmt::Write;
+
+use crate::int_ty::IntTy;
+use crate::layout::{Layout, TagLayout, TagPath, TaggedLayout, ValueProj, VariantLayout};
+use crate::lir::{Shape, Size};
+use crate::name::Name;
+use crate::util::{display_map_like, display_table, Map};
+
+pub struct Diagram {
+    pub shape: Shape,
+    pub rows: Vec<Row>,
+}
+
+/// The bytes of one variant, or of the whole type if it isn't a variant type.
+pub struct Row {
+    pub variant: Option<Name>,
+    /// The cells are in order of their offsets and don't overlap. Bytes
+    /// outside of every cell are padding, or unused by this variant.
+    pub cells: Vec<Cell>,
+}
+
+pub struct Cell {
+    pub offset: Size,
+    pub size: Size,
+    /// Where the cell's value is, like in tag paths.
+    pub path: TagPath,
+    pub kind: CellKind,
+    /// Like `U8 (niches: 3..=255)` or `U8 tag = 1`.
+    pub descr: String,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CellKind {
+    /// An integer or a pointer, or the union of several variants' data.
+    Data,
+    /// A direct tag, a pointer with a tag in its low bits, or a niche value
+    /// that identifies a variant.
+    Tag,
+    /// Data whose niche values identify the other variants.
+    Niche,
+}
+
+impl CellKind {
+    pub fn descr(self) -> &'static str {
+        match self {
+            CellKind::Data => "data",
+            CellKind::Tag => "tag",
+            CellKind::Niche => "niche",
+        }
+    }
+
+    fn ascii_fill(self) -> char {
+        match self {
+            CellKind::Data => '-',
+            CellKind::Tag => '=',
+            CellKind::Niche => '~',
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            CellKind::Data => "#9ecae1",
+            CellKind::Tag => "#fdae6b",
+            CellKind::Niche => "#a1d99b",
+        }
+    }
+}
+
+pub fn draw_layout(layout: &Layout) -> Diagram {
+    let shape = layout.shape();
+    let root = TagPath::empty();
+    let mut rows = match outer_tagged(layout) {
+        Some(tagged) => tagged
+            .variants
+            .keys()
+            .map(|name| Row { variant: Some(name.clone()), cells: variant_cells(tagged, name) })
+            .collect(),
+        None => {
+            let mut cells = vec![];
+            push_cells(layout, Size::ZERO, &root, &mut cells);
+            vec![Row { variant: None, cells }]
+        }
+    };
+    for row in &mut rows {
+        row.cells.retain(|c| c.size != Size::ZERO);
+        row.cells.sort_by_key(|c| c.offset);
+    }
+    Diagram { shape, rows }
+}
+
+/// The outermost variant type with a tag, unless it is behind a pointer or
+/// in a record.
+fn outer_tagged(layout: &Layout) -> Option<&TaggedLayout> {
+    match layout {
+        Layout::Variant(VariantLayout::Tagged(tagged)) => Some(tagged),
+        Layout::Variant(VariantLayout::Single { field }) => outer_tagged(field),
+        Layout::Recursive(body) => outer_tagged(body),
+        _ => None,
+    }
+}
+
+fn child(path: &TagPath, proj: ValueProj) -> TagPath {
+    TagPath::singleton(proj).with_outer_path(path.clone())
+}
+
+fn variant_path(path: &TagPath, tag: &TagLayout, name: &Name) -> TagPath {
+    child(path, ValueProj::Variant { repr: tag.as_variant_repr(), name: name.clone() })
+}
+
+fn add(offset: Size, other: Size) -> Size {
+    offset.checked_add(other).unwrap()
+}
+
+/// The cells of variant `name` of the outermost variant type, `tagged`.
+fn variant_cells(tagged: &TaggedLayout, name: &Name) -> Vec<Cell> {
+    let root = TagPath::empty();
+    let TaggedLayout { tag, variants } = tagged;
+    let mut cells = vec![];
+    let data_offset = tagged.data_offset();
+    push_cells(&variants[name], data_offset, &variant_path(&root, tag, name), &mut cells);
+    match tag {
+        TagLayout::Direct { ty, values, niches: _, placement: _ } => cells.push(Cell {
+            offset: tagged.tag_offset().unwrap(),
+            size: ty.shape().size,
+            path: child(&root, ValueProj::Tag),
+            kind: CellKind::Tag,
+            descr: format!("{} tag = {}", ty, values[name]),
+        }),
+        TagLayout::Niche { path, ty, values } => match values.get(name) {
+            Some(value) => {
+                let holder = niche_holder(tagged, &root);
+                cells.push(niche_value_cell(holder, ty, *value));
+            }
+            None => mark_niche(&mut cells, &path.clone().with_outer_path(root), values),
+        },
+        TagLayout::MultiNiche { holder, field, path, ty, values, .. } => {
+            let mut tag_cells = vec![];
+            let tag_offset = tagged.tag_offset().unwrap();
+            push_cells(field, tag_offset, &child(&root, ValueProj::Tag), &mut tag_cells);
+            let path = path.clone().with_outer_path(root);
+            if name == holder {
+                mark_niche(&mut tag_cells, &path, values);
+                cells.extend(tag_cells);
+            } else {
+                let niche = tag_cells.into_iter().find(|c| c.path == path).unwrap();
+                cells.push(niche_value_cell(niche, ty, values[name]));
+            }
+        }
+        TagLayout::PointerTag { bits, values } => {
+            let descr = match cells.pop() {
+                Some(_) => format!("Ptr, low {} bits = {}", bits, values[name]),
+                None => format!("null Ptr, low {} bits = {}", bits, values[name]),
+            };
+            let path = variant_path(&root, tag, name);
+            let size = Size::BITS_64;
+            cells.push(Cell { offset: Size::ZERO, size, path, kind: CellKind::Tag, descr });
+        }
+    }
+    cells
+}
+
+/// The cell whose niche holds the tag of `tagged`, which has a niche tag.
+fn niche_holder(tagged: &TaggedLayout, path: &TagPath) -> Cell {
+    let (tag_path, values) = match &tagged.tag {
+        TagLayout::Niche { path, ty: _, values } => (path, values),
+        _ => unreachable!(),
+    };
+    let (name, dataful) = tagged.variants.iter().find(|(n, _)| !values.contains_key(n)).unwrap();
+    let mut cells = vec![];
+    push_cells(dataful, Size::ZERO, &variant_path(path, &tagged.tag, name), &mut cells);
+    let tag_path = tag_path.clone().with_outer_path(path.clone());
+    cells.into_iter().find(|c| c.path == tag_path).expect("no scalar at niche path")
+}
+
+fn niche_value_cell(holder: Cell, ty: &IntTy, value: u64) -> Cell {
+    Cell { kind: CellKind::Tag, descr: format!("{} = {}", ty, value), ..holder }
+}
+
+/// Mark the cell at `path` as holding the niche `values` of other variants.
+fn mark_niche(cells: &mut [Cell], path: &TagPath, values: &Map<Name, u64>) {
+    let cell = cells.iter_mut().find(|c| c.path == *path).expect("no scalar at niche path");
+    cell.kind = CellKind::Niche;
+    write!(cell.descr, "; niche for {}", display_map_like(values.iter(), " = ", ", ")).unwrap();
+}
+
+/// Push the cells of `layout`, at `offset`, with nested variant types
+/// collapsed.
+fn push_cells(layout: &Layout, offset: Size, path: &TagPath, cells: &mut Vec<Cell>) {
+    let scalar =
+        |size, descr| Cell { offset, size, path: path.clone(), kind: CellKind::Data, descr };
+    match layout {
+        Layout::Int(ty, niches) => {
+            cells.push(scalar(ty.shape().size, format!("{} (niches: {})", ty, niches)))
+        }
+        Layout::Ptr { pointee: _, niches } => {
+            cells.push(scalar(Size::BITS_64, format!("Ptr (niches: {})", niches)))
+        }
+        Layout::Aggregate { fields, repr: _ } => {
+            let offsets = layout.field_offsets().unwrap();
+            for (name, field) in fields {
+                let path = child(path, ValueProj::Field(name.clone()));
+                push_cells(field, add(offset, offsets[name]), &path, cells);
+            }
+        }
+        Layout::Variant(VariantLayout::Single { field }) => push_cells(field, offset, path, cells),
+        Layout::Variant(VariantLayout::Tagged(tagged)) => {
+            push_tagged_cells(tagged, offset, path, cells)
+        }
+        Layout::Recursive(body) => push_cells(body, offset, path, cells),
+        Layout::RecurId { id: _, unrolled } => {
+            let unrolled = unrolled.as_ref().expect("layout has an unbound `RecurId`");
+            push_cells(unrolled, offset, path, cells)
+        }
+    }
+}
+
+fn push_tagged_cells(tagged: &TaggedLayout, offset: Size, path: &TagPath, cells: &mut Vec<Cell>) {
+    let TaggedLayout { tag, variants } = tagged;
+    let union = |descr| {
+        let data = Shape::union(variants.values().map(Layout::shape));
+        let offset = add(offset, tagged.data_offset());
+        Cell { offset, size: data.size, path: path.clone(), kind: CellKind::Data, descr }
+    };
+    let names = || variants.keys().map(Name::to_string).collect::<Vec<_>>().join(" | ");
+    match tag {
+        TagLayout::Direct { ty, values, niches, placement: _ } => {
+            cells.push(union(format!("data of {}", names())));
+            cells.push(Cell {
+                offset: add(offset, tagged.tag_offset().unwrap()),
+                size: ty.shape().size,
+                path: child(path, ValueProj::Tag),
+                kind: CellKind::Tag,
+                descr: format!(
+                    "{} tag: {} (niches: {})",
+                    ty,
+                    display_map_like(values.iter(), " = ", ", "),
+                    niches
+                ),
+            });
+        }
+        TagLayout::Niche { path: tag_path, ty: _, values } => {
+            let (name, dataful) = variants.iter().find(|(n, _)| !values.contains_key(n)).unwrap();
+            let start = cells.len();
+            push_cells(dataful, offset, &variant_path(path, tag, name), cells);
+            let tag_path = tag_path.clone().with_outer_path(path.clone());
+            mark_niche(&mut cells[start..], &tag_path, values);
+        }
+        TagLayout::MultiNiche { field, path: tag_path, values, .. } => {
+            cells.push(union(format!("data of {}", names())));
+            let start = cells.len();
+            let tag_offset = add(offset, tagged.tag_offset().unwrap());
+            push_cells(field, tag_offset, &child(path, ValueProj::Tag), cells);
+            let tag_path = tag_path.clone().with_outer_path(path.clone());
+            mark_niche(&mut cells[start..], &tag_path, values);
+        }
+        TagLayout::PointerTag { bits, values } => cells.push(Cell {
+            offset,
+            size: Size::BITS_64,
+            path: path.clone(),
+            kind: CellKind::Tag,
+            descr: format!(
+                "Ptr, low {} bits: {}",
+                bits,
+                display_map_like(values.iter(), " = ", ", ")
+            ),
+        }),
+    }
+}
+
+impl Diagram {
+    /// The distinct cells, each with a one-character key.
+    fn legend(&self) -> Vec<(char, &Cell)> {
+        let keys = ('a'..='z').chain('A'..='Z').chain('0'..='9');
+        let mut legend: Vec<(char, &Cell)> = vec![];
+        for cell in self.rows.iter().flat_map(|r| &r.cells) {
+            if !legend.iter().any(|(_, c)| c.path == cell.path && c.descr == cell.descr) {
+                legend.push((keys.clone().nth(legend.len()).unwrap_or('*'), cell));
+            }
+        }
+        legend
+    }
+
+    fn key(&self, legend: &[(char, &Cell)], cell: &Cell) -> char {
+        legend.iter().find(|(_, c)| c.path == cell.path && c.descr == cell.descr).unwrap().0
+    }
+
+    fn row_label(row: &Row) -> String {
+        row.variant.as_ref().map_or_else(String::new, Name::to_string)
+    }
+
+    /// Draw the grid with three characters per byte, followed by a legend.
+    pub fn to_ascii(&self) -> String {
+        let legend = self.legend();
+        let size = self.shape.size.bytes();
+        let label_width = self.rows.iter().map(|r| Self::row_label(r).chars().count()).max();
+        let label_width = label_width.unwrap_or(0);
+
+        let mut out = format!("{}\n", self.shape);
+        if size == 0 {
+            out.push_str("(no bytes)");
+            return out;
+        }
+        let mut header = " ".repeat(label_width);
+        for byte in (0..size).step_by(4) {
+            write!(header, " {:<11}", byte).unwrap();
+        }
+        writeln!(out, "{}", header.trim_end()).unwrap();
+        for row in &self.rows {
+            let mut line = format!("{:<1$} ", Self::row_label(row), label_width);
+            let mut byte = 0;
+            for cell in &row.cells {
+                let (start, len) = (cell.offset.bytes(), cell.size.bytes());
+                line.push_str(&" . ".repeat((start - byte) as usize));
+                let fill = cell.kind.ascii_fill().to_string().repeat(3 * len as usize - 3);
+                write!(line, "[{}{}]", self.key(&legend, cell), fill).unwrap();
+                byte = start + len;
+            }
+            line.push_str(&" . ".repeat((size - byte) as usize));
+            writeln!(out, "{}", line.trim_end()).unwrap();
+        }
+        let rows: Vec<_> = legend
+            .iter()
+            .map(|(key, c)| {
+                vec![
+                    key.to_string(),
+                    c.kind.descr().to_owned(),
+                    c.path.to_string(),
+                    c.descr.clone(),
+                ]
+            })
+            .collect();
+        out.push_str(&display_table(&rows));
+        out
+    }
+
+    pub fn to_svg(&self) -> String {
+        const BYTE: u64 = 32;
+        const ROW: u64 = 32;
+        const LEGEND_ROW: u64 = 20;
+        let legend = self.legend();
+        let size = self.shape.size.bytes();
+        let label_width = self.rows.iter().map(|r| Self::row_label(r).chars().count()).max();
+        let left = 8 * label_width.unwrap_or(0) as u64 + 16;
+        let top = 24;
+        let legend_top = top + ROW * self.rows.len() as u64 + 16;
+        let width = (left + BYTE * size + 8).max(480);
+        let height = legend_top + LEGEND_ROW * legend.len() as u64 + 8;
+
+        let mut out = String::new();
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}" font-family="monospace" font-size="12">"#,
+            width, height
+        )
+        .unwrap();
+        for byte in 0..size {
+            let x = left + BYTE * byte + BYTE / 2;
+            writeln!(out, r#"  <text x="{}" y="16" text-anchor="middle">{}</text>"#, x, byte)
+                .unwrap();
+        }
+        for (i, row) in self.rows.iter().enumerate() {
+            let y = top + ROW * i as u64;
+            let label = escape(&Self::row_label(row));
+            writeln!(out, r#"  <text x="8" y="{}">{}</text>"#, y + ROW / 2 + 4, label).unwrap();
+            writeln!(
+                out,
+                r##"  <rect x="{}" y="{}" width="{}" height="{}" fill="#eeeeee" stroke="#999999"/>"##,
+                left,
+                y,
+                BYTE * size,
+                ROW
+            )
+            .unwrap();
+            for cell in &row.cells {
+                let x = left + BYTE * cell.offset.bytes();
+                let w = BYTE * cell.size.bytes();
+                writeln!(
+                    out,
+                    r##"  <rect x="{}" y="{}" width="{}" height="{}" fill="{}" stroke="#333333"><title>{}: {}</title></rect>"##,
+                    x,
+                    y,
+                    w,
+                    ROW,
+                    cell.kind.color(),
+                    escape(&cell.path.to_string()),
+                    escape(&cell.descr)
+                )
+                .unwrap();
+                let key = self.key(&legend, cell);
+                let (tx, ty) = (x + w / 2, y + ROW / 2 + 4);
+                writeln!(
+                    out,
+                    r#"  <text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+                    tx, ty, key
+                )
+                .unwrap();
+            }
+        }
+        for (i, (key, cell)) in legend.iter().enumerate() {
+            let y = legend_top + LEGEND_ROW * i as u64 + 14;
+            writeln!(
+                out,
+                r#"  <text x="8" y="{}">{} {}: {} {}</text>"#,
+                y,
+                key,
+                cell.kind.descr(),
+                escape(&cell.path.to_string()),
+                escape(&cell.descr)
+            )
+            .unwrap();
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+
+    /// Draw the grid as an HTML-like table in a Graphviz graph.
+    pub fn to_dot(&self) -> String {
+        let legend = self.legend();
+        let size = self.shape.size.bytes();
+        let mut out = String::from("digraph layout {\n  node [shape=plaintext];\n");
+        out.push_str("  layout [label=<\n");
+        out.push_str(r#"    <table border="0" cellborder="1" cellspacing="0">"#);
+        out.push('\n');
+        let bytes: String = (0..size).map(|b| format!("<td>{}</td>", b)).collect();
+        writeln!(out, "      <tr><td></td>{}</tr>", bytes).unwrap();
+        for row in &self.rows {
+            let mut tds = String::new();
+            let mut byte = 0;
+            for cell in &row.cells {
+                let (start, len) = (cell.offset.bytes(), cell.size.bytes());
+                if start > byte {
+                    write!(tds, r#"<td colspan="{}"></td>"#, start - byte).unwrap();
+                }
+                write!(
+                    tds,
+                    r#"<td colspan="{}" bgcolor="{}" tooltip="{}">{}</td>"#,
+                    len,
+                    cell.kind.color(),
+                    escape(&cell.descr),
+                    self.key(&legend, cell)
+                )
+                .unwrap();
+                byte = start + len;
+            }
+            if size > byte {
+                write!(tds, r#"<td colspan="{}"></td>"#, size - byte).unwrap();
+            }
+            let label = escape(&Self::row_label(row));
+            writeln!(out, "      <tr><td>{}</td>{}</tr>", label, tds).unwrap();
+        }
+        for (key, cell) in &legend {
+            writeln!(
+                out,
+                r#"      <tr><td>{}</td><td colspan="{}" align="left">{}: {} {}</td></tr>"#,
+                key,
+                size.max(1),
+                cell.kind.descr(),
+                escape(&cell.path.to_string()),
+                escape(&cell.descr)
+            )
+            .unwrap();
+        }
+        out.push_str("    </table>\n  >];\n}\n");
+        out
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -1,45 +1,198 @@
-use crate::lir::{Expr, Value};
+//! The LIR evaluator.
+//!
+//! Boxes are shared between the values that point to them, and freed when
+//! the last of those is dropped, so the evaluator can count how much heap a
+//! program uses. Like in the [`vm`](crate::vm), a box's size comes from the
+//! type of the place it's stored in, when that's known: the values of
+//! niche-encoded variants only have the fields that hold the tag.
+
+use std::cell::Cell;
+use std::fmt::{Display, Formatter, Result};
+use std::rc::Rc;
+
+use crate::int_ty::IntTy;
+use crate::lir::{Expr, Size, Ty, Value};
 use crate::name::Name;
 use crate::util::Map;
 
+/// The heap use of one evaluation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// The number of `Box` expressions that were evaluated.
+    pub allocations: u64,
+    /// The total size of those boxes.
+    pub bytes: u64,
+    /// The most bytes that were allocated and not yet freed at once.
+    pub peak_live: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Heap {
+    stats: HeapStats,
+    live: u64,
+}
+
+/// A value during evaluation.
+#[derive(Debug, Clone)]
+enum Val {
+    Int(IntTy, u64),
+    Record(Map<Name, Val>),
+    Box(Rc<Alloc>),
+    TaggedBox(Rc<Alloc>, u64),
+}
+
+#[derive(Debug)]
+struct Alloc {
+    value: Val,
+    size: u64,
+    heap: Rc<Cell<Heap>>,
+}
+
+impl Drop for Alloc {
+    fn drop(&mut self) {
+        let mut heap = self.heap.get();
+        heap.live -= self.size;
+        self.heap.set(heap);
+    }
+}
+
+impl Val {
+    fn to_value(&self) -> Value {
+        match self {
+            Val::Int(ty, n) => Value::Int(*ty, *n),
+            Val::Record(fields) => {
+                Value::Record(fields.iter().map(|(n, v)| (n.clone(), v.to_value())).collect())
+            }
+            Val::Box(alloc) => Value::Box(Box::new(alloc.value.to_value())),
+            Val::TaggedBox(alloc, tag) => Value::TaggedBox(Box::new(alloc.value.to_value()), *tag),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Ctxt {
-    vars: Map<Name, Value>,
+    vars: Map<Name, Val>,
+    heap: Rc<Cell<Heap>>,
 }
 
 impl Ctxt {
     fn empty() -> Self {
-        Self { vars: Map::new() }
+        Self { vars: Map::new(), heap: Rc::default() }
+    }
+
+    fn alloc(&self, value: Val, size: u64) -> Rc<Alloc> {
+        let mut heap = self.heap.get();
+        heap.stats.allocations += 1;
+        heap.stats.bytes += size;
+        heap.live += size;
+        heap.stats.peak_live = heap.stats.peak_live.max(heap.live);
+        self.heap.set(heap);
+        Rc::new(Alloc { value, size, heap: self.heap.clone() })
     }
 }
 
 pub fn eval_root_expr(expr: Expr) -> Value {
-    eval_expr(Ctxt::empty(), expr)
+    eval_root_expr_in(expr, None).0
 }
 
-fn eval_expr(mut cx: Ctxt, expr: Expr) -> Value {
+/// Evaluate `expr`, whose value is stored in a place of type `ty`, and count
+/// the boxes that it allocates.
+pub fn eval_with_stats(expr: Expr, ty: &Ty) -> (Value, HeapStats) {
+    eval_root_expr_in(expr, Some(ty))
+}
+
+fn eval_root_expr_in(expr: Expr, ty: Option<&Ty>) -> (Value, HeapStats) {
+    let cx = Ctxt::empty();
+    let heap = cx.heap.clone();
+    let value = eval_expr(cx, expr, ty).to_value();
+    (value, heap.get().stats)
+}
+
+/// The type of the field `field` of a value of type `ty`, if it has one.
+/// Union values are transparent, so that's the field of a member.
+fn field_ty(ty: &Ty, field: &Name) -> Option<Ty> {
+    match ty.unfolded() {
+        Ty::Record(fields, _) => fields.into_iter().find(|(n, _)| n == field).map(|(_, t)| t),
+        Ty::UntaggedUnion(members) => members.values().find_map(|t| field_ty(t, field)),
+        _ => None,
+    }
+}
+
+/// `ty` is the type of the place that the value is stored in, if it's known.
+fn eval_expr(mut cx: Ctxt, expr: Expr, ty: Option<&Ty>) -> Val {
     match expr {
         Expr::Var(var) => cx.vars[&var.name].clone(),
 
-        Expr::U64(n) => Value::U64(n),
-        Expr::Record(fields) => {
-            Value::Record(fields.into_iter().map(|(n, e)| (n, eval_expr(cx.clone(), e))).collect())
+        Expr::Int(ty, n) => Val::Int(ty, n),
+        Expr::Record(fields) => Val::Record(
+            fields
+                .into_iter()
+                .map(|(n, e)| {
+                    let field_ty = ty.and_then(|ty| field_ty(ty, &n));
+                    let value = eval_expr(cx.clone(), e, field_ty.as_ref());
+                    (n, value)
+                })
+                .collect(),
+        ),
+        Expr::UntaggedUnion { ty, field, value } => {
+            let member_ty = match &ty {
+                Ty::UntaggedUnion(members) => members.get(&field),
+                _ => None,
+            };
+            eval_expr(cx, *value, member_ty)
         }
-        Expr::UntaggedUnion { ty: _, field: _, value } => eval_expr(cx, *value),
 
-        Expr::Box(val) => Value::Box(Box::new(eval_expr(cx, *val))),
+        Expr::Box(val) => {
+            let pointee = match ty.map(Ty::unfolded) {
+                Some(Ty::Ptr(pointee)) => *pointee,
+                _ => val.ty(),
+            };
+            let size = pointee.shape().size.bytes();
+            let val = eval_expr(cx.clone(), *val, Some(&pointee));
+            Val::Box(cx.alloc(val, size))
+        }
         Expr::Deref(ptr) => {
-            let ptr = eval_expr(cx, *ptr);
+            let ptr = eval_expr(cx, *ptr, None);
             match ptr {
-                Value::Box(val) => *val,
+                Val::Box(alloc) => alloc.value.clone(),
+                Val::TaggedBox(..) => panic!("dereferenced a tagged pointer"),
+                _ => panic!(),
+            }
+        }
+        Expr::TagPtr { ptr, bits, tag } => {
+            let mask = ptr_tag_mask(bits);
+            assert!(tag <= mask, "tag {} does not fit in {} bits", tag, bits);
+            match eval_expr(cx, *ptr, ty) {
+                Val::Box(alloc) if tag == 0 => Val::Box(alloc),
+                Val::Box(alloc) => Val::TaggedBox(alloc, tag),
+                // A null pointer standing in for a zero-sized variant.
+                Val::Int(IntTy::U64, n) => {
+                    assert_eq!(n & mask, 0, "pointer is already tagged");
+                    Val::Int(IntTy::U64, n | tag)
+                }
+                Val::TaggedBox(..) => panic!("pointer is already tagged"),
                 _ => panic!(),
             }
         }
+        Expr::PtrTag { ptr, bits } => {
+            let tag = match eval_expr(cx, *ptr, None) {
+                Val::Box(_) => 0,
+                Val::TaggedBox(_, tag) => tag,
+                Val::Int(IntTy::U64, n) => n & ptr_tag_mask(bits),
+                _ => panic!(),
+            };
+            Val::Int(IntTy::U64, tag)
+        }
+        Expr::UntagPtr { ptr, bits } => match eval_expr(cx, *ptr, ty) {
+            Val::Box(alloc) | Val::TaggedBox(alloc, _) => Val::Box(alloc),
+            Val::Int(IntTy::U64, n) => Val::Int(IntTy::U64, n & !ptr_tag_mask(bits)),
+            _ => panic!(),
+        },
 
         Expr::Select { record, field } => {
-            let record = eval_expr(cx, *record);
+            let record = eval_expr(cx, *record, None);
             match record {
-                Value::Record(fields) => fields[&field].clone(),
+                Val::Record(fields) => fields[&field].clone(),
                 _ => panic!(),
             }
         }
@@ -47,20 +200,39 @@ fn eval_expr(mut cx: Ctxt, expr: Expr) -> Value {
         Expr::Switch { subj, cases, default } => {
             let subj = cx.vars[&subj.name].clone();
             match subj {
-                Value::U64(subj_val) => {
+                Val::Int(_, subj_val) => {
                     let case_body = cases
                         .get(&subj_val)
                         .or(default.as_deref())
                         .expect("no matching case found");
-                    eval_expr(cx, case_body.clone())
+                    eval_expr(cx, case_body.clone(), ty)
+                }
+                // Pointers to values are never equal to niche values.
+                Val::Box(_) | Val::TaggedBox(..) => {
+                    let default = default.expect("no matching case found");
+                    eval_expr(cx, *default, ty)
                 }
                 _ => panic!(),
             }
         }
         Expr::Let { binder, value, body } => {
-            let value = eval_expr(cx.clone(), *value);
+            let value = eval_expr(cx.clone(), *value, Some(&binder.ty));
             cx.vars.insert(binder.name, value);
-            eval_expr(cx, *body)
+            eval_expr(cx, *body, ty)
         }
     }
 }
+
+fn ptr_tag_mask(bits: u32) -> u64 {
+    (1 << bits) - 1
+}
+
+impl Display for HeapStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let HeapStats { allocations, bytes, peak_live } = self;
+        let boxes = if *allocations == 1 { "box" } else { "boxes" };
+        let (bytes, peak_live) = (Size::from_bytes(*bytes), Size::from_bytes(*peak_live));
+        writeln!(f, "allocated: {} {}, {}", allocations, boxes, bytes)?;
+        write!(f, "peak live: {}", peak_live)
+    }
+}
@@ -1,19 +1,25 @@
 //! High-level IR.
 
 mod display;
+mod inhabited;
+mod intern;
 mod ty_subst;
 mod typeck;
 
+pub use intern::{TyId, TyInterner, TyKind};
 pub(crate) use typeck::validate_ty;
 
+use std::borrow::Borrow;
+
 use crate::debruijn::Debruijn;
+use crate::int_ty::IntTy;
 use crate::name::Name;
 use crate::util::Map;
 
 #[derive(Debug, Clone)]
 pub enum Expr {
     Var(Var),
-    U64(u64),
+    Int(IntTy, u64),
 
     Box(Box<Expr>),
 
@@ -27,6 +33,15 @@ pub enum Expr {
     Match { subj: Box<Expr>, cases: Vec<(Pat, Expr)> },
 }
 
+/// A value of a HIR type. Folding is not visible in values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Int(IntTy, u64),
+    Box(Box<Value>),
+    Record(Map<Name, Value>),
+    Variant { variant: Name, field: Box<Value> },
+}
+
 #[derive(Debug, Clone)]
 pub enum Pat {
     Variant { ty: Ty, variant: Name, field: Var },
@@ -40,7 +55,7 @@ pub struct Var {
 
 #[derive(Debug, Clone)]
 pub enum Ty {
-    U64,
+    Int(IntTy),
     Box(Box<Ty>),
 
     Record(Map<Name, Ty>),
@@ -48,6 +63,31 @@ pub enum Ty {
 
     Recursive(Box<Ty>),
     Named(Debruijn),
+
+    /// A record or variant type with representation attributes, like
+    /// `#[repr(C)] { a : U8 }` or `< A = 5 of {} | B of U64 >`.
+    Repr(Box<Ty>, Repr),
+}
+
+/// Representation attributes that pin down parts of a type's layout.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Repr {
+    /// `#[repr(C)]`: keep fields in declaration order, and store the tag
+    /// directly, before the data, in a `U32` like a C enum unless `tag` says
+    /// otherwise.
+    pub c: bool,
+    /// `#[repr(u8)]` etc.: store the tag directly, in this type.
+    pub tag: Option<IntTy>,
+    /// `#[no_niche]`: don't let enclosing types store tags in this type's
+    /// niches.
+    pub no_niche: bool,
+    /// `#[packed]`: lay out fields without padding, with an alignment of one.
+    pub packed: bool,
+    /// Explicit tag values of variants, like `A = 5`.
+    pub discriminants: Map<Name, u64>,
+    /// The declaration order of a `#[repr(C)]` record's fields, or empty if
+    /// they were declared in order of their names.
+    pub field_order: Vec<Name>,
 }
 
 impl Ty {
@@ -57,4 +97,58 @@ impl Ty {
             _ => None,
         }
     }
+
+    /// The type without its representation attributes.
+    pub fn without_repr(&self) -> &Ty {
+        match self {
+            Ty::Repr(inner, _) => inner,
+            _ => self,
+        }
+    }
+
+    /// Unfold a recursive type by one level, replacing the back-references in
+    /// its body with the type itself.
+    pub fn unfold(&self) -> Option<Ty> {
+        let body = self.as_recursive()?;
+        Some(ty_subst::subst_ty((Debruijn::ZERO, self.clone()), body.clone()))
+    }
+}
+
+impl Repr {
+    /// Whether the tag has to be stored directly, rather than in a niche.
+    pub fn has_direct_tag(&self) -> bool {
+        self.c || self.tag.is_some() || !self.discriminants.is_empty()
+    }
+
+    /// The tag values of `variants`, which are in order of their names.
+    /// Variants without an explicit discriminant get the smallest values that
+    /// no other variant uses.
+    pub fn tag_values<'a>(&self, variants: impl Iterator<Item = &'a Name>) -> Map<Name, u64> {
+        let mut next = 0;
+        variants
+            .map(|variant| {
+                let value = match self.discriminants.get(variant) {
+                    Some(&value) => value,
+                    None => {
+                        while self.discriminants.values().any(|&v| v == next) {
+                            next += 1;
+                        }
+                        next += 1;
+                        next - 1
+                    }
+                };
+                (variant.clone(), value)
+            })
+            .collect()
+    }
+
+    /// Sort `fields`, which are in order of their names, into declaration
+    /// order.
+    pub fn in_declaration_order<N: Borrow<Name>, T>(&self, mut fields: Vec<(N, T)>) -> Vec<(N, T)> {
+        if !self.field_order.is_empty() {
+            fields
+                .sort_by_key(|(name, _)| self.field_order.iter().position(|n| n == name.borrow()));
+        }
+        fields
+    }
 }
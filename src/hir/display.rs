@@ -10,7 +10,7 @@ impl Display for Expr {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         match self {
             Expr::Var(v) => v.fmt(f),
-            Expr::U64(u) => write!(f, "{}_u64", u),
+            Expr::Int(ty, n) => write!(f, "{}_{}", n, ty.suffix()),
             Expr::Box(boxed) => write!(f, "box({})", boxed),
             Expr::Record(fields) => {
                 write!(f, "{}", display_map_like(fields.iter(), " = ", ", "))
@@ -37,6 +37,17 @@ impl Display for Expr {
     }
 }
 
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Value::Int(ty, n) => write!(f, "{}_{}", n, ty.suffix()),
+            Value::Box(boxed) => write!(f, "box({})", boxed),
+            Value::Record(fields) => write!(f, "{}", display_map_like(fields.iter(), " = ", ", ")),
+            Value::Variant { variant, field } => write!(f, "<{} = {}>", variant, field),
+        }
+    }
+}
+
 impl Display for Pat {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         match self {
@@ -61,20 +72,69 @@ impl Display for Var {
 impl Display for Ty {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         match self {
-            Ty::U64 => write!(f, "U64"),
+            Ty::Int(ty) => write!(f, "{}", ty),
             Ty::Box(boxed) => write!(f, "Box[{}]", boxed),
             Ty::Record(fields) => write!(f, "{}", display_map_like(fields.iter(), " : ", ", ")),
-            Ty::Variant(variants) => write!(
-                f,
-                "< {} >",
-                variants
-                    .iter()
-                    .map(|(n, t)| format!("{} of {}", n, t))
-                    .intersperse(" | ".into())
-                    .collect::<String>()
-            ),
+            Ty::Variant(variants) => fmt_variants(f, variants, &Map::new()),
             Ty::Recursive(body) => write!(f, "µ. {}", body),
             Ty::Named(name) => write!(f, "{}", name),
+            Ty::Repr(inner, repr) => {
+                for attr in repr.attrs() {
+                    write!(f, "#[{}] ", attr)?;
+                }
+                match &**inner {
+                    Ty::Record(fields) => {
+                        let fields = repr.in_declaration_order(fields.iter().collect());
+                        write!(f, "{}", display_map_like(fields.into_iter(), " : ", ", "))
+                    }
+                    Ty::Variant(variants) => fmt_variants(f, variants, &repr.discriminants),
+                    _ => inner.fmt(f),
+                }
+            }
+        }
+    }
+}
+
+fn fmt_variants(
+    f: &mut Formatter<'_>,
+    variants: &Map<Name, Ty>,
+    discriminants: &Map<Name, u64>,
+) -> Result {
+    write!(
+        f,
+        "< {} >",
+        variants
+            .iter()
+            .map(|(n, t)| match discriminants.get(n) {
+                Some(value) => format!("{} = {} of {}", n, value, t),
+                None => format!("{} of {}", n, t),
+            })
+            .intersperse(" | ".into())
+            .collect::<String>()
+    )
+}
+
+impl Repr {
+    /// The attributes, without `#[]`. Discriminants are shown on the
+    /// variants instead.
+    fn attrs(&self) -> Vec<String> {
+        let mut reprs = vec![];
+        if self.c {
+            reprs.push("C");
+        }
+        if let Some(ty) = self.tag {
+            reprs.push(ty.suffix());
+        }
+        let mut attrs = vec![];
+        if !reprs.is_empty() {
+            attrs.push(format!("repr({})", reprs.join(", ")));
+        }
+        if self.no_niche {
+            attrs.push("no_niche".to_owned());
+        }
+        if self.packed {
+            attrs.push("packed".to_owned());
         }
+        attrs
     }
 }
@@ -0,0 +1,80 @@
+//! Inhabitedness of types: whether a type has any (finite) values at all.
+
+use super::{TyId, TyInterner, TyKind};
+
+impl TyInterner {
+    /// Whether `ty` has no values, like the empty variant type `<>`.
+    ///
+    /// Unbound [`TyKind::Named`]s are assumed to be inhabited.
+    pub fn is_uninhabited(&mut self, ty: TyId) -> bool {
+        self.is_uninhabited_in(ty, &mut vec![])
+    }
+
+    /// `env` says whether each enclosing [`TyKind::Recursive`] is
+    /// uninhabited, innermost last.
+    pub(crate) fn is_uninhabited_in(&mut self, ty: TyId, env: &mut Vec<bool>) -> bool {
+        // Closed types don't depend on `env`.
+        let closed = self.is_closed(ty);
+        if let Some(&uninhabited) = self.uninhabited.get(&ty).filter(|_| closed) {
+            return uninhabited;
+        }
+        let uninhabited = match self.kind(ty).clone() {
+            TyKind::Int(_) => false,
+            // Values are finite, so a box has to hold a value.
+            TyKind::Box(boxed) => self.is_uninhabited_in(boxed, env),
+            TyKind::Repr(inner, _) => self.is_uninhabited_in(inner, env),
+            TyKind::Record(fields) => fields.values().any(|&t| self.is_uninhabited_in(t, env)),
+            TyKind::Variant(variants) => variants.values().all(|&t| self.is_uninhabited_in(t, env)),
+            TyKind::Recursive(body) => {
+                // Compute the least fixpoint: start by assuming the type is
+                // uninhabited. One step is enough, since if the body is
+                // inhabited under that assumption, it's inhabited regardless.
+                env.push(true);
+                let uninhabited = self.is_uninhabited_in(body, env);
+                env.pop();
+                uninhabited
+            }
+            TyKind::Named(k) => match env.len().checked_sub(k.index() as usize + 1) {
+                Some(idx) => env[idx],
+                None => false,
+            },
+        };
+        if closed {
+            self.uninhabited.insert(ty, uninhabited);
+        }
+        uninhabited
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_ty;
+
+    use super::*;
+
+    fn u(src: &str) -> bool {
+        let mut types = TyInterner::default();
+        let ty = types.intern(&parse_ty(src));
+        types.is_uninhabited(ty)
+    }
+
+    #[test]
+    fn inhabited_types() {
+        assert!(!u("U64"));
+        assert!(!u("{}"));
+        assert!(!u("<A of <> | B of {}>"));
+        assert!(!u("µX. <Nil of {} | Cons of { hd : {}, tl : Box[X] }>"));
+        assert!(!u("µX. <A of Box[X] | B of µY. <C of Box[Y] | D of {}>>"));
+    }
+
+    #[test]
+    fn uninhabited_types() {
+        assert!(u("<>"));
+        assert!(u("Box[<>]"));
+        assert!(u("{ x : U64, y : <> }"));
+        assert!(u("<A of <> | B of { x : <> }>"));
+        assert!(u("µX. Box[X]"));
+        assert!(u("µX. <A of Box[X] | B of { x : U64, y : Box[X] }>"));
+        assert!(u("µX. <A of Box[X] | B of µY. <C of Box[Y] | D of Box[X]>>"));
+    }
+}
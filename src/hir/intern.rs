@@ -0,0 +1,161 @@
+//! Hash-consed types.
+//!
+//! Structurally equal types are interned to the same [`TyId`], so facts about
+//! a type, like its layout, only have to be computed once per id.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+use crate::debruijn::Debruijn;
+use crate::int_ty::IntTy;
+use crate::name::Name;
+use crate::util::Map;
+
+use super::{Repr, Ty};
+
+/// An interned [`Ty`]. Ids are only meaningful to the [`TyInterner`] that
+/// created them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TyId(u32);
+
+/// A [`Ty`] whose children are interned.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TyKind {
+    Int(IntTy),
+    Box(TyId),
+
+    Record(Map<Name, TyId>),
+    Variant(Map<Name, TyId>),
+
+    Recursive(TyId),
+    Named(Debruijn),
+
+    Repr(TyId, Repr),
+}
+
+#[derive(Debug, Default)]
+pub struct TyInterner {
+    kinds: Vec<TyKind>,
+    /// For each type, the number of enclosing [`TyKind::Recursive`]s that it
+    /// refers to. Closed types refer to none.
+    free_depths: Vec<u64>,
+    ids: HashMap<TyKind, TyId>,
+    /// The memoized inhabitedness of closed types.
+    pub(super) uninhabited: HashMap<TyId, bool>,
+}
+
+impl TyInterner {
+    pub fn intern(&mut self, ty: &Ty) -> TyId {
+        let kind = match ty {
+            Ty::Int(ty) => TyKind::Int(*ty),
+            Ty::Box(boxed) => TyKind::Box(self.intern(boxed)),
+            Ty::Record(fields) => TyKind::Record(self.intern_map(fields)),
+            Ty::Variant(variants) => TyKind::Variant(self.intern_map(variants)),
+            Ty::Recursive(body) => TyKind::Recursive(self.intern(body)),
+            Ty::Named(id) => TyKind::Named(*id),
+            Ty::Repr(inner, repr) => TyKind::Repr(self.intern(inner), repr.clone()),
+        };
+        self.intern_kind(kind)
+    }
+
+    fn intern_map(&mut self, tys: &Map<Name, Ty>) -> Map<Name, TyId> {
+        tys.iter().map(|(n, t)| (n.clone(), self.intern(t))).collect()
+    }
+
+    pub fn intern_kind(&mut self, kind: TyKind) -> TyId {
+        if let Some(&id) = self.ids.get(&kind) {
+            return id;
+        }
+        let free_depth = match &kind {
+            TyKind::Int(_) => 0,
+            TyKind::Box(boxed) | TyKind::Repr(boxed, _) => self.free_depth(*boxed),
+            TyKind::Record(tys) | TyKind::Variant(tys) => {
+                tys.values().map(|&t| self.free_depth(t)).max().unwrap_or(0)
+            }
+            TyKind::Recursive(body) => self.free_depth(*body).saturating_sub(1),
+            TyKind::Named(id) => id.index() + 1,
+        };
+        let id = TyId(self.kinds.len().try_into().expect("too many types"));
+        self.kinds.push(kind.clone());
+        self.free_depths.push(free_depth);
+        self.ids.insert(kind, id);
+        id
+    }
+
+    pub fn kind(&self, id: TyId) -> &TyKind {
+        &self.kinds[id.0 as usize]
+    }
+
+    /// The type that `id` interns.
+    pub fn ty(&self, id: TyId) -> Ty {
+        let map =
+            |tys: &Map<Name, TyId>| tys.iter().map(|(n, &t)| (n.clone(), self.ty(t))).collect();
+        match self.kind(id) {
+            TyKind::Int(ty) => Ty::Int(*ty),
+            TyKind::Box(boxed) => Ty::Box(Box::new(self.ty(*boxed))),
+            TyKind::Record(fields) => Ty::Record(map(fields)),
+            TyKind::Variant(variants) => Ty::Variant(map(variants)),
+            TyKind::Recursive(body) => Ty::Recursive(Box::new(self.ty(*body))),
+            TyKind::Named(id) => Ty::Named(*id),
+            TyKind::Repr(inner, repr) => Ty::Repr(Box::new(self.ty(*inner)), repr.clone()),
+        }
+    }
+
+    /// Whether `id` has no back-references to enclosing recursive types, so
+    /// that it means the same wherever it occurs.
+    pub fn is_closed(&self, id: TyId) -> bool {
+        self.free_depth(id) == 0
+    }
+
+    fn free_depth(&self, id: TyId) -> u64 {
+        self.free_depths[id.0 as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_ty;
+
+    use super::*;
+
+    #[test]
+    fn equal_types_share_ids() {
+        let mut types = TyInterner::default();
+        let mut intern = |src: &str| types.intern(&parse_ty(src));
+        let list = intern("µL. <Nil of {} | Cons of { hd : U64, tl : Box[L] }>");
+        assert_eq!(list, intern("µM. <Cons of { tl : Box[M], hd : U64 } | Nil of {}>"));
+        assert_ne!(list, intern("µL. <Nil of {} | Cons of { hd : U32, tl : Box[L] }>"));
+        assert_eq!(intern("{}"), intern("{}"));
+        assert_ne!(intern("{}"), intern("<>"));
+    }
+
+    #[test]
+    fn ids_resolve_to_types() {
+        let mut types = TyInterner::default();
+        let ty = parse_ty("µL. #[no_niche] < Nil of {} | Cons of { hd : U64, tl : Box[L] } >");
+        let id = types.intern(&ty);
+        assert_eq!(types.ty(id).to_string(), ty.to_string());
+    }
+
+    #[test]
+    fn closed_types() {
+        let mut types = TyInterner::default();
+        let ty = parse_ty(
+            "µT. Box[{ v : U64, kids : µL. < Nil of {} | Cons of { hd : T, tl : Box[L] } > }]",
+        );
+        let outer = types.intern(&ty);
+        assert!(types.is_closed(outer));
+
+        let inner = |types: &TyInterner, id| match types.kind(id) {
+            TyKind::Recursive(body) | TyKind::Box(body) => *body,
+            TyKind::Record(fields) => fields[&Name::from("kids")],
+            kind => panic!("unexpected type {:?}", kind),
+        };
+        // `{ v : U64, kids : µL. ... }` refers to `T`, and so does `µL. ...`.
+        let record = inner(&types, inner(&types, outer));
+        assert!(!types.is_closed(record));
+        assert!(!types.is_closed(inner(&types, record)));
+        let option = types.intern(&parse_ty("<Nil of {} | Cons of U64>"));
+        assert!(types.is_closed(option));
+    }
+}
@@ -5,7 +5,7 @@ pub(super) type Subst = (Debruijn, Ty);
 
 pub(super) fn subst_ty(subst: Subst, target: Ty) -> Ty {
     match target {
-        Ty::U64 => target,
+        Ty::Int(_) => target,
         Ty::Box(mut boxed) => {
             *boxed = subst_ty(subst, *boxed);
             Ty::Box(boxed)
@@ -28,6 +28,10 @@ pub(super) fn subst_ty(subst: Subst, target: Ty) -> Ty {
                 Ty::Named(this)
             }
         }
+        Ty::Repr(mut inner, repr) => {
+            *inner = subst_ty(subst, *inner);
+            Ty::Repr(inner, repr)
+        }
     }
 }
 
@@ -37,7 +41,7 @@ fn shift_ty(ty: Ty, offset: u64) -> Ty {
 
 fn shift_ty_inner(ty: Ty, offset: u64, cutoff: Debruijn) -> Ty {
     match ty {
-        Ty::U64 => ty,
+        Ty::Int(_) => ty,
         Ty::Box(mut boxed) => {
             *boxed = shift_ty_inner(*boxed, offset, cutoff);
             Ty::Box(boxed)
@@ -53,5 +57,9 @@ fn shift_ty_inner(ty: Ty, offset: u64, cutoff: Debruijn) -> Ty {
             Ty::Recursive(body)
         }
         Ty::Named(k) => Ty::Named(if k < cutoff { k } else { k.shift_by(offset) }),
+        Ty::Repr(mut inner, repr) => {
+            *inner = shift_ty_inner(*inner, offset, cutoff);
+            Ty::Repr(inner, repr)
+        }
     }
 }
@@ -1,35 +1,46 @@
 use crate::debruijn::Debruijn;
+use crate::int_ty::IntTy;
+use crate::name::Name;
+use crate::util::Map;
 
 use super::ty_subst::subst_ty;
-use super::{Expr, Ty};
+use super::{Expr, Repr, Ty};
 
 impl Expr {
     pub(crate) fn ty(&self) -> Ty {
-        let ty = match self {
+        let ty = self.ty_unvalidated();
+        validate_ty(&ty);
+        ty
+    }
+
+    /// Like [`Expr::ty()`], but without validating the type. Validating the
+    /// types of all subexpressions would make computing a type quadratic.
+    fn ty_unvalidated(&self) -> Ty {
+        match self {
             Expr::Var(var) => var.ty.clone(),
-            Expr::U64(_) => Ty::U64,
-            Expr::Box(boxed) => Ty::Box(Box::new(boxed.ty())),
-            Expr::Record(rec) => Ty::Record(rec.iter().map(|(n, e)| (n.clone(), e.ty())).collect()),
+            Expr::Int(ty, _) => Ty::Int(*ty),
+            Expr::Box(boxed) => Ty::Box(Box::new(boxed.ty_unvalidated())),
+            Expr::Record(rec) => {
+                Ty::Record(rec.iter().map(|(n, e)| (n.clone(), e.ty_unvalidated())).collect())
+            }
             // FIXME: check the variant type too?
             Expr::Variant { ty, variant: _, field: _ } => ty.clone(),
             // FIXME: check types?
             Expr::Fold { ty, value: _ } => ty.clone(),
             Expr::Unfold { ty, value } => {
-                let v_ty = value.ty();
+                let v_ty = value.ty_unvalidated();
                 let rec_body = ty.as_recursive().unwrap();
                 let subst = (Debruijn::ZERO, v_ty);
                 subst_ty(subst, rec_body.clone())
             }
             // FIXME: check types?
-            Expr::Let { binder: _, value: _, body } => body.ty(),
+            Expr::Let { binder: _, value: _, body } => body.ty_unvalidated(),
             // FIXME: check the subj's and other cases' types too?
             Expr::Match { subj: _, cases } => {
                 let (_, first_body) = cases.first().expect("empty match is unsupported");
-                first_body.ty()
+                first_body.ty_unvalidated()
             }
-        };
-        validate_ty(&ty);
-        ty
+        }
     }
 }
 
@@ -39,7 +50,7 @@ pub(crate) fn validate_ty(ty: &Ty) {
 
 fn validate_ty_helper(max_recur_id: Debruijn, ty: &Ty) {
     match ty {
-        Ty::U64 => {}
+        Ty::Int(_) => {}
         Ty::Box(boxed) => validate_ty_helper(Debruijn::ZERO, boxed),
         Ty::Record(fields) => fields.values().for_each(|t| validate_ty_helper(max_recur_id, t)),
         Ty::Variant(variants) => {
@@ -51,6 +62,25 @@ fn validate_ty_helper(max_recur_id: Debruijn, ty: &Ty) {
                 panic!("type error: infinite recursive type; insert a Box");
             }
         }
+        Ty::Repr(inner, repr) => {
+            if let Ty::Variant(variants) = &**inner {
+                validate_discriminants(repr.tag_values(variants.keys()), repr);
+            }
+            validate_ty_helper(max_recur_id, inner)
+        }
+    }
+}
+
+fn validate_discriminants(values: Map<Name, u64>, repr: &Repr) {
+    let tag_ty = repr.tag.or(if repr.c { Some(IntTy::U32) } else { None });
+    let mut seen = Map::new();
+    for (variant, value) in values {
+        if let Some(ty) = tag_ty.filter(|ty| value > ty.max_value()) {
+            panic!("type error: discriminant {} of {} doesn't fit in {}", value, variant, ty);
+        }
+        if let Some(other) = seen.insert(value, variant.clone()) {
+            panic!("type error: {} and {} have the same discriminant {}", other, variant, value);
+        }
     }
 }
 
@@ -105,4 +135,16 @@ mod tests {
     fn invalid_type_5() {
         t("X");
     }
+
+    #[test]
+    #[should_panic = "have the same discriminant 1"]
+    fn duplicate_discriminants() {
+        t("< A = 1 of {} | B of {} | C = 1 of {} >");
+    }
+
+    #[test]
+    #[should_panic = "discriminant 256 of B doesn't fit in U8"]
+    fn discriminant_out_of_range() {
+        t("#[repr(u8)] < A of {} | B = 256 of {} >");
+    }
 }
@@ -0,0 +1,99 @@
+use crate::lir::{Align, Shape, Size};
+
+/// An unsigned integer type.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IntTy {
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl IntTy {
+    /// All integer types, from smallest to largest.
+    pub const ALL: [IntTy; 4] = [IntTy::U8, IntTy::U16, IntTy::U32, IntTy::U64];
+
+    /// The smallest integer type that can hold `value`.
+    pub fn fitting(value: u64) -> Self {
+        Self::ALL.into_iter().find(|ty| value <= ty.max_value()).unwrap()
+    }
+
+    /// Parse a type name, like `U8`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+
+    /// Parse a literal suffix, like `u8`.
+    pub fn from_suffix(suffix: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ty| ty.suffix() == suffix)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntTy::U8 => "U8",
+            IntTy::U16 => "U16",
+            IntTy::U32 => "U32",
+            IntTy::U64 => "U64",
+        }
+    }
+
+    pub fn suffix(self) -> &'static str {
+        match self {
+            IntTy::U8 => "u8",
+            IntTy::U16 => "u16",
+            IntTy::U32 => "u32",
+            IntTy::U64 => "u64",
+        }
+    }
+
+    pub fn max_value(self) -> u64 {
+        match self {
+            IntTy::U8 => u8::MAX.into(),
+            IntTy::U16 => u16::MAX.into(),
+            IntTy::U32 => u32::MAX.into(),
+            IntTy::U64 => u64::MAX,
+        }
+    }
+
+    /// Integers are naturally aligned.
+    pub fn shape(self) -> Shape {
+        let bytes = match self {
+            IntTy::U8 => 1,
+            IntTy::U16 => 2,
+            IntTy::U32 => 4,
+            IntTy::U64 => 8,
+        };
+        Shape { size: Size::from_bytes(bytes), align: Align::from_bytes(bytes) }
+    }
+}
+
+impl std::fmt::Display for IntTy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fitting() {
+        assert_eq!(IntTy::fitting(0), IntTy::U8);
+        assert_eq!(IntTy::fitting(255), IntTy::U8);
+        assert_eq!(IntTy::fitting(256), IntTy::U16);
+        assert_eq!(IntTy::fitting(65_536), IntTy::U32);
+        assert_eq!(IntTy::fitting(u64::from(u32::MAX) + 1), IntTy::U64);
+        assert_eq!(IntTy::fitting(u64::MAX), IntTy::U64);
+    }
+
+    #[test]
+    fn test_names() {
+        for ty in IntTy::ALL {
+            assert_eq!(IntTy::from_name(ty.name()), Some(ty));
+            assert_eq!(IntTy::from_suffix(ty.suffix()), Some(ty));
+        }
+        assert_eq!(IntTy::from_name("u8"), None);
+        assert_eq!(IntTy::from_suffix("U8"), None);
+    }
+}
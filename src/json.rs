@@ -0,0 +1,550 @@
+//! A stable JSON serialization of types, layouts, and values, for tools.
+//!
+//! Every enum is an object with a `"kind"`, in `snake_case`, and its fields.
+//! Names are strings, integer types are strings like `"U8"`, record reprs are
+//! `"padded"`, `"compact"`, or `"packed"`, and sizes are numbers of bytes.
+//! Integers are numbers, even above 2<sup>53</sup>.
+//! Fields in memory order are arrays; fields in name order are objects.
+//!
+//! | Type            | Serialization                                                         |
+//! |-----------------|-----------------------------------------------------------------------|
+//! | [`IntNiches`]   | `[[start, end], ...]`: sorted, disjoint, inclusive ranges             |
+//! | [`TagPath`]     | Projections from the root: `{"kind": "field", "name"}`, `{"kind": "variant", "repr", "name"}`, or `{"kind": "tag"}` |
+//! | [`Layout`]      | `int` (`ty`, `niches`), `ptr` (`pointee`, `niches`), `aggregate` (`repr`, `fields`: `[{"name", "layout"}]`), `single` (`field`), `tagged` (`tag`, `variants`), `recursive` (`body`), `recur_id` (`id`) |
+//! | [`TagLayout`]   | `direct` (`ty`, `values`, `niches`, `placement`: `"after"`, `"tail"`, or `"first"`), `niche` (`path`, `ty`, `values`), `multi_niche` (`holder`, `hoisted`, `field`, `path`, `ty`, `values`), `pointer_tag` (`bits`, `values`) |
+//! | [`lir::Ty`]     | `int` (`ty`), `ptr` (`pointee`), `record` (`repr`, `fields`: `[{"name", "ty"}]`), `union` (`fields`), `recursive` (`body`), `recur_id` (`id`) |
+//! | [`lir::Value`]  | `int` (`ty`, `value`), `record` (`fields`), `box` (`value`), `tagged_box` (`value`, `tag`) |
+//! | [`hir::Ty`]     | `int` (`ty`), `box` (`ty`), `record` (`fields`), `variant` (`variants`), `recursive` (`body`), `recur_id` (`id`), `repr` (`ty`, `c`, `tag`, `no_niche`, `packed`, `discriminants`, `field_order`) |
+//! | [`hir::Value`]  | `int` (`ty`, `value`), `box` (`value`), `record` (`fields`), `variant` (`variant`, `field`) |
+//! | [`Shape`]       | `{"size", "align", "stride"}`                                         |
+//! | [`Comparison`]  | `[{"strategy", "shape", "packed_size", "tag": {"kind", "location"} or null, "niches"}]` |
+//! | [`HeapStats`]   | `{"allocations", "bytes", "peak_live"}`                               |
+//! | [`HeapComparison`] | `[{"strategy", "size", "allocations", "bytes", "peak_live"}]`      |
+//! | [`Trace`]       | The lines, as strings, indented by two spaces per level              |
+//! | [`Diagram`]     | `{"shape", "rows": [{"variant", "cells": [{"offset", "size", "path", "kind", "descr"}]}]}` |
+//! | [`Memory`]      | `[{"address", "align", "bytes": [byte, ...], "scalars": [{"offset", "size", "path", "descr"}]}]`, by address |
+//! | [`Program`]     | `{"code": [instruction, ...], "result", "ty"}`, with the instructions and the result register as strings |
+//! | [`Differential`]| `{"eval", "vm", "agrees", "root", "memory"}`: `vm` is the value read back from the VM's memory, or null |
+//! | [`CrossCheck`]  | `{"rustc": {"size", "align"}, "predicted": {"size", "align"}, "match", "values": [{"value", "bytes", "lir"}]}` |
+//!
+//! Tag `values` are objects from variant names to tag values.
+//!
+//! With `--json`, the REPL prints each command's output as one line on
+//! stdout. Errors are printed as `{"error": message}` on stderr instead, so
+//! tools can tell them apart without parsing.
+
+use std::fmt::{Display, Formatter, Result};
+
+use crate::compare::{Comparison, HeapComparison};
+use crate::draw::Diagram;
+use crate::eval::HeapStats;
+use crate::hir;
+use crate::int_ty::IntTy;
+use crate::layout::{IntNiches, Layout, TagLayout, TagPath, ValueProj, VariantLayout};
+use crate::layout_of::Trace;
+use crate::lir::{self, Shape, Size};
+use crate::memory::Memory;
+use crate::name::Name;
+use crate::rust::CrossCheck;
+use crate::util::Map;
+use crate::vm::{Differential, Program};
+
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(u64),
+    String(String),
+    Array(Vec<Json>),
+    /// The entries are kept in order.
+    Object(Vec<(String, Json)>),
+}
+
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+/// An object with `kind` and then `fields`.
+fn object<const N: usize>(kind: &str, fields: [(&str, Json); N]) -> Json {
+    let kind = ("kind".to_owned(), Json::String(kind.to_owned()));
+    Json::Object(std::iter::once(kind).chain(fields.map(|(k, v)| (k.to_owned(), v))).collect())
+}
+
+impl Json {
+    /// An object without a `kind`.
+    pub fn record<const N: usize>(fields: [(&str, Json); N]) -> Json {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+    }
+
+    pub fn string(text: impl Display) -> Json {
+        Json::String(text.to_string())
+    }
+
+    pub fn array<T: ToJson>(items: impl IntoIterator<Item = T>) -> Json {
+        Json::Array(items.into_iter().map(|i| i.to_json()).collect())
+    }
+
+    pub fn map<'a, V: ToJson + 'a>(entries: impl IntoIterator<Item = (&'a Name, V)>) -> Json {
+        Json::Object(entries.into_iter().map(|(n, v)| (n.to_string(), v.to_json())).collect())
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for &T {
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+}
+
+impl<T: ToJson> ToJson for Box<T> {
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        self.as_ref().map_or(Json::Null, T::to_json)
+    }
+}
+
+impl ToJson for bool {
+    fn to_json(&self) -> Json {
+        Json::Bool(*self)
+    }
+}
+
+impl ToJson for u64 {
+    fn to_json(&self) -> Json {
+        Json::Number(*self)
+    }
+}
+
+impl ToJson for str {
+    fn to_json(&self) -> Json {
+        Json::string(self)
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> Json {
+        Json::string(self)
+    }
+}
+
+impl ToJson for Name {
+    fn to_json(&self) -> Json {
+        Json::string(self)
+    }
+}
+
+impl ToJson for IntTy {
+    fn to_json(&self) -> Json {
+        Json::string(self)
+    }
+}
+
+impl ToJson for lir::RecordRepr {
+    fn to_json(&self) -> Json {
+        Json::string(self.descr())
+    }
+}
+
+impl ToJson for Size {
+    fn to_json(&self) -> Json {
+        Json::Number(self.bytes())
+    }
+}
+
+impl ToJson for Shape {
+    fn to_json(&self) -> Json {
+        Json::record([
+            ("size", self.size.to_json()),
+            ("align", Json::Number(self.align.bytes())),
+            ("stride", self.stride().to_json()),
+        ])
+    }
+}
+
+impl ToJson for IntNiches {
+    fn to_json(&self) -> Json {
+        Json::Array(
+            self.ranges()
+                .iter()
+                .map(|r| Json::Array(vec![Json::Number(*r.start()), Json::Number(*r.end())]))
+                .collect(),
+        )
+    }
+}
+
+impl ToJson for TagPath {
+    fn to_json(&self) -> Json {
+        let mut projs = self.clone().rfold(vec![], |mut projs, proj| {
+            projs.push(proj.to_json());
+            projs
+        });
+        // `rfold()` starts at the innermost projection.
+        projs.reverse();
+        Json::Array(projs)
+    }
+}
+
+impl ToJson for ValueProj {
+    fn to_json(&self) -> Json {
+        match self {
+            ValueProj::Field(name) => object("field", [("name", name.to_json())]),
+            ValueProj::Variant { repr, name } => {
+                object("variant", [("repr", Json::string(repr.descr())), ("name", name.to_json())])
+            }
+            ValueProj::Tag => object("tag", []),
+        }
+    }
+}
+
+impl ToJson for Layout {
+    fn to_json(&self) -> Json {
+        match self {
+            Layout::Int(ty, niches) => {
+                object("int", [("ty", ty.to_json()), ("niches", niches.to_json())])
+            }
+            Layout::Ptr { pointee, niches } => {
+                object("ptr", [("pointee", pointee.to_json()), ("niches", niches.to_json())])
+            }
+            Layout::Aggregate { fields, repr } => {
+                let fields = fields.iter().map(|(name, layout)| {
+                    Json::record([("name", name.to_json()), ("layout", layout.to_json())])
+                });
+                object(
+                    "aggregate",
+                    [("repr", repr.to_json()), ("fields", Json::Array(fields.collect()))],
+                )
+            }
+            Layout::Variant(VariantLayout::Single { field }) => {
+                object("single", [("field", field.to_json())])
+            }
+            Layout::Variant(VariantLayout::Tagged(tagged)) => object(
+                "tagged",
+                [("tag", tagged.tag.to_json()), ("variants", Json::map(&tagged.variants))],
+            ),
+            Layout::Recursive(body) => object("recursive", [("body", body.to_json())]),
+            Layout::RecurId { id, unrolled: _ } => {
+                object("recur_id", [("id", id.index().to_json())])
+            }
+        }
+    }
+}
+
+fn tag_values(values: &Map<Name, u64>) -> Json {
+    Json::map(values)
+}
+
+impl ToJson for TagLayout {
+    fn to_json(&self) -> Json {
+        match self {
+            TagLayout::Direct { ty, values, niches, placement } => object(
+                "direct",
+                [
+                    ("ty", ty.to_json()),
+                    ("values", tag_values(values)),
+                    ("niches", niches.to_json()),
+                    ("placement", Json::string(placement.descr())),
+                ],
+            ),
+            TagLayout::Niche { path, ty, values } => object(
+                "niche",
+                [("path", path.to_json()), ("ty", ty.to_json()), ("values", tag_values(values))],
+            ),
+            TagLayout::MultiNiche { holder, hoisted, field, path, ty, values } => object(
+                "multi_niche",
+                [
+                    ("holder", holder.to_json()),
+                    ("hoisted", hoisted.to_json()),
+                    ("field", field.to_json()),
+                    ("path", path.to_json()),
+                    ("ty", ty.to_json()),
+                    ("values", tag_values(values)),
+                ],
+            ),
+            TagLayout::PointerTag { bits, values } => object(
+                "pointer_tag",
+                [("bits", u64::from(*bits).to_json()), ("values", tag_values(values))],
+            ),
+        }
+    }
+}
+
+impl ToJson for lir::Ty {
+    fn to_json(&self) -> Json {
+        let record = |fields: &Vec<(Name, lir::Ty)>, repr: &lir::RecordRepr| {
+            let fields = fields
+                .iter()
+                .map(|(name, ty)| Json::record([("name", name.to_json()), ("ty", ty.to_json())]));
+            object("record", [("repr", repr.to_json()), ("fields", Json::Array(fields.collect()))])
+        };
+        match self {
+            lir::Ty::Int(ty) => object("int", [("ty", ty.to_json())]),
+            lir::Ty::Ptr(pointee) => object("ptr", [("pointee", pointee.to_json())]),
+            lir::Ty::Record(fields, repr) => record(fields, repr),
+            lir::Ty::UntaggedUnion(fields) => object("union", [("fields", Json::map(fields))]),
+            lir::Ty::Recursive(body) => object("recursive", [("body", body.to_json())]),
+            lir::Ty::RecurId(id) => object("recur_id", [("id", id.index().to_json())]),
+        }
+    }
+}
+
+impl ToJson for lir::Value {
+    fn to_json(&self) -> Json {
+        match self {
+            lir::Value::Int(ty, value) => {
+                object("int", [("ty", ty.to_json()), ("value", value.to_json())])
+            }
+            lir::Value::Record(fields) => object("record", [("fields", Json::map(fields))]),
+            lir::Value::Box(value) => object("box", [("value", value.to_json())]),
+            lir::Value::TaggedBox(value, tag) => {
+                object("tagged_box", [("value", value.to_json()), ("tag", tag.to_json())])
+            }
+        }
+    }
+}
+
+impl ToJson for hir::Ty {
+    fn to_json(&self) -> Json {
+        match self {
+            hir::Ty::Int(ty) => object("int", [("ty", ty.to_json())]),
+            hir::Ty::Box(ty) => object("box", [("ty", ty.to_json())]),
+            hir::Ty::Record(fields) => object("record", [("fields", Json::map(fields))]),
+            hir::Ty::Variant(variants) => object("variant", [("variants", Json::map(variants))]),
+            hir::Ty::Recursive(body) => object("recursive", [("body", body.to_json())]),
+            hir::Ty::Named(id) => object("recur_id", [("id", id.index().to_json())]),
+            hir::Ty::Repr(ty, repr) => object(
+                "repr",
+                [
+                    ("ty", ty.to_json()),
+                    ("c", repr.c.to_json()),
+                    ("tag", repr.tag.to_json()),
+                    ("no_niche", repr.no_niche.to_json()),
+                    ("packed", repr.packed.to_json()),
+                    ("discriminants", tag_values(&repr.discriminants)),
+                    ("field_order", Json::array(&repr.field_order)),
+                ],
+            ),
+        }
+    }
+}
+
+impl ToJson for hir::Value {
+    fn to_json(&self) -> Json {
+        match self {
+            hir::Value::Int(ty, value) => {
+                object("int", [("ty", ty.to_json()), ("value", value.to_json())])
+            }
+            hir::Value::Box(value) => object("box", [("value", value.to_json())]),
+            hir::Value::Record(fields) => object("record", [("fields", Json::map(fields))]),
+            hir::Value::Variant { variant, field } => {
+                object("variant", [("variant", variant.to_json()), ("field", field.to_json())])
+            }
+        }
+    }
+}
+
+impl ToJson for Comparison {
+    fn to_json(&self) -> Json {
+        Json::Array(
+            self.rows
+                .iter()
+                .map(|row| {
+                    let tag = row.tag.as_ref().map(|(kind, location)| {
+                        Json::record([("kind", kind.to_json()), ("location", location.to_json())])
+                    });
+                    Json::record([
+                        ("strategy", row.strategy.to_json()),
+                        ("shape", row.shape.to_json()),
+                        ("packed_size", row.packed_size.to_json()),
+                        ("tag", tag.unwrap_or(Json::Null)),
+                        ("niches", row.niches.to_json()),
+                    ])
+                })
+                .collect(),
+        )
+    }
+}
+
+impl ToJson for HeapStats {
+    fn to_json(&self) -> Json {
+        Json::record([
+            ("allocations", self.allocations.to_json()),
+            ("bytes", self.bytes.to_json()),
+            ("peak_live", self.peak_live.to_json()),
+        ])
+    }
+}
+
+impl ToJson for HeapComparison {
+    fn to_json(&self) -> Json {
+        Json::Array(
+            self.rows
+                .iter()
+                .map(|row| {
+                    Json::record([
+                        ("strategy", row.strategy.to_json()),
+                        ("size", row.size.to_json()),
+                        ("allocations", row.stats.allocations.to_json()),
+                        ("bytes", row.stats.bytes.to_json()),
+                        ("peak_live", row.stats.peak_live.to_json()),
+                    ])
+                })
+                .collect(),
+        )
+    }
+}
+
+impl ToJson for Trace {
+    fn to_json(&self) -> Json {
+        Json::array(self.lines())
+    }
+}
+
+impl ToJson for Diagram {
+    fn to_json(&self) -> Json {
+        let rows = self.rows.iter().map(|row| {
+            let cells = row.cells.iter().map(|cell| {
+                Json::record([
+                    ("offset", cell.offset.to_json()),
+                    ("size", cell.size.to_json()),
+                    ("path", cell.path.to_json()),
+                    ("kind", Json::string(cell.kind.descr())),
+                    ("descr", cell.descr.to_json()),
+                ])
+            });
+            Json::record([
+                ("variant", row.variant.to_json()),
+                ("cells", Json::Array(cells.collect())),
+            ])
+        });
+        Json::record([("shape", self.shape.to_json()), ("rows", Json::Array(rows.collect()))])
+    }
+}
+
+impl ToJson for CrossCheck {
+    fn to_json(&self) -> Json {
+        let shape = |(size, align): (u64, u64)| {
+            Json::record([("size", size.to_json()), ("align", align.to_json())])
+        };
+        let values = self.values.iter().map(|(value, bytes, machine)| {
+            Json::record([
+                ("value", value.to_json()),
+                ("bytes", bytes.to_json()),
+                ("lir", machine.to_json()),
+            ])
+        });
+        Json::record([
+            ("rustc", shape(self.rustc)),
+            ("predicted", shape(self.predicted)),
+            ("match", self.layouts_match().to_json()),
+            ("values", Json::Array(values.collect())),
+        ])
+    }
+}
+
+impl ToJson for Memory {
+    fn to_json(&self) -> Json {
+        let allocations = self.allocations().map(|(addr, alloc)| {
+            let bytes = alloc.bytes.iter().map(|&b| Json::Number(b.into()));
+            let scalars = alloc.scalars.iter().map(|scalar| {
+                Json::record([
+                    ("offset", scalar.offset.to_json()),
+                    ("size", scalar.size.to_json()),
+                    ("path", scalar.path.to_json()),
+                    ("descr", scalar.descr.to_json()),
+                ])
+            });
+            Json::record([
+                ("address", addr.to_json()),
+                ("align", alloc.align.bytes().to_json()),
+                ("bytes", Json::Array(bytes.collect())),
+                ("scalars", Json::Array(scalars.collect())),
+            ])
+        });
+        Json::Array(allocations.collect())
+    }
+}
+
+impl ToJson for Program {
+    fn to_json(&self) -> Json {
+        Json::record([
+            ("code", Json::Array(self.code.iter().map(Json::string).collect())),
+            ("result", Json::string(self.result)),
+            ("ty", self.ty.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Differential {
+    fn to_json(&self) -> Json {
+        Json::record([
+            ("eval", self.value.to_json()),
+            ("vm", self.read_back.as_ref().map_or(Json::Null, |v| v.to_json())),
+            ("agrees", self.agrees().to_json()),
+            ("root", self.addr.to_json()),
+            ("memory", self.memory.to_json()),
+        ])
+    }
+}
+
+/// Compact, on one line.
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut Formatter<'_>, s: &str) -> Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings() {
+        let json = Json::record([("a \"b\"", Json::string("c\\d\ne\u{1}")), ("f", Json::Null)]);
+        assert_eq!(json.to_string(), r#"{"a \"b\"":"c\\d\ne\u0001","f":null}"#);
+    }
+}
@@ -2,25 +2,32 @@ mod display;
 pub mod extract;
 mod niches;
 mod path;
+mod shape;
+mod verify;
 
 pub use self::niches::IntNiches;
 pub use self::path::{TagPath, ValueProj};
+pub use self::verify::{VerifyError, VerifyErrorKind};
 
 use crate::debruijn::Debruijn;
+use crate::int_ty::IntTy;
+use crate::lir::RecordRepr;
 use crate::name::Name;
 use crate::util::Map;
 
 #[derive(Debug, Clone)]
 pub enum Layout {
-    U64(IntNiches),
+    Int(IntTy, IntNiches),
     /// **Note:** Use [`Layout::ptr()`] to construct this layout.
     Ptr {
         pointee: Box<Layout>,
         niches: IntNiches,
     },
 
+    /// The fields are in memory order.
     Aggregate {
-        fields: Map<Name, Layout>,
+        fields: Vec<(Name, Layout)>,
+        repr: RecordRepr,
     },
     Variant(VariantLayout),
 
@@ -29,7 +36,17 @@ pub enum Layout {
     /// Its sole raison d'être is to provide a "backreference" target for
     /// [`Layout::RecurId`]s.
     Recursive(Box<Layout>),
-    RecurId(Debruijn),
+    /// `unrolled` is the layout of the referenced [`Layout::Recursive`]'s body,
+    /// as seen from here. Niches are extracted from it, so it records which of
+    /// them are in use by enclosing variant types. Only its niches and shape
+    /// are meaningful: its pointees were computed before the back-references
+    /// were resolved.
+    ///
+    /// It is `None` while the referenced layout is still being computed.
+    RecurId {
+        id: Debruijn,
+        unrolled: Option<Box<Layout>>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -47,8 +64,72 @@ pub struct TaggedLayout {
 
 #[derive(Debug, Clone)]
 pub enum TagLayout {
-    Direct { values: Map<Name, u64>, niches: IntNiches },
-    Niche { path: TagPath, values: Map<Name, u64> },
+    Direct {
+        ty: IntTy,
+        values: Map<Name, u64>,
+        niches: IntNiches,
+        placement: TagPlacement,
+    },
+    /// `ty` is the type of the scalar at `path`.
+    Niche {
+        path: TagPath,
+        ty: IntTy,
+        values: Map<Name, u64>,
+    },
+    /// Like [`TagLayout::Niche`], but other variants than `holder` may have data.
+    ///
+    /// The part of `holder`'s data that contains the niche is moved into the
+    /// tag (`field`); this is either the whole data (`hoisted: None`) or the
+    /// single field `hoisted`. All variants' (remaining) data then overlap,
+    /// like with [`TagLayout::Direct`]. Every variant except `holder` is
+    /// identified by its value in `values`, read at `path`.
+    MultiNiche {
+        holder: Name,
+        hoisted: Option<Name>,
+        field: Box<Layout>,
+        path: TagPath,
+        ty: IntTy,
+        values: Map<Name, u64>,
+    },
+    /// The tag is stored in the low `bits` bits of a pointer, which are
+    /// always zero because of the pointee's alignment.
+    ///
+    /// Every variant's data is either a [`Layout::Ptr`] with enough spare
+    /// bits, or zero-sized (then it's stored as a null pointer with the tag).
+    PointerTag {
+        bits: u32,
+        values: Map<Name, u64>,
+    },
+}
+
+/// Where a [`TagLayout::Direct`] tag is stored, relative to the variants'
+/// data.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TagPlacement {
+    /// After the data's stride, like the next field of a padded record.
+    After,
+    /// Right after the data's size, in its trailing padding, like Swift.
+    Tail,
+    /// Before the data, like a C struct of a tag and a union.
+    First,
+}
+
+impl TagPlacement {
+    /// How the record of the data and the tag is laid out.
+    pub fn record_repr(self) -> RecordRepr {
+        match self {
+            TagPlacement::After | TagPlacement::First => RecordRepr::Padded,
+            TagPlacement::Tail => RecordRepr::Compact,
+        }
+    }
+
+    pub fn descr(self) -> &'static str {
+        match self {
+            TagPlacement::After => "after",
+            TagPlacement::Tail => "tail",
+            TagPlacement::First => "first",
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -83,45 +164,55 @@ impl Layout {
 
     pub fn is_zst(&self) -> bool {
         match self {
-            Layout::U64(..) | Layout::Ptr { .. } => false,
-            Layout::Aggregate { fields } => fields.values().all(Layout::is_zst),
+            Layout::Int(..) | Layout::Ptr { .. } => false,
+            Layout::Aggregate { fields, repr: _ } => fields.iter().all(|(_, l)| l.is_zst()),
             Layout::Variant(VariantLayout::Single { field }) => field.is_zst(),
             Layout::Variant(VariantLayout::Tagged(TaggedLayout { tag, variants })) => {
                 tag.is_zst() && variants.values().all(Layout::is_zst)
             }
             Layout::Recursive(body) => body.is_zst(),
-            // FIXME: is this correct?
-            Layout::RecurId(_) => false,
+            // Back-references that aren't unrolled yet can only be reached
+            // through a pointer, so they aren't zero-sized.
+            Layout::RecurId { id: _, unrolled } => unrolled.as_ref().map_or(false, |l| l.is_zst()),
         }
     }
 }
 
 impl TagLayout {
-    pub fn direct(values: Map<Name, u64>) -> Self {
-        let niches = IntNiches::range(0..=u64::MAX);
+    /// Uses the smallest integer type that can hold all of `values`.
+    pub fn direct(values: Map<Name, u64>, placement: TagPlacement) -> Self {
+        let ty = IntTy::fitting(values.values().copied().max().unwrap_or(0));
+        Self::direct_of_ty(ty, values, placement)
+    }
+
+    pub fn direct_of_ty(ty: IntTy, values: Map<Name, u64>, placement: TagPlacement) -> Self {
+        let niches = IntNiches::range(0..=ty.max_value());
         let niches =
             values.values().fold(niches, |niches, &value| niches.remove_value(value).unwrap());
-        Self::Direct { values, niches }
+        Self::Direct { ty, values, niches, placement }
     }
 
     pub fn is_zst(&self) -> bool {
         match self {
             TagLayout::Direct { .. } => false,
-            TagLayout::Niche { .. } => true,
+            TagLayout::Niche { .. } | TagLayout::PointerTag { .. } => true,
+            TagLayout::MultiNiche { field, .. } => field.is_zst(),
         }
     }
 
-    pub fn niches(&self) -> IntNiches {
+    pub fn descr(&self) -> &'static str {
         match self {
-            TagLayout::Direct { niches, values: _ } => niches.clone(),
-            TagLayout::Niche { path: _, values: _ } => IntNiches::none(),
+            TagLayout::Direct { .. } => "direct",
+            TagLayout::Niche { .. } => "niche",
+            TagLayout::MultiNiche { .. } => "multi-niche",
+            TagLayout::PointerTag { .. } => "pointer tag",
         }
     }
 
     pub fn as_variant_repr(&self) -> VariantRepr {
         match self {
-            TagLayout::Direct { .. } => VariantRepr::Wrapper,
-            TagLayout::Niche { .. } => VariantRepr::Transparent,
+            TagLayout::Direct { .. } | TagLayout::MultiNiche { .. } => VariantRepr::Wrapper,
+            TagLayout::Niche { .. } | TagLayout::PointerTag { .. } => VariantRepr::Transparent,
         }
     }
 }
@@ -7,14 +7,20 @@ use super::*;
 impl Display for Layout {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         match self {
-            Layout::U64(niches) => write!(f, "U64(niches: {})", niches),
+            Layout::Int(ty, niches) => write!(f, "{}(niches: {})", ty, niches),
             Layout::Ptr { pointee, niches } => {
                 write!(f, "Ptr(pointee: {}, niches: {})", pointee, niches)
             }
-            Layout::Aggregate { fields } => write!(f, "Aggregate {}", display_map(fields.iter())),
+            Layout::Aggregate { fields, repr } => {
+                write!(f, "Aggregate")?;
+                if *repr != RecordRepr::Padded {
+                    write!(f, "({})", repr.descr())?;
+                }
+                write!(f, " {}", display_map(fields.iter().map(|(n, l)| (n, l))))
+            }
             Layout::Variant(lyt) => write!(f, "Variant({})", lyt),
             Layout::Recursive(body) => write!(f, "Recursive({})", body),
-            Layout::RecurId(k) => write!(f, "recur{}", k),
+            Layout::RecurId { id, unrolled: _ } => write!(f, "recur{}", id),
         }
     }
 }
@@ -42,11 +48,42 @@ impl Display for TaggedLayout {
 impl Display for TagLayout {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         match self {
-            TagLayout::Direct { values, niches } => {
-                write!(f, "Direct(values: {}, niches: {})", display_map(values.iter()), niches)
+            TagLayout::Direct { ty, values, niches, placement } => {
+                write!(
+                    f,
+                    "Direct(ty: {}, values: {}, niches: {}",
+                    ty,
+                    display_map(values.iter()),
+                    niches
+                )?;
+                if *placement != TagPlacement::After {
+                    write!(f, ", placement: {}", placement.descr())?;
+                }
+                write!(f, ")")
+            }
+            TagLayout::Niche { path, ty, values } => write!(
+                f,
+                "Niche(path: {}, ty: {}, values: {})",
+                path,
+                ty,
+                display_map(values.iter())
+            ),
+            TagLayout::PointerTag { bits, values } => {
+                write!(f, "PointerTag(bits: {}, values: {})", bits, display_map(values.iter()))
             }
-            TagLayout::Niche { path, values } => {
-                write!(f, "Niche(path: {}, values: {})", path, display_map(values.iter()))
+            TagLayout::MultiNiche { holder, hoisted, field, path, ty, values } => {
+                write!(f, "MultiNiche(holder: {}", holder)?;
+                if let Some(hoisted) = hoisted {
+                    write!(f, ".{}", hoisted)?;
+                }
+                write!(
+                    f,
+                    ", path: {}, ty: {}, values: {}, field: {})",
+                    path,
+                    ty,
+                    display_map(values.iter()),
+                    field
+                )
             }
         }
     }
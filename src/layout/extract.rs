@@ -1,3 +1,4 @@
+use crate::int_ty::IntTy;
 use crate::name::Name;
 use crate::util::Map;
 
@@ -7,72 +8,232 @@ use super::{
 
 pub struct ExtractedNiche {
     pub path: TagPath,
+    /// The type of the scalar at `path`.
+    pub ty: IntTy,
     pub niche: IntNiches,
 }
 
-impl ExtractedNiche {
-    pub fn empty_path(niche: IntNiches) -> Self {
-        Self { path: TagPath::empty(), niche }
-    }
+/// How to choose among the scalars that have enough niche values.
+///
+/// The choice only depends on the shape of the layout, not on the names of
+/// fields and variants. Remaining ties are broken by the order in which
+/// fields and variants are laid out.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum NichePolicy {
+    /// Leave the largest niche possible for enclosing variant types, by using
+    /// the smallest niche that is large enough.
+    #[default]
+    LargestRemaining,
+    /// Make the discriminant check cheap: prefer niches whose values form
+    /// fewer ranges, then smaller scalars.
+    CheapestCheck,
+    /// Prefer scalars that are nested the least deeply.
+    Shallowest,
 }
 
-impl Layout {
-    pub fn extract_niche(&mut self, count: u64) -> Result<ExtractedNiche, ()> {
+impl NichePolicy {
+    /// Smaller is better.
+    fn rank(self, candidate: &NicheCandidate, largest_other: u64, count: u64) -> [u64; 3] {
+        let depth = candidate.path.depth() as u64;
+        let size = candidate.ty.shape().size.bytes();
+        let available = candidate.available();
+        let remaining = largest_other.max(available - count);
         match self {
-            Layout::U64(niches) | Layout::Ptr { pointee: _, niches } => {
-                niches.remove_some_values_mut(count).map(ExtractedNiche::empty_path)
+            NichePolicy::LargestRemaining => [u64::MAX - remaining, depth, size],
+            NichePolicy::CheapestCheck => {
+                let (_, taken) = candidate.niches.clone().remove_some_values(count).ok().unwrap();
+                [taken.ranges_count() as u64, size, depth]
             }
-            Layout::Aggregate { fields } => {
-                let layouts = fields.iter_mut().map(|(n, l)| with_field_proj(n.clone(), l));
-                extract_niches_from_many(layouts, count)
+            NichePolicy::Shallowest => [depth, u64::MAX - remaining, size],
+        }
+    }
+}
+
+/// A scalar with niche values.
+struct NicheCandidate {
+    path: TagPath,
+    ty: IntTy,
+    niches: IntNiches,
+}
+
+impl NicheCandidate {
+    fn available(&self) -> u64 {
+        self.niches.values_count().unwrap_or(u64::MAX)
+    }
+
+    fn with_outer_path(self, outer: TagPath) -> Self {
+        Self { path: self.path.with_outer_path(outer), ..self }
+    }
+}
+
+impl Layout {
+    pub fn extract_niche(&mut self, count: u64, policy: NichePolicy) -> Result<ExtractedNiche, ()> {
+        extract_niches_from_many(std::iter::once((TagPath::empty(), self)), count, policy)
+    }
+
+    /// The largest `count` for which [`Layout::extract_niche()`] would succeed.
+    pub fn max_niche_values_count(&self) -> u64 {
+        self.niche_candidates().iter().map(NicheCandidate::available).max().unwrap_or(0)
+    }
+
+    /// Take all niche values, so that enclosing variant types can't store
+    /// their tags in `self`.
+    pub fn clear_niches(&mut self) {
+        for candidate in self.niche_candidates() {
+            let count = candidate.available();
+            self.extract_niche_at(candidate.path, count);
+        }
+    }
+
+    /// All scalars that niche values could be taken from, in layout order.
+    fn niche_candidates(&self) -> Vec<NicheCandidate> {
+        let scalar = |ty, niches: &IntNiches| {
+            let niches = niches.clone();
+            if niches.is_empty() {
+                vec![]
+            } else {
+                vec![NicheCandidate { path: TagPath::empty(), ty, niches }]
             }
+        };
+        match self {
+            Layout::Int(ty, niches) => scalar(*ty, niches),
+            // Pointers are pointer-sized integers.
+            Layout::Ptr { pointee: _, niches } => scalar(IntTy::U64, niches),
+            Layout::Aggregate { fields, repr: _ } => fields
+                .iter()
+                .flat_map(|(n, l)| with_proj(ValueProj::Field(n.clone()), l.niche_candidates()))
+                .collect(),
             // FIXME: does this need a projection?
-            Layout::Variant(VariantLayout::Single { field }) => field.extract_niche(count),
+            Layout::Variant(VariantLayout::Single { field }) => field.niche_candidates(),
             Layout::Variant(VariantLayout::Tagged(TaggedLayout { tag, variants })) => {
-                if let Ok(niche) = tag.extract_niche(count) {
-                    return Ok(ExtractedNiche { path: TagPath::singleton(ValueProj::Tag), niche });
+                let mut candidates = with_proj(ValueProj::Tag, tag.niche_candidates());
+                match tag.as_variant_repr() {
+                    // Every variant's pointer holds the tag.
+                    _ if matches!(tag, TagLayout::PointerTag { .. }) => {}
+                    VariantRepr::Transparent => {
+                        candidates.extend(variants.iter().flat_map(|(name, l)| {
+                            let repr = VariantRepr::Transparent;
+                            let proj = ValueProj::Variant { repr, name: name.clone() };
+                            with_proj(proj, l.niche_candidates())
+                        }))
+                    }
+                    // The variants' data overlap, so a niche in one variant's
+                    // data would clash with the other variants' data.
+                    VariantRepr::Wrapper => {}
                 }
-                extract_niches_from_variants(variants, count, tag.as_variant_repr())
+                candidates
             }
-            Layout::Recursive(body) => body.extract_niche(count),
-            Layout::RecurId(_) => Err(()),
+            Layout::Recursive(body) => body.niche_candidates(),
+            Layout::RecurId { id: _, unrolled } => match unrolled {
+                Some(unrolled) => unrolled.niche_candidates(),
+                None => vec![],
+            },
         }
     }
-}
 
-fn with_field_proj<T>(field: Name, other: T) -> (TagPath, T) {
-    (TagPath::singleton(ValueProj::Field(field)), other)
+    /// Take `count` values from the niche of the scalar at `path`.
+    fn extract_niche_at(&mut self, path: TagPath, count: u64) -> IntNiches {
+        match self {
+            Layout::Variant(VariantLayout::Single { field }) => {
+                return field.extract_niche_at(path, count)
+            }
+            Layout::Recursive(body) => return body.extract_niche_at(path, count),
+            Layout::RecurId { id: _, unrolled: Some(unrolled) } => {
+                return unrolled.extract_niche_at(path, count)
+            }
+            _ => {}
+        }
+        match (self, path.split_outermost()) {
+            (Layout::Int(_, niches) | Layout::Ptr { pointee: _, niches }, None) => {
+                niches.remove_some_values_mut(count).unwrap()
+            }
+            (Layout::Aggregate { fields, repr: _ }, Some((ValueProj::Field(name), path))) => {
+                let (_, field) = fields.iter_mut().find(|(n, _)| *n == name).unwrap();
+                field.extract_niche_at(path, count)
+            }
+            (
+                Layout::Variant(VariantLayout::Tagged(TaggedLayout { tag, variants: _ })),
+                Some((ValueProj::Tag, path)),
+            ) => tag.extract_niche_at(path, count),
+            (
+                Layout::Variant(VariantLayout::Tagged(TaggedLayout { tag: _, variants })),
+                Some((ValueProj::Variant { repr: _, name }, path)),
+            ) => variants.get_mut(&name).unwrap().extract_niche_at(path, count),
+            _ => panic!("no niche at path"),
+        }
+    }
 }
-fn with_variant_proj<T>(repr: VariantRepr, name: Name, other: T) -> (TagPath, T) {
-    (TagPath::singleton(ValueProj::Variant { repr, name }), other)
+
+fn with_proj(proj: ValueProj, candidates: Vec<NicheCandidate>) -> Vec<NicheCandidate> {
+    let outer = TagPath::singleton(proj);
+    candidates.into_iter().map(|c| c.with_outer_path(outer.clone())).collect()
 }
 
 pub fn extract_niches_from_variants(
     variants: &mut Map<Name, Layout>,
     count: u64,
     repr: VariantRepr,
+    policy: NichePolicy,
 ) -> Result<ExtractedNiche, ()> {
-    let layouts = variants.iter_mut().map(|(n, l)| with_variant_proj(repr, n.clone(), l));
-    extract_niches_from_many(layouts, count)
+    let layouts = variants
+        .iter_mut()
+        .map(|(n, l)| (TagPath::singleton(ValueProj::Variant { repr, name: n.clone() }), l));
+    extract_niches_from_many(layouts, count, policy)
 }
 
-fn extract_niches_from_many<'a, I>(layouts: I, count: u64) -> Result<ExtractedNiche, ()>
+fn extract_niches_from_many<'a, I>(
+    layouts: I,
+    count: u64,
+    policy: NichePolicy,
+) -> Result<ExtractedNiche, ()>
 where
     I: Iterator<Item = (TagPath, &'a mut Layout)>,
 {
-    for (this_path, lyt) in layouts {
-        if let Ok(ExtractedNiche { path: inner_path, niche }) = lyt.extract_niche(count) {
-            return Ok(ExtractedNiche { path: inner_path.with_outer_path(this_path), niche });
-        }
-    }
-    Err(())
+    let mut layouts: Vec<_> = layouts.collect();
+    // The candidates of each layout, with paths relative to that layout.
+    let candidates: Vec<(usize, NicheCandidate)> = layouts
+        .iter()
+        .enumerate()
+        .flat_map(|(i, (_, l))| l.niche_candidates().into_iter().map(move |c| (i, c)))
+        .collect();
+
+    let best =
+        candidates.iter().enumerate().filter(|(_, (_, c))| c.available() >= count).min_by_key(
+            |&(idx, (_, c))| {
+                let others = candidates.iter().enumerate().filter(|&(j, _)| j != idx);
+                let largest_other = others.map(|(_, (_, c))| c.available()).max().unwrap_or(0);
+                policy.rank(c, largest_other, count)
+            },
+        );
+    let (_, (i, candidate)) = best.ok_or(())?;
+
+    let (outer, layout) = &mut layouts[*i];
+    let niche = layout.extract_niche_at(candidate.path.clone(), count);
+    let path = candidate.path.clone().with_outer_path(outer.clone());
+    Ok(ExtractedNiche { path, ty: candidate.ty, niche })
 }
 
 impl TagLayout {
-    pub fn extract_niche(&mut self, count: u64) -> Result<IntNiches, ()> {
+    /// The returned paths are relative to the tag.
+    fn niche_candidates(&self) -> Vec<NicheCandidate> {
         match self {
-            TagLayout::Direct { values: _, niches } => niches.remove_some_values_mut(count),
-            TagLayout::Niche { path: _, values: _ } => Err(()),
+            TagLayout::Direct { ty, values: _, niches, placement: _ } if !niches.is_empty() => {
+                vec![NicheCandidate { path: TagPath::empty(), ty: *ty, niches: niches.clone() }]
+            }
+            TagLayout::Direct { .. } | TagLayout::Niche { .. } | TagLayout::PointerTag { .. } => {
+                vec![]
+            }
+            TagLayout::MultiNiche { field, .. } => field.niche_candidates(),
+        }
+    }
+
+    fn extract_niche_at(&mut self, path: TagPath, count: u64) -> IntNiches {
+        match self {
+            TagLayout::Direct { ty: _, values: _, niches, placement: _ } if path.depth() == 0 => {
+                niches.remove_some_values_mut(count).unwrap()
+            }
+            TagLayout::MultiNiche { field, .. } => field.extract_niche_at(path, count),
+            _ => panic!("no niche at path"),
         }
     }
 }
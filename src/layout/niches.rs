@@ -2,54 +2,113 @@ use std::ops::RangeInclusive;
 
 use crate::util::range_values_count;
 
-#[derive(Debug, Clone, Default)]
+/// A set of niche values, stored as a normalized list of ranges.
+///
+/// The ranges are sorted, non-empty, and pairwise disjoint and non-adjacent
+/// (i.e., `0..=1` and `2..=3` are stored as `0..=3`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct IntNiches {
-    // TODO: use Vec to support multiple disjoint niche ranges
-    range: Option<Range>,
+    ranges: Vec<Range>,
 }
 
 type Range = RangeInclusive<u64>;
 
 impl IntNiches {
     pub fn none() -> Self {
-        Self { range: None }
+        Self { ranges: vec![] }
     }
 
     pub fn range(range: Range) -> Self {
-        Self { range: Some(range) }
+        Self::from_ranges(vec![range])
     }
 
-    fn from_option(range: Option<Range>) -> Self {
-        Self { range }
+    pub fn from_ranges(ranges: Vec<Range>) -> Self {
+        Self { ranges: normalize_ranges(ranges) }
     }
 
-    pub(crate) fn as_range(&self) -> Option<Range> {
-        let Self { range } = self;
-        range.clone()
+    /// The sorted, disjoint ranges of values.
+    pub fn ranges(&self) -> &[Range] {
+        &self.ranges
+    }
+
+    /// The number of disjoint ranges the values form.
+    pub fn ranges_count(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// The number of niche values, or `None` if it doesn't fit in a `u64`.
+    pub fn values_count(&self) -> Option<u64> {
+        self.ranges
+            .iter()
+            .try_fold(0u64, |acc, r| range_values_count(r.clone()).and_then(|n| acc.checked_add(n)))
+    }
+
+    pub fn contains(&self, value: u64) -> bool {
+        self.ranges.iter().any(|r| r.contains(&value))
+    }
+
+    /// The values that are in `self` or in `other`.
+    pub fn union(self, other: Self) -> Self {
+        Self::from_ranges([self.ranges, other.ranges].concat())
+    }
+
+    /// Iterate over the niche values in increasing order.
+    pub fn values(&self) -> impl Iterator<Item = u64> + '_ {
+        self.ranges.iter().flat_map(Clone::clone)
     }
 
     pub fn remove_value(self, value: u64) -> Result<Self, Self> {
-        match self {
-            Self { range: None } => Err(Self::none()),
-            Self { range: Some(range) } => {
-                remove_value_from_range(value, range).map(Self::from_option).map_err(Self::range)
+        let Self { ranges } = self;
+        match ranges.iter().position(|r| r.contains(&value)) {
+            None => Err(Self { ranges }),
+            Some(idx) => {
+                let mut ranges = ranges;
+                let range = ranges.remove(idx);
+                let (lower, upper) = split_range_at(value, range);
+                ranges.extend(lower);
+                ranges.extend(upper);
+                Ok(Self::from_ranges(ranges))
             }
         }
     }
 
-    /// Remove `count` niche values from `self`.
+    /// Remove `count` niche values from `self`, starting from the smallest.
     ///
     /// If that many values were available, returns `Ok((new_self, extracted_values))`.
     /// If not enough values were available, returns `Err(old_self)`.
     pub fn remove_some_values(self, count: u64) -> Result<(Self, Self), Self> {
-        match self {
-            Self { range: None } => Err(Self::none()),
-            Self { range: Some(range) } => shrink_range_by(range, count)
-                .map(|OkRangeShrink { new_range, extracted }| {
-                    (Self::from_option(new_range), Self::from_option(extracted))
-                })
-                .map_err(Self::range),
+        if self.is_empty() || self.values_count().map_or(false, |available| available < count) {
+            return Err(self);
         }
+
+        let mut remaining = count;
+        let mut kept = vec![];
+        let mut extracted = vec![];
+        for range in self.ranges {
+            if remaining == 0 {
+                kept.push(range);
+                continue;
+            }
+            match shrink_range_by(range, remaining) {
+                Ok(OkRangeShrink { new_range, extracted: taken }) => {
+                    remaining = 0;
+                    kept.extend(new_range);
+                    extracted.extend(taken);
+                }
+                // The whole range is smaller than what we still need, so take all of it.
+                Err(range) => {
+                    remaining -= range_values_count(range.clone()).unwrap();
+                    extracted.push(range);
+                }
+            }
+        }
+        assert_eq!(remaining, 0);
+
+        Ok((Self::from_ranges(kept), Self::from_ranges(extracted)))
     }
 
     pub fn remove_some_values_mut(&mut self, count: u64) -> Result<Self, ()> {
@@ -84,28 +143,11 @@ fn shrink_range_by(range: Range, count: u64) -> Result<OkRangeShrink, Range> {
     }
 }
 
-fn remove_value_from_range(value: u64, range: Range) -> Result<Option<Range>, Range> {
-    if !range.contains(&value) {
-        return Err(range);
-    }
-
-    let old_start = *range.start();
-    let old_end = *range.end();
-
-    let new_range = if value == old_end {
-        match value.checked_sub(1) {
-            Some(new_end) => old_start..=new_end,
-            // We're removing 0 from a range that ended at 0,
-            // so return an empty range.
-            None => return Ok(None),
-        }
-    } else {
-        // Arbitrarily keep the upper side of the range.
-        let new_start = value.checked_add(1).unwrap();
-        new_start..=old_end
-    };
-
-    Ok(normalize_range(new_range))
+/// Split `range` into the values below `value` and the values above it.
+fn split_range_at(value: u64, range: Range) -> (Option<Range>, Option<Range>) {
+    let lower = value.checked_sub(1).and_then(|end| normalize_range(*range.start()..=end));
+    let upper = value.checked_add(1).and_then(|start| normalize_range(start..=*range.end()));
+    (lower, upper)
 }
 
 fn normalize_range(range: Range) -> Option<Range> {
@@ -116,14 +158,36 @@ fn normalize_range(range: Range) -> Option<Range> {
     }
 }
 
+fn normalize_ranges(mut ranges: Vec<Range>) -> Vec<Range> {
+    ranges.retain(|r| !r.is_empty());
+    ranges.sort_by_key(|r| *r.start());
+
+    let mut normalized: Vec<Range> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match normalized.last_mut() {
+            // Merge overlapping or adjacent ranges.
+            Some(last) if last.end().checked_add(1).map_or(true, |next| *range.start() <= next) => {
+                let end = (*last.end()).max(*range.end());
+                *last = *last.start()..=end;
+            }
+            _ => normalized.push(range),
+        }
+    }
+    normalized
+}
+
 impl std::fmt::Display for IntNiches {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self { range: None } => {
-                write!(f, "none")
+        if self.ranges.is_empty() {
+            return write!(f, "none");
+        }
+        for (i, range) in self.ranges.iter().enumerate() {
+            if i != 0 {
+                write!(f, " ∪ ")?;
             }
-            Self { range: Some(range) } => write!(f, "{:?}", range),
+            write!(f, "{:?}", range)?;
         }
+        Ok(())
     }
 }
 
@@ -166,12 +230,63 @@ mod tests {
     }
 
     #[test]
-    fn test_remove_value_from_range() {
-        assert_eq!(remove_value_from_range(7, 2..=5), Err(2..=5));
-        assert_eq!(remove_value_from_range(0, 0..=0), Ok(None));
-        assert_eq!(remove_value_from_range(1, 0..=3), Ok(Some(2..=3)));
-        assert_eq!(remove_value_from_range(2, 1..=3), Ok(Some(3..=3)));
-        assert_eq!(remove_value_from_range(1, 1..=3), Ok(Some(2..=3)));
-        assert_eq!(remove_value_from_range(3, 1..=3), Ok(Some(1..=2)));
+    fn test_remove_value() {
+        fn remove(ranges: Vec<Range>, value: u64) -> Result<Vec<Range>, Vec<Range>> {
+            IntNiches::from_ranges(ranges)
+                .remove_value(value)
+                .map(|n| n.ranges)
+                .map_err(|n| n.ranges)
+        }
+
+        assert_eq!(remove(vec![2..=5], 7), Err(vec![2..=5]));
+        assert_eq!(remove(vec![0..=0], 0), Ok(vec![]));
+        assert_eq!(remove(vec![0..=3], 1), Ok(vec![0..=0, 2..=3]));
+        assert_eq!(remove(vec![1..=3], 2), Ok(vec![1..=1, 3..=3]));
+        assert_eq!(remove(vec![1..=3], 1), Ok(vec![2..=3]));
+        assert_eq!(remove(vec![1..=3], 3), Ok(vec![1..=2]));
+        assert_eq!(remove(vec![0..=u64::MAX], u64::MAX), Ok(vec![0..=(u64::MAX - 1)]));
+        assert_eq!(remove(vec![0..=1, 3..=5], 4), Ok(vec![0..=1, 3..=3, 5..=5]));
+        assert_eq!(remove(vec![0..=1, 3..=5], 2), Err(vec![0..=1, 3..=5]));
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn test_from_ranges_normalizes() {
+        fn norm(ranges: Vec<Range>) -> Vec<Range> {
+            IntNiches::from_ranges(ranges).ranges
+        }
+
+        assert_eq!(norm(vec![3..=2]), vec![]);
+        assert_eq!(norm(vec![4..=5, 0..=1]), vec![0..=1, 4..=5]);
+        assert_eq!(norm(vec![0..=1, 2..=3]), vec![0..=3]);
+        assert_eq!(norm(vec![0..=5, 2..=3]), vec![0..=5]);
+        assert_eq!(norm(vec![0..=u64::MAX, 7..=9]), vec![0..=u64::MAX]);
+    }
+
+    #[test]
+    fn test_remove_some_values() {
+        fn take(ranges: Vec<Range>, count: u64) -> Result<(Vec<Range>, Vec<Range>), Vec<Range>> {
+            IntNiches::from_ranges(ranges)
+                .remove_some_values(count)
+                .map(|(kept, taken)| (kept.ranges, taken.ranges))
+                .map_err(|n| n.ranges)
+        }
+
+        assert_eq!(take(vec![], 0), Err(vec![]));
+        assert_eq!(take(vec![0..=3], 2), Ok((vec![2..=3], vec![0..=1])));
+        assert_eq!(take(vec![0..=1, 5..=9], 3), Ok((vec![6..=9], vec![0..=1, 5..=5])));
+        assert_eq!(take(vec![0..=1, 5..=9], 7), Ok((vec![], vec![0..=1, 5..=9])));
+        assert_eq!(take(vec![0..=1, 5..=9], 8), Err(vec![0..=1, 5..=9]));
+        assert_eq!(
+            take(vec![0..=u64::MAX], u64::MAX),
+            Ok((vec![u64::MAX..=u64::MAX], vec![0..=(u64::MAX - 1)]))
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(IntNiches::none().to_string(), "none");
+        assert_eq!(IntNiches::range(2..=5).to_string(), "2..=5");
+        assert_eq!(IntNiches::from_ranges(vec![0..=0, 2..=5]).to_string(), "0..=0 ∪ 2..=5");
     }
 }
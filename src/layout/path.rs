@@ -5,7 +5,7 @@ use super::VariantRepr;
 
 /// The path to the variant tag.
 // FIXME: rename to ValuePath?
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TagPath {
     /// **Note:** The path is *reversed*. In other words, if the path is
     /// `<root>.field1.field2`, then the representation will be `[field2, field1]`.
@@ -25,6 +25,19 @@ impl TagPath {
         Self { reversed: self.reversed.concat(outer.reversed) }
     }
 
+    /// The number of projections.
+    pub fn depth(&self) -> usize {
+        self.reversed.iter().count()
+    }
+
+    /// Split off the outermost projection, i.e., the one applied to the root first.
+    pub fn split_outermost(self) -> Option<(ValueProj, TagPath)> {
+        let mut projs: Vec<_> = self.reversed.into_iter().collect();
+        let outermost = projs.pop()?;
+        let reversed = projs.into_iter().rev().fold(Nil, |tl, hd| Cons(hd, Box::new(tl)));
+        Some((outermost, TagPath { reversed }))
+    }
+
     pub fn rfold<R, F>(self, init: R, f: F) -> R
     where
         F: FnMut(R, ValueProj) -> R,
@@ -33,7 +46,7 @@ impl TagPath {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ValueProj {
     Field(Name),
     Variant { repr: VariantRepr, name: Name },
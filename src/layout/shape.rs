@@ -0,0 +1,171 @@
+//! Sizes, alignments, and field offsets of layouts.
+//!
+//! These agree with [`crate::lir::Ty::shape()`] on the lowered type: a tagged
+//! layout lowers to a record `{ data, tag }`, so the variants' data is at
+//! offset zero and the tag comes after it, as [`TaggedLayout::record_repr()`]
+//! says, unless the tag comes first (`{ tag, data }`).
+
+use crate::lir::{RecordRepr, Shape, Size};
+use crate::name::Name;
+use crate::util::Map;
+
+use super::{Layout, TagLayout, TagPlacement, TaggedLayout, VariantLayout};
+
+/// The enclosing [`Layout::Recursive`] bodies, innermost last.
+type Env<'a> = Vec<&'a Layout>;
+
+impl Layout {
+    /// The size and alignment of this layout.
+    ///
+    /// Panics if `self` has a [`Layout::RecurId`] that is neither bound inside
+    /// of it nor unrolled (outside of a pointer).
+    #[track_caller]
+    pub fn shape(&self) -> Shape {
+        self.try_shape().expect("layout has an unbound `RecurId`")
+    }
+
+    /// Like [`Layout::shape()`], but returns `None` instead of panicking.
+    pub fn try_shape(&self) -> Option<Shape> {
+        self.shape_in(&mut vec![])
+    }
+
+    /// The byte offsets of the fields of an `Aggregate`, or of the `data` and
+    /// `tag` of a tagged variant layout that stores its tag separately.
+    pub fn field_offsets(&self) -> Option<Map<Name, Size>> {
+        match self {
+            Layout::Aggregate { fields, repr } => {
+                let shapes = fields.iter().map(|(_, l)| l.shape());
+                let (offsets, _) = Shape::record(shapes, *repr);
+                Some(fields.iter().map(|(n, _)| n.clone()).zip(offsets).collect())
+            }
+            Layout::Variant(VariantLayout::Tagged(tagged)) => {
+                let tag = tagged.tag_offset()?;
+                let data = tagged.data_offset();
+                Some(map! { Name::from("data") => data, Name::from("tag") => tag })
+            }
+            Layout::Int(..)
+            | Layout::Ptr { .. }
+            | Layout::Variant(VariantLayout::Single { .. })
+            | Layout::Recursive(_)
+            | Layout::RecurId { .. } => None,
+        }
+    }
+
+    /// The number of low bits of a pointer that are always zero because of
+    /// the pointee's alignment.
+    ///
+    /// This is zero for non-pointers, and for pointers whose pointee can't be
+    /// sized yet.
+    pub fn spare_ptr_bits(&self) -> u32 {
+        match self {
+            Layout::Ptr { pointee, niches: _ } => {
+                pointee.try_shape().map_or(0, |s| s.align.bytes().trailing_zeros())
+            }
+            _ => 0,
+        }
+    }
+
+    fn shape_in<'a>(&'a self, env: &mut Env<'a>) -> Option<Shape> {
+        match self {
+            Layout::Int(ty, _) => Some(ty.shape()),
+            Layout::Ptr { .. } => Some(Shape::BITS_64),
+            Layout::Aggregate { fields, repr } => {
+                let shapes =
+                    fields.iter().map(|(_, l)| l.shape_in(env)).collect::<Option<Vec<_>>>()?;
+                Some(Shape::record(shapes, *repr).1)
+            }
+            Layout::Variant(VariantLayout::Single { field }) => field.shape_in(env),
+            Layout::Variant(VariantLayout::Tagged(tagged)) => {
+                let (data, tag) = tagged.parts_in(env)?;
+                Some(match tag {
+                    Some(tag) => tagged.record(data, tag).2,
+                    None => data,
+                })
+            }
+            Layout::Recursive(body) => {
+                env.push(body);
+                let shape = body.shape_in(env);
+                env.pop();
+                shape
+            }
+            // Direct references (not behind a pointer) can only occur in
+            // nested recursive types, so this terminates.
+            Layout::RecurId { id, unrolled } => {
+                match env.len().checked_sub(id.index() as usize + 1) {
+                    Some(idx) => {
+                        let mut outer = env[..=idx].to_vec();
+                        env[idx].shape_in(&mut outer)
+                    }
+                    // The referenced layout is outside of `self`.
+                    None => unrolled.as_ref()?.shape_in(env),
+                }
+            }
+        }
+    }
+}
+
+impl TaggedLayout {
+    /// The byte offset of the tag, if it is stored separately from the
+    /// variants' data.
+    pub fn tag_offset(&self) -> Option<Size> {
+        self.offsets().1
+    }
+
+    /// The byte offset of the variants' data, which is zero unless the tag
+    /// comes first.
+    pub fn data_offset(&self) -> Size {
+        self.offsets().0
+    }
+
+    /// Whether the tag is stored before the variants' data.
+    pub fn tag_first(&self) -> bool {
+        matches!(self.tag, TagLayout::Direct { placement: TagPlacement::First, .. })
+    }
+
+    fn offsets(&self) -> (Size, Option<Size>) {
+        let (data, tag) = self.parts_in(&mut vec![]).expect("layout has an unbound `RecurId`");
+        match tag {
+            Some(tag) => {
+                let (data, tag, _) = self.record(data, tag);
+                (data, Some(tag))
+            }
+            None => (Size::ZERO, None),
+        }
+    }
+
+    /// Lay out the variants' data and the separate tag in a record. Returns
+    /// their offsets and the shape of the whole record.
+    fn record(&self, data: Shape, tag: Shape) -> (Size, Size, Shape) {
+        if self.tag_first() {
+            let (offsets, whole) = Shape::record([tag, data], self.record_repr());
+            (offsets[1], offsets[0], whole)
+        } else {
+            let (offsets, whole) = Shape::record([data, tag], self.record_repr());
+            (offsets[0], offsets[1], whole)
+        }
+    }
+
+    /// How the record of the variants' data and the separate tag is laid out.
+    pub fn record_repr(&self) -> RecordRepr {
+        match &self.tag {
+            TagLayout::Direct { placement, .. } => placement.record_repr(),
+            // Only niche-filling strategies use these, and they pad records.
+            TagLayout::Niche { .. }
+            | TagLayout::PointerTag { .. }
+            | TagLayout::MultiNiche { .. } => RecordRepr::Padded,
+        }
+    }
+
+    /// The shapes of the overlapping variants' data and of the separate tag, if any.
+    fn parts_in<'a>(&'a self, env: &mut Env<'a>) -> Option<(Shape, Option<Shape>)> {
+        let TaggedLayout { tag, variants } = self;
+        let shapes = variants.values().map(|l| l.shape_in(env)).collect::<Option<Vec<_>>>()?;
+        let data = Shape::union(shapes);
+        let tag = match tag {
+            TagLayout::Direct { ty, .. } => Some(ty.shape()),
+            TagLayout::Niche { .. } | TagLayout::PointerTag { .. } => None,
+            TagLayout::MultiNiche { field, .. } => Some(field.shape_in(env)?),
+        };
+        Some((data, tag))
+    }
+}
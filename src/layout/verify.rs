@@ -0,0 +1,423 @@
+//! Checking the invariants that lowering and niche extraction rely on.
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::debruijn::Debruijn;
+use crate::int_ty::IntTy;
+use crate::name::Name;
+use crate::util::Map;
+
+use super::{IntNiches, Layout, TagLayout, TagPath, TaggedLayout, ValueProj, VariantLayout};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    /// Where in the value the invalid layout is, like a [`TagPath`], with
+    /// `*` for dereferencing a pointer.
+    pub location: String,
+    pub kind: VerifyErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyErrorKind {
+    DuplicateTagValue {
+        value: u64,
+    },
+    TagValueOutOfRange {
+        variant: Name,
+        value: u64,
+        ty: IntTy,
+    },
+    /// The tag value is still available as a niche, so an enclosing variant
+    /// type could use it too.
+    TagValueIsNiche {
+        variant: Name,
+        value: u64,
+    },
+    /// The scalar that holds the tag has this value for a value of the
+    /// variant that holds the niche.
+    TagValueInUse {
+        variant: Name,
+        value: u64,
+    },
+    UnresolvedPath(TagPath),
+    PathTypeMismatch {
+        path: TagPath,
+        expected: IntTy,
+        found: IntTy,
+    },
+    /// A variant of a [`TagLayout::PointerTag`] is neither a pointer with
+    /// enough spare bits nor zero-sized.
+    NotPointerTaggable {
+        variant: Name,
+    },
+    UnboundRecurId(Debruijn),
+}
+
+impl Layout {
+    /// Check that tag values are distinct and don't clash with niches or
+    /// with the values of the variants' data, that niche paths resolve to
+    /// scalars of the right type, and that back-references are bound.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        verify_in(self, "{root}", 0)
+    }
+}
+
+/// `depth` is the number of enclosing [`Layout::Recursive`]s.
+fn verify_in(layout: &Layout, loc: &str, depth: u64) -> Result<(), VerifyError> {
+    match layout {
+        Layout::Int(..) => Ok(()),
+        Layout::Ptr { pointee, niches: _ } => verify_in(pointee, &format!("*{}", loc), depth),
+        Layout::Aggregate { fields, repr: _ } => {
+            fields.iter().try_for_each(|(n, l)| verify_in(l, &format!("{}.{}", loc, n), depth))
+        }
+        Layout::Variant(VariantLayout::Single { field }) => verify_in(field, loc, depth),
+        Layout::Variant(VariantLayout::Tagged(tagged)) => {
+            verify_tag(tagged).map_err(|kind| VerifyError { location: loc.to_owned(), kind })?;
+            if let TagLayout::MultiNiche { field, .. } = &tagged.tag {
+                verify_in(field, &format!("{}.{{tag}}", loc), depth)?;
+            }
+            tagged
+                .variants
+                .iter()
+                .try_for_each(|(n, l)| verify_in(l, &format!("({} as {})", loc, n), depth))
+        }
+        Layout::Recursive(body) => verify_in(body, loc, depth + 1),
+        // `unrolled` is a provisional copy of an enclosing layout, which is
+        // verified where it occurs.
+        Layout::RecurId { id, unrolled: _ } if id.index() < depth => Ok(()),
+        Layout::RecurId { id, unrolled: _ } => Err(VerifyError {
+            location: loc.to_owned(),
+            kind: VerifyErrorKind::UnboundRecurId(*id),
+        }),
+    }
+}
+
+fn verify_tag(tagged: &TaggedLayout) -> Result<(), VerifyErrorKind> {
+    let TaggedLayout { tag, variants } = tagged;
+    match tag {
+        TagLayout::Direct { ty, values, niches, placement: _ } => {
+            verify_distinct(values)?;
+            for (variant, &value) in values {
+                verify_in_range(variant, value, *ty)?;
+                if niches.contains(value) {
+                    return Err(VerifyErrorKind::TagValueIsNiche {
+                        variant: variant.clone(),
+                        value,
+                    });
+                }
+            }
+            Ok(())
+        }
+        TagLayout::Niche { path, ty, values } => {
+            verify_distinct(values)?;
+            // The niche is in the one variant that has data.
+            let unresolved = || VerifyErrorKind::UnresolvedPath(path.clone());
+            let (dataful, scalar) = match path.clone().split_outermost() {
+                Some((ValueProj::Variant { repr: _, name }, rest)) => {
+                    let layout = variants.get(&name).ok_or_else(unresolved)?;
+                    (name, resolve(layout, rest).ok_or_else(unresolved)?)
+                }
+                _ => return Err(unresolved()),
+            };
+            if values.contains_key(&dataful) {
+                return Err(VerifyErrorKind::UnresolvedPath(path.clone()));
+            }
+            verify_niche_values(path, *ty, values, scalar)
+        }
+        TagLayout::MultiNiche { holder, hoisted: _, field, path, ty, values } => {
+            verify_distinct(values)?;
+            let unresolved = || VerifyErrorKind::UnresolvedPath(path.clone());
+            let scalar = match path.clone().split_outermost() {
+                Some((ValueProj::Tag, rest)) => resolve(field, rest).ok_or_else(unresolved)?,
+                _ => return Err(unresolved()),
+            };
+            if values.contains_key(holder) {
+                return Err(VerifyErrorKind::UnresolvedPath(path.clone()));
+            }
+            verify_niche_values(path, *ty, values, scalar)
+        }
+        TagLayout::PointerTag { bits, values } => {
+            verify_distinct(values)?;
+            for (variant, &value) in values {
+                if value.checked_shr(*bits).map_or(false, |high| high != 0) {
+                    return Err(VerifyErrorKind::TagValueOutOfRange {
+                        variant: variant.clone(),
+                        value,
+                        ty: IntTy::U64,
+                    });
+                }
+            }
+            for (variant, layout) in variants {
+                let taggable = match layout {
+                    Layout::Ptr { .. } => layout.spare_ptr_bits() >= *bits,
+                    _ => layout.is_zst(),
+                };
+                if !taggable {
+                    return Err(VerifyErrorKind::NotPointerTaggable { variant: variant.clone() });
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn verify_distinct(values: &Map<Name, u64>) -> Result<(), VerifyErrorKind> {
+    let mut seen: Vec<u64> = values.values().copied().collect();
+    seen.sort_unstable();
+    match seen.windows(2).find(|w| w[0] == w[1]) {
+        Some(w) => Err(VerifyErrorKind::DuplicateTagValue { value: w[0] }),
+        None => Ok(()),
+    }
+}
+
+fn verify_in_range(variant: &Name, value: u64, ty: IntTy) -> Result<(), VerifyErrorKind> {
+    if value > ty.max_value() {
+        return Err(VerifyErrorKind::TagValueOutOfRange { variant: variant.clone(), value, ty });
+    }
+    Ok(())
+}
+
+fn verify_niche_values(
+    path: &TagPath,
+    ty: IntTy,
+    values: &Map<Name, u64>,
+    scalar: Scalar,
+) -> Result<(), VerifyErrorKind> {
+    if scalar.ty != ty {
+        return Err(VerifyErrorKind::PathTypeMismatch {
+            path: path.clone(),
+            expected: ty,
+            found: scalar.ty,
+        });
+    }
+    for (variant, &value) in values {
+        verify_in_range(variant, value, ty)?;
+        if scalar.niches.contains(value) {
+            return Err(VerifyErrorKind::TagValueIsNiche { variant: variant.clone(), value });
+        }
+        if scalar.used.contains(value) {
+            return Err(VerifyErrorKind::TagValueInUse { variant: variant.clone(), value });
+        }
+    }
+    Ok(())
+}
+
+/// The scalar at the end of a niche path.
+struct Scalar {
+    ty: IntTy,
+    /// The values that are still free for enclosing variant types.
+    niches: IntNiches,
+    /// The values that the scalar holds for values of the layout that the
+    /// path was resolved in.
+    used: IntNiches,
+}
+
+/// Follow `path` through `layout` like niche extraction does.
+fn resolve(layout: &Layout, path: TagPath) -> Option<Scalar> {
+    match layout {
+        Layout::Variant(VariantLayout::Single { field }) => return resolve(field, path),
+        Layout::Recursive(body) => return resolve(body, path),
+        Layout::RecurId { id: _, unrolled: Some(unrolled) } => return resolve(unrolled, path),
+        _ => {}
+    }
+    match (layout, path.split_outermost()) {
+        (Layout::Int(ty, niches), None) => Some(Scalar {
+            ty: *ty,
+            niches: niches.clone(),
+            used: IntNiches::range(0..=ty.max_value()),
+        }),
+        // Only null pointers are invalid.
+        (Layout::Ptr { pointee: _, niches }, None) => Some(Scalar {
+            ty: IntTy::U64,
+            niches: niches.clone(),
+            used: IntNiches::range(1..=u64::MAX),
+        }),
+        (Layout::Aggregate { fields, repr: _ }, Some((ValueProj::Field(name), rest))) => {
+            let (_, field) = fields.iter().find(|(n, _)| *n == name)?;
+            resolve(field, rest)
+        }
+        (
+            Layout::Variant(VariantLayout::Tagged(TaggedLayout { tag, variants: _ })),
+            Some((ValueProj::Tag, rest)),
+        ) => match tag {
+            TagLayout::Direct { ty, values, niches, placement: _ } if rest.depth() == 0 => {
+                Some(Scalar {
+                    ty: *ty,
+                    niches: niches.clone(),
+                    used: IntNiches::from_ranges(values.values().map(|&v| v..=v).collect()),
+                })
+            }
+            TagLayout::MultiNiche { field, path, values, .. } => {
+                let scalar = resolve(field, rest.clone())?;
+                Some(with_tag_values(scalar, path, ValueProj::Tag, rest, values))
+            }
+            TagLayout::Direct { .. } | TagLayout::Niche { .. } | TagLayout::PointerTag { .. } => {
+                None
+            }
+        },
+        (
+            Layout::Variant(VariantLayout::Tagged(TaggedLayout { tag, variants })),
+            Some((proj @ ValueProj::Variant { .. }, rest)),
+        ) => {
+            let name = match &proj {
+                ValueProj::Variant { repr: _, name } => name,
+                _ => unreachable!(),
+            };
+            let scalar = resolve(variants.get(name)?, rest.clone())?;
+            match tag {
+                TagLayout::Niche { path, ty: _, values } => {
+                    Some(with_tag_values(scalar, path, proj, rest, values))
+                }
+                _ => Some(scalar),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// If a tag's niche is at the same scalar as `proj` followed by `rest`, the
+/// scalar also holds that tag's values.
+fn with_tag_values(
+    scalar: Scalar,
+    tag_path: &TagPath,
+    proj: ValueProj,
+    rest: TagPath,
+    values: &Map<Name, u64>,
+) -> Scalar {
+    if *tag_path != rest.with_outer_path(TagPath::singleton(proj)) {
+        return scalar;
+    }
+    let tag_values = IntNiches::from_ranges(values.values().map(|&v| v..=v).collect());
+    Scalar { used: scalar.used.union(tag_values), ..scalar }
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid layout at {}: {}", self.location, self.kind)
+    }
+}
+
+impl Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyErrorKind::DuplicateTagValue { value } => {
+                write!(f, "tag value {} is used for multiple variants", value)
+            }
+            VerifyErrorKind::TagValueOutOfRange { variant, value, ty } => {
+                write!(f, "tag value {} of variant `{}` doesn't fit in {}", value, variant, ty)
+            }
+            VerifyErrorKind::TagValueIsNiche { variant, value } => {
+                write!(f, "tag value {} of variant `{}` is still a niche", value, variant)
+            }
+            VerifyErrorKind::TagValueInUse { variant, value } => write!(
+                f,
+                "tag value {} of variant `{}` is a valid value of the niche's scalar",
+                value, variant
+            ),
+            VerifyErrorKind::UnresolvedPath(path) => {
+                write!(f, "niche path {} doesn't lead to a scalar in a dataful variant", path)
+            }
+            VerifyErrorKind::PathTypeMismatch { path, expected, found } => {
+                write!(f, "niche path {} leads to a {}, not a {}", path, found, expected)
+            }
+            VerifyErrorKind::NotPointerTaggable { variant } => write!(
+                f,
+                "variant `{}` is neither a pointer with enough spare bits nor zero-sized",
+                variant
+            ),
+            VerifyErrorKind::UnboundRecurId(id) => {
+                write!(f, "back-reference {} has no enclosing recursive layout", id)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layout_of::{layout_of, layout_of_with, STRATEGIES};
+    use crate::parse_ty;
+
+    use super::*;
+
+    const MAYBE_BOOL: &str = "<None of {} | Some of <False of {} | True of {}>>";
+
+    fn tagged(layout: &mut Layout) -> &mut TaggedLayout {
+        match layout {
+            Layout::Variant(VariantLayout::Tagged(tagged)) => tagged,
+            _ => panic!("expected a tagged layout"),
+        }
+    }
+
+    fn error(layout: Layout) -> String {
+        layout.verify().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn computed_layouts_are_valid() {
+        let tys = [
+            MAYBE_BOOL,
+            "<A of {} | B of { x : U8, y : <C of {} | D of {}> } | E of { z : U16 }>",
+            "µL. Box[< Nil of {} | Cons of { hd : U64, tl : L } >]",
+            "µT. Box[{ v : U64, kids : µL. < Nil of {} | Cons of { hd : T, tl : Box[L] } > }]",
+        ];
+        for ty in tys {
+            for strategy in STRATEGIES {
+                layout_of_with(parse_ty(ty), strategy).verify().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_tags() {
+        let mut layout = layout_of(parse_ty("<A of {} | B of {} | C of {}>"));
+        if let TagLayout::Direct { values, .. } = &mut tagged(&mut layout).tag {
+            values.insert("C".into(), 0);
+        }
+        assert_eq!(
+            error(layout),
+            "invalid layout at {root}: tag value 0 is used for multiple variants"
+        );
+
+        // `0` is how `Some(False)` is represented.
+        let mut layout = layout_of(parse_ty(MAYBE_BOOL));
+        if let TagLayout::Niche { values, .. } = &mut tagged(&mut layout).tag {
+            values.insert("None".into(), 0);
+        }
+        assert_eq!(
+            error(layout),
+            "invalid layout at {root}: tag value 0 of variant `None` is a valid value of the niche's scalar"
+        );
+
+        // `2` is how `S(None)` is represented.
+        let ty = format!("<N of {{}} | S of {}>", MAYBE_BOOL);
+        let mut layout = layout_of(parse_ty(&ty));
+        if let TagLayout::Niche { values, .. } = &mut tagged(&mut layout).tag {
+            values.insert("N".into(), 2);
+        }
+        assert_eq!(
+            error(layout),
+            "invalid layout at {root}: tag value 2 of variant `N` is a valid value of the niche's scalar"
+        );
+
+        let mut layout = layout_of(parse_ty(MAYBE_BOOL));
+        if let TagLayout::Niche { path, .. } = &mut tagged(&mut layout).tag {
+            *path = TagPath::singleton(ValueProj::Field("x".into()));
+        }
+        assert_eq!(
+            error(layout),
+            "invalid layout at {root}: niche path {root}.x doesn't lead to a scalar in a dataful variant"
+        );
+    }
+
+    #[test]
+    fn unbound_recur_id() {
+        let layout = Layout::Aggregate {
+            fields: vec![("x".into(), Layout::RecurId { id: Debruijn::ZERO, unrolled: None })],
+            repr: crate::lir::RecordRepr::Padded,
+        };
+        assert_eq!(
+            error(layout),
+            "invalid layout at {root}.x: back-reference #0 has no enclosing recursive layout"
+        );
+    }
+}
@@ -1,128 +1,571 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
 use std::convert::TryInto;
 
-use crate::hir;
-use crate::layout::extract::{extract_niches_from_variants, ExtractedNiche};
-use crate::layout::{IntNiches, Layout, TagLayout, TaggedLayout, VariantLayout, VariantRepr};
+use crate::debruijn::Debruijn;
+use crate::hir::{self, Repr, TyId, TyKind};
+use crate::int_ty::IntTy;
+use crate::layout::extract::{extract_niches_from_variants, ExtractedNiche, NichePolicy};
+use crate::layout::{
+    IntNiches, Layout, TagLayout, TagPath, TagPlacement, TaggedLayout, ValueProj, VariantLayout,
+    VariantRepr,
+};
+use crate::lir::{RecordRepr, Size};
 use crate::name::Name;
-use crate::util::{expect_singleton_vec, range_values_count, Map};
+use crate::util::{expect_singleton_vec, Map};
 
+mod strategy;
+mod trace;
+
+pub use self::strategy::{
+    strategy_by_name, CLike, LayoutStrategy, NicheFilling, SpareBits, DEFAULT, STRATEGIES,
+};
+pub use self::trace::Trace;
+
+use self::trace::{count, display_names};
+
+/// Uses the [`DEFAULT`] strategy.
+#[cfg(test)]
 pub fn layout_of(ty: hir::Ty) -> Layout {
-    hir::validate_ty(&ty);
-    match ty {
-        hir::Ty::U64 => Layout::U64(IntNiches::none()),
-        hir::Ty::Box(boxed) => Layout::ptr(layout_of(*boxed)),
-        hir::Ty::Record(fields) => Layout::Aggregate {
-            fields: fields.into_iter().map(|(n, t)| (n, layout_of(t))).collect(),
-        },
-        hir::Ty::Variant(variants) => match variants.len() {
-            0 => layout_of_empty_type(),
-            1 => {
-                let field_ty = expect_singleton_vec(variants.into_values().collect());
-                layout_of_singleton_variant(field_ty)
+    layout_of_with(ty, DEFAULT)
+}
+
+/// Lays out `ty` without reusing any previously computed layouts.
+pub fn layout_of_with(ty: hir::Ty, strategy: &dyn LayoutStrategy) -> Layout {
+    LayoutCx::new(strategy).layout_of(&ty)
+}
+
+/// Memoized layout computation with a fixed strategy.
+///
+/// Types are interned, and the layouts of closed types are cached by id, so
+/// laying out the same type again, or a type containing it, is cheap.
+pub struct LayoutCx<'s> {
+    strategy: &'s dyn LayoutStrategy,
+    types: hir::TyInterner,
+    /// The layouts of closed types, which don't depend on enclosing types.
+    layouts: HashMap<hir::TyId, Layout>,
+}
+
+impl<'s> LayoutCx<'s> {
+    pub fn new(strategy: &'s dyn LayoutStrategy) -> Self {
+        Self { strategy, types: hir::TyInterner::default(), layouts: HashMap::new() }
+    }
+
+    pub fn strategy(&self) -> &'s dyn LayoutStrategy {
+        self.strategy
+    }
+
+    /// Switch to another strategy, dropping the cached layouts.
+    pub fn set_strategy(&mut self, strategy: &'s dyn LayoutStrategy) {
+        self.strategy = strategy;
+        self.layouts.clear();
+    }
+
+    pub fn layout_of(&mut self, ty: &hir::Ty) -> Layout {
+        self.layout_of_traced(ty, &mut Trace::disabled())
+    }
+
+    /// Like [`LayoutCx::layout_of()`], but also records why each part of the
+    /// layout was chosen.
+    pub fn explain(&mut self, ty: &hir::Ty) -> (Layout, Trace) {
+        let mut trace = Trace::enabled();
+        let layout = self.layout_of_traced(ty, &mut trace);
+        (layout, trace)
+    }
+
+    /// Like [`LayoutCx::layout_of()`], for a type that is already interned,
+    /// so that callers that build types out of other types' ids don't have to
+    /// intern the whole type again.
+    pub fn layout_of_id(&mut self, id: TyId) -> Layout {
+        self.layout_of_id_traced(id, &mut Trace::disabled())
+    }
+
+    fn layout_of_traced(&mut self, ty: &hir::Ty, trace: &mut Trace) -> Layout {
+        let id = self.types.intern(ty);
+        if !self.layouts.contains_key(&id) {
+            hir::validate_ty(ty);
+        }
+        self.layout_of_id_traced(id, trace)
+    }
+
+    /// `id` has to be valid, like the types that [`LayoutCx::intern()`]
+    /// returns, and records and boxes of them.
+    fn layout_of_id_traced(&mut self, id: TyId, trace: &mut Trace) -> Layout {
+        let cached = self.layouts.contains_key(&id);
+        let layout =
+            layout_of_in(&mut Ctxt { lcx: self, uninhabited: vec![], unrolled: vec![], trace }, id);
+        if cfg!(debug_assertions) && !cached {
+            if let Err(err) = layout.verify() {
+                panic!("{}\nfor type: {}\nlayout: {}", err, self.types.ty(id), layout);
             }
-            _ => layout_of_multi_variant_type(variants),
-        },
-        hir::Ty::Recursive(body) => Layout::Recursive(Box::new(layout_of(*body))),
-        hir::Ty::Named(k) => Layout::RecurId(k),
+        }
+        layout
+    }
+
+    /// Validates and interns `ty`.
+    pub fn intern(&mut self, ty: &hir::Ty) -> TyId {
+        hir::validate_ty(ty);
+        self.types.intern(ty)
+    }
+
+    /// The id of a type whose children are already interned.
+    pub fn intern_kind(&mut self, kind: TyKind) -> TyId {
+        self.types.intern_kind(kind)
+    }
+
+    pub fn is_uninhabited(&mut self, ty: &hir::Ty) -> bool {
+        let id = self.types.intern(ty);
+        self.types.is_uninhabited(id)
     }
 }
 
-fn layout_of_empty_type() -> Layout {
-    // TODO: layout types containing empty types more efficiently
-    Layout::Variant(VariantLayout::Single {
-        field: Box::new(Layout::Aggregate { fields: map! {} }),
-    })
+struct Ctxt<'a, 's> {
+    lcx: &'a mut LayoutCx<'s>,
+    /// Whether each enclosing recursive type is uninhabited, innermost last.
+    uninhabited: Vec<bool>,
+    /// The provisional layout of each enclosing recursive type's body, if it
+    /// has been computed yet, innermost last.
+    unrolled: Vec<Option<Layout>>,
+    trace: &'a mut Trace,
 }
 
-fn layout_of_singleton_variant(field_ty: hir::Ty) -> Layout {
-    let field_lyt = layout_of(field_ty);
-    Layout::Variant(VariantLayout::Single { field: Box::new(field_lyt) })
+fn layout_of_in(cx: &mut Ctxt, ty: TyId) -> Layout {
+    // Closed types mean the same everywhere, so their layouts can be reused.
+    let closed = cx.lcx.types.is_closed(ty);
+    // Cached layouts would hide their decisions from the trace.
+    if let Some(layout) = cx.lcx.layouts.get(&ty).filter(|_| closed && !cx.trace.is_enabled()) {
+        return layout.clone();
+    }
+    let kind = cx.lcx.types.kind(ty).clone();
+    let traced = !matches!(kind, TyKind::Int(_) | TyKind::Box(_) | TyKind::Named(_));
+    if traced {
+        let types = &cx.lcx.types;
+        cx.trace.enter(|| types.ty(ty).to_string());
+    }
+    let layout = match kind {
+        TyKind::Int(ty) => Layout::Int(ty, IntNiches::none()),
+        TyKind::Box(boxed) => Layout::ptr(layout_of_in(cx, boxed)),
+        TyKind::Record(fields) => layout_of_record(cx, fields, &Repr::default()),
+        TyKind::Variant(variants) => layout_of_variant_type(cx, variants, &Repr::default()),
+        TyKind::Recursive(body) => {
+            // See `hir::TyInterner::is_uninhabited_in()`.
+            cx.uninhabited.push(true);
+            let uninhabited = cx.lcx.types.is_uninhabited_in(body, &mut cx.uninhabited);
+            *cx.uninhabited.last_mut().unwrap() = uninhabited;
+
+            // Lay out the body once with opaque back-references, then again
+            // with them unrolled to that provisional layout. Back-references
+            // can only be reached directly from inside a pointer in the body,
+            // and niches don't look through pointers, so the provisional
+            // layout's niches are already final.
+            cx.unrolled.push(None);
+            cx.trace.enter(|| "provisional layout, with opaque back-references".to_owned());
+            let provisional = layout_of_in(cx, body);
+            cx.trace.exit();
+            *cx.unrolled.last_mut().unwrap() = Some(provisional);
+            cx.trace.enter(|| "final layout, with unrolled back-references".to_owned());
+            let body = layout_of_in(cx, body);
+            cx.trace.exit();
+
+            cx.unrolled.pop();
+            cx.uninhabited.pop();
+            Layout::Recursive(Box::new(body))
+        }
+        TyKind::Named(id) => {
+            let unrolled = match cx.unrolled.len().checked_sub(id.index() as usize + 1) {
+                Some(idx) => cx.unrolled[idx].clone(),
+                None => None,
+            };
+            // The body is now `id.index()` recursive types further in.
+            let unrolled = unrolled.map(|l| Box::new(shift_layout(l, id.index(), Debruijn::ZERO)));
+            Layout::RecurId { id, unrolled }
+        }
+        TyKind::Repr(inner, repr) => {
+            let mut layout = match cx.lcx.types.kind(inner).clone() {
+                TyKind::Record(fields) => layout_of_record(cx, fields, &repr),
+                TyKind::Variant(variants) => layout_of_variant_type(cx, variants, &repr),
+                kind => unreachable!("representation attributes on {:?}", kind),
+            };
+            if repr.no_niche {
+                cx.trace.note(|| {
+                    let available = count(layout.max_niche_values_count(), "value");
+                    format!("cleared niches with up to {} for #[no_niche]", available)
+                });
+                layout.clear_niches();
+            }
+            layout
+        }
+    };
+    if traced {
+        cx.trace.exit();
+    }
+    if closed {
+        cx.lcx.layouts.insert(ty, layout.clone());
+    }
+    layout
+}
+
+/// Shift the back-references in `layout` that point outside of it by `offset`.
+fn shift_layout(layout: Layout, offset: u64, cutoff: Debruijn) -> Layout {
+    let shift = |l: Layout| shift_layout(l, offset, cutoff);
+    match layout {
+        Layout::Int(..) => layout,
+        Layout::Ptr { pointee, niches } => {
+            Layout::Ptr { pointee: Box::new(shift(*pointee)), niches }
+        }
+        Layout::Aggregate { fields, repr } => {
+            let fields = fields.into_iter().map(|(n, l)| (n, shift(l))).collect();
+            Layout::Aggregate { fields, repr }
+        }
+        Layout::Variant(VariantLayout::Single { field }) => {
+            Layout::Variant(VariantLayout::Single { field: Box::new(shift(*field)) })
+        }
+        Layout::Variant(VariantLayout::Tagged(TaggedLayout { tag, variants })) => {
+            let tag = match tag {
+                TagLayout::MultiNiche { holder, hoisted, field, path, ty, values } => {
+                    let field = Box::new(shift(*field));
+                    TagLayout::MultiNiche { holder, hoisted, field, path, ty, values }
+                }
+                TagLayout::Direct { .. }
+                | TagLayout::Niche { .. }
+                | TagLayout::PointerTag { .. } => tag,
+            };
+            let variants = variants.into_iter().map(|(n, l)| (n, shift(l))).collect();
+            Layout::Variant(VariantLayout::Tagged(TaggedLayout { tag, variants }))
+        }
+        Layout::Recursive(body) => {
+            Layout::Recursive(Box::new(shift_layout(*body, offset, cutoff.shift_by(1))))
+        }
+        Layout::RecurId { id, unrolled } => {
+            let id = if id >= cutoff { id.shift_by(offset) } else { id };
+            Layout::RecurId { id, unrolled: unrolled.map(|l| Box::new(shift(*l))) }
+        }
+    }
 }
 
-fn layout_of_multi_variant_type(variants: Map<Name, hir::Ty>) -> Layout {
-    let variants = variants.into_iter().map(|(n, t)| (n, layout_of(t))).collect();
-    let lyt = match is_nicheable(&variants) {
-        Nicheable::Yes { field: _, nullary_variants } => {
-            layout_of_tagged_niche_type(variants, nullary_variants)
+fn layout_of_record(cx: &mut Ctxt, fields: Map<Name, TyId>, repr: &Repr) -> Layout {
+    let fields = repr.in_declaration_order(fields.into_iter().collect());
+    let mut fields: Vec<_> = fields.into_iter().map(|(n, t)| (n, layout_of_in(cx, t))).collect();
+    if repr.c {
+        cx.trace.note(|| "kept the fields in declaration order for #[repr(C)]".to_owned());
+    } else if repr.packed {
+        // Packed records have no padding to save by reordering.
+        cx.trace.note(|| "kept the fields in order of their names for #[packed]".to_owned());
+    } else {
+        let names = |fields: &[(Name, Layout)]| fields.iter().map(|(n, _)| n.clone()).collect();
+        let before: Vec<_> = names(&fields);
+        cx.lcx.strategy.order_fields(&mut fields);
+        let after: Vec<_> = names(&fields);
+        if before != after {
+            cx.trace.note(|| format!("reordered the fields to {}", display_names(&after)));
         }
-        Nicheable::No => layout_of_tagged_direct_type(variants),
+    }
+    // C lays out nested structs with their padding, whatever the strategy.
+    let repr = if repr.packed {
+        RecordRepr::Packed
+    } else if repr.c {
+        RecordRepr::Padded
+    } else {
+        cx.lcx.strategy.record_repr()
     };
-    Layout::Variant(VariantLayout::Tagged(lyt))
+    Layout::Aggregate { fields, repr }
+}
+
+/// Sort fields like rustc does: zero-sized fields first, then by decreasing
+/// alignment, so that no padding is needed between them. Among fields with the
+/// same alignment, the ones with the most niche values go first, since that's
+/// where niche extraction looks first.
+fn reorder_fields(fields: &mut [(Name, Layout)]) {
+    // Fields with back-references to enclosing types can't be sized yet.
+    if fields.iter().any(|(_, l)| l.try_shape().is_none()) {
+        return;
+    }
+    fields.sort_by_cached_key(|(_, l)| {
+        (!l.is_zst(), Reverse(l.shape().align), Reverse(l.max_niche_values_count()))
+    });
+}
+
+fn layout_of_variant_type(cx: &mut Ctxt, variants: Map<Name, TyId>, repr: &Repr) -> Layout {
+    // Explicit discriminants apply to all variants, even uninhabited ones.
+    let tag_values = repr.tag_values(variants.keys());
+    // Variants whose field is uninhabited can never be constructed, so
+    // they need neither a tag value nor space.
+    let (variants, uninhabited): (Map<_, _>, Map<_, _>) = variants
+        .into_iter()
+        .partition(|&(_, t)| !cx.lcx.types.is_uninhabited_in(t, &mut cx.uninhabited));
+    if !uninhabited.is_empty() {
+        let names: Vec<_> = uninhabited.into_keys().collect();
+        cx.trace.note(|| {
+            let noun = if names.len() == 1 { "variant" } else { "variants" };
+            format!("dropped uninhabited {} {}", noun, display_names(&names))
+        });
+    }
+    match variants.len() {
+        0 => {
+            cx.trace.note(|| "no variant is inhabited, so the type is zero-sized".to_owned());
+            layout_of_empty_type()
+        }
+        _ if repr.has_direct_tag() => {
+            let tag_values = tag_values.into_iter().filter(|(n, _)| variants.contains_key(n));
+            // C puts the tag of a tagged union before the data.
+            let placement =
+                if repr.c { TagPlacement::First } else { cx.lcx.strategy.tag_placement() };
+            let (tag, reason) = match repr.tag {
+                Some(ty) => {
+                    let reason = format!("#[repr({})]", ty.suffix());
+                    (TagLayout::direct_of_ty(ty, tag_values.collect(), placement), reason)
+                }
+                // Like a C enum, which is an `int`.
+                None if repr.c => {
+                    let tag = TagLayout::direct_of_ty(IntTy::U32, tag_values.collect(), placement);
+                    (tag, "#[repr(C)]".to_owned())
+                }
+                None => {
+                    let tag = cx.lcx.strategy.direct_tag(tag_values.collect());
+                    (tag, "explicit discriminants".to_owned())
+                }
+            };
+            cx.trace.note(|| format!("used a direct tag for {}", reason));
+            let variants = variants.into_iter().map(|(n, t)| (n, layout_of_in(cx, t))).collect();
+            Layout::Variant(VariantLayout::Tagged(TaggedLayout { tag, variants }))
+        }
+        1 => {
+            let (name, field_ty) = expect_singleton_vec(variants.into_iter().collect());
+            cx.trace
+                .note(|| format!("variant {} is the only inhabited one, so it needs no tag", name));
+            layout_of_singleton_variant(cx, field_ty)
+        }
+        _ => layout_of_multi_variant_type(cx, variants),
+    }
+}
+
+/// The layout of a variant type without (inhabited) variants.
+///
+/// It has no values, so it is zero-sized.
+fn layout_of_empty_type() -> Layout {
+    let field = Layout::Aggregate { fields: vec![], repr: RecordRepr::Padded };
+    Layout::Variant(VariantLayout::Single { field: Box::new(field) })
+}
+
+fn layout_of_singleton_variant(cx: &mut Ctxt, field_ty: TyId) -> Layout {
+    let field_lyt = layout_of_in(cx, field_ty);
+    Layout::Variant(VariantLayout::Single { field: Box::new(field_lyt) })
+}
+
+fn layout_of_multi_variant_type(cx: &mut Ctxt, variants: Map<Name, TyId>) -> Layout {
+    let variants = variants.into_iter().map(|(n, t)| (n, layout_of_in(cx, t))).collect();
+    Layout::Variant(VariantLayout::Tagged(cx.lcx.strategy.layout_of_tagged(variants, cx.trace)))
 }
 
 fn layout_of_tagged_niche_type(
     mut variants: Map<Name, Layout>,
     nullary_variants: Vec<Name>,
+    policy: NichePolicy,
+    strategy: &dyn LayoutStrategy,
+    trace: &mut Trace,
 ) -> TaggedLayout {
     let needed_tag_values_count: u64 = nullary_variants.len().try_into().unwrap();
+    trace.note(|| {
+        let dataful = variants.keys().find(|&n| !nullary_variants.contains(n)).unwrap();
+        format!("variant {} is the only dataful arm", dataful)
+    });
 
     // If the niche extraction is successful, the variants will be transparent.
     let variant_repr = VariantRepr::Transparent;
     // TODO: could just extract from `field` in `Nicheable` and wrap in variant proj
-    let ExtractedNiche { path: tag_path, niche } =
-        match extract_niches_from_variants(&mut variants, needed_tag_values_count, variant_repr) {
+    let ExtractedNiche { path: tag_path, ty, niche } = match extract_niches_from_variants(
+        &mut variants,
+        needed_tag_values_count,
+        variant_repr,
+        policy,
+    ) {
+        Ok(niche) => niche,
+        // We couldn't find a niche, so we need a direct tag layout.
+        Err(()) => {
+            trace.note(|| {
+                let needed = count(needed_tag_values_count, "value");
+                format!("fell back to direct tag because no niche had {}", needed)
+            });
+            return layout_of_tagged_direct_type(variants, strategy);
+        }
+    };
+    let niche_values_count = niche.values_count().unwrap();
+    assert_eq!(needed_tag_values_count, niche_values_count);
+    trace
+        .note(|| format!("extracted {} at {}", count(niche_values_count, "niche value"), tag_path));
+
+    let tag_values = nullary_variants.into_iter().zip(niche.values()).collect();
+
+    let tag = TagLayout::Niche { path: tag_path, ty, values: tag_values };
+    TaggedLayout { tag, variants }
+}
+
+/// Store the tag in a niche of the largest dataful variant, `holder`.
+///
+/// The part of `holder`'s data that contains the niche is moved out into the
+/// tag; the other variants' data then only has to fit around it.
+fn layout_of_tagged_multi_niche_type(
+    variants: &Map<Name, Layout>,
+    dataful_variants: Vec<Name>,
+    policy: NichePolicy,
+    trace: &mut Trace,
+) -> Option<TaggedLayout> {
+    trace.note(|| format!("variants {} are dataful", display_names(&dataful_variants)));
+    // Back-references to enclosing types that aren't behind a pointer can't
+    // be sized yet, since the enclosing layout is still being computed.
+    let sizes = variants.iter().map(|(n, l)| Some((n, l.try_shape()?.size))).collect();
+    let sizes: Map<_, _> = match sizes {
+        Some(sizes) => sizes,
+        None => {
+            trace.note(|| "can't use a niche, since some variants can't be sized yet".to_owned());
+            return None;
+        }
+    };
+    // `max_by_key` returns the last maximum, but we want the first.
+    let holder = dataful_variants.into_iter().rev().max_by_key(|v| sizes[v]).unwrap();
+
+    let needed_tag_values_count: u64 = (variants.len() - 1).try_into().unwrap();
+    let mut holder_lyt = variants[&holder].clone();
+    let ExtractedNiche { path, ty, niche } =
+        match holder_lyt.extract_niche(needed_tag_values_count, policy) {
             Ok(niche) => niche,
-            // We couldn't find a niche, so we need a direct tag layout.
-            Err(()) => return layout_of_tagged_direct_type(variants),
+            Err(()) => {
+                trace.note(|| {
+                    let needed = count(needed_tag_values_count, "value");
+                    format!("no niche in the largest variant, {}, had {}", holder, needed)
+                });
+                return None;
+            }
         };
-    let niche_range = niche.as_range().unwrap();
+    trace.note(|| {
+        let extracted = count(needed_tag_values_count, "niche value");
+        format!("extracted {} at {} of the largest variant, {}", extracted, path, holder)
+    });
 
-    let niche_values_count = range_values_count(niche_range.clone()).unwrap();
-    assert_eq!(needed_tag_values_count, niche_values_count);
+    let (hoisted, field_lyt, inner_path, rest_lyt) =
+        match (path.clone().split_outermost(), holder_lyt) {
+            // Only move out the field that contains the niche.
+            (
+                Some((ValueProj::Field(name), inner_path)),
+                Layout::Aggregate { mut fields, repr },
+            ) => {
+                let idx = fields.iter().position(|(n, _)| *n == name).unwrap();
+                let (_, field_lyt) = fields.remove(idx);
+                (Some(name), field_lyt, inner_path, Layout::Aggregate { fields, repr })
+            }
+            // Move out all of the data.
+            (_, holder_lyt) => {
+                let rest = Layout::Aggregate { fields: vec![], repr: RecordRepr::Padded };
+                (None, holder_lyt, path, rest)
+            }
+        };
+    let path = inner_path.with_outer_path(TagPath::singleton(ValueProj::Tag));
 
-    let tag_values = nullary_variants.into_iter().zip(niche_range).collect();
+    let other_variants = variants.keys().filter(|&v| *v != holder).cloned();
+    let tag_values = other_variants.zip(niche.values()).collect();
 
-    let tag = TagLayout::Niche { path: tag_path, values: tag_values };
-    TaggedLayout { tag, variants }
+    let mut variants = variants.clone();
+    variants.insert(holder.clone(), rest_lyt);
+
+    let tag = TagLayout::MultiNiche {
+        holder,
+        hoisted,
+        field: Box::new(field_lyt),
+        path,
+        ty,
+        values: tag_values,
+    };
+    Some(TaggedLayout { tag, variants })
+}
+
+/// Store the tag in the spare low bits of the variants' pointers, like
+/// Haskell's and OCaml's pointer tagging.
+fn layout_of_tagged_pointer_tag_type(
+    variants: &Map<Name, Layout>,
+    trace: &mut Trace,
+) -> Option<TaggedLayout> {
+    let variant_count: u64 = variants.len().try_into().unwrap();
+    let max_tag_value = variant_count.checked_sub(1).unwrap();
+    let bits = u64::BITS - max_tag_value.leading_zeros();
+
+    let mut has_ptr = false;
+    for (name, lyt) in variants {
+        match lyt {
+            Layout::Ptr { .. } if lyt.spare_ptr_bits() >= bits => has_ptr = true,
+            _ if lyt.is_zst() => {}
+            _ => {
+                trace.note(|| {
+                    let spare = count(bits.into(), "spare bit");
+                    format!("variant {} is neither zero-sized nor a pointer with {}", name, spare)
+                });
+                return None;
+            }
+        }
+    }
+    if !has_ptr {
+        trace.note(|| "no variant is a pointer".to_owned());
+        return None;
+    }
+
+    trace.note(|| format!("stored the tag in the low {} bits of the pointers", bits));
+    let tag_values = variants.keys().cloned().zip(0..=max_tag_value).collect();
+    let tag = TagLayout::PointerTag { bits, values: tag_values };
+    Some(TaggedLayout { tag, variants: variants.clone() })
+}
+
+fn tagged_size(lyt: &TaggedLayout) -> Size {
+    Layout::Variant(VariantLayout::Tagged(lyt.clone())).shape().size
 }
 
-fn layout_of_tagged_direct_type(variants: Map<Name, Layout>) -> TaggedLayout {
+fn layout_of_tagged_direct_type(
+    variants: Map<Name, Layout>,
+    strategy: &dyn LayoutStrategy,
+) -> TaggedLayout {
     let variant_count: u64 = variants.len().try_into().unwrap();
 
     let max_tag_value = variant_count.checked_sub(1).unwrap();
     let tag_values = variants.keys().cloned().zip(0..=max_tag_value).collect();
-    let tag = TagLayout::direct(tag_values);
+    let tag = strategy.direct_tag(tag_values);
 
     TaggedLayout { tag, variants }
 }
 
 pub(crate) fn is_nicheable(variants: &Map<Name, Layout>) -> Nicheable<'_> {
-    let mut found_field = None;
+    let mut dataful_variants = vec![];
     let mut nullary_variants = vec![];
 
     for (variant_name, field) in variants {
         if field.is_zst() {
             nullary_variants.push(variant_name.clone());
         } else {
-            if found_field.is_some() {
-                // Multiple variants have fields.
-                return Nicheable::No;
-            } else {
-                found_field = Some(field);
-            }
+            dataful_variants.push((variant_name.clone(), field));
         }
     }
 
-    if let Some(field) = found_field {
-        // All variants except one have a field.
-        Nicheable::Yes { field, nullary_variants }
-    } else {
+    match dataful_variants.len() {
         // No variant has a field, so there's no niche.
-        Nicheable::No
+        0 => Nicheable::No,
+        // All variants except one have a field.
+        1 => Nicheable::Yes { field: dataful_variants[0].1, nullary_variants },
+        // Multiple variants have fields.
+        _ => Nicheable::Multi {
+            dataful_variants: dataful_variants.into_iter().map(|(n, _)| n).collect(),
+        },
     }
 }
 
 // TODO: replace with Result?
 pub(crate) enum Nicheable<'a> {
     Yes { field: &'a Layout, nullary_variants: Vec<Name> },
+    Multi { dataful_variants: Vec<Name> },
     No,
 }
 
 impl<'a> Nicheable<'a> {
-    pub(crate) fn as_field(self) -> Option<&'a Layout> {
+    pub(crate) fn into_field(self) -> Option<&'a Layout> {
         match self {
             Nicheable::Yes { field, nullary_variants: _ } => Some(field),
-            Nicheable::No => None,
+            Nicheable::Multi { .. } | Nicheable::No => None,
         }
     }
 }
@@ -0,0 +1,229 @@
+//! The choices that differ between representations, like rustc's, C's, and
+//! Swift's.
+
+use crate::int_ty::IntTy;
+use crate::layout::extract::NichePolicy;
+use crate::layout::{Layout, TagLayout, TagPlacement, TaggedLayout};
+use crate::lir::RecordRepr;
+use crate::name::Name;
+use crate::util::Map;
+
+use super::{
+    is_nicheable, layout_of_tagged_direct_type, layout_of_tagged_multi_niche_type,
+    layout_of_tagged_niche_type, layout_of_tagged_pointer_tag_type, reorder_fields, tagged_size,
+    Nicheable, Trace,
+};
+
+/// How to lay out records and variant types.
+///
+/// Everything else, like dropping uninhabited variants and resolving
+/// back-references to recursive types, is shared by all strategies.
+pub trait LayoutStrategy {
+    /// The name to select this strategy by, like `:strategy c`.
+    fn name(&self) -> &'static str;
+
+    /// Put the fields of a record, which are in order of their names, in
+    /// memory order.
+    fn order_fields(&self, fields: &mut [(Name, Layout)]);
+
+    /// How to place the fields of records that aren't `#[packed]`.
+    fn record_repr(&self) -> RecordRepr {
+        RecordRepr::Padded
+    }
+
+    /// Where to store direct tags.
+    fn tag_placement(&self) -> TagPlacement {
+        TagPlacement::After
+    }
+
+    /// A direct tag with `values`, if no attribute says what type it has.
+    fn direct_tag(&self, values: Map<Name, u64>) -> TagLayout {
+        TagLayout::direct(values, self.tag_placement())
+    }
+
+    /// Lay out a variant type with at least two (inhabited) variants, and
+    /// note the decisions in `trace`.
+    fn layout_of_tagged(&self, variants: Map<Name, Layout>, trace: &mut Trace) -> TaggedLayout;
+
+    /// Whether enclosing variant types can store their tags in the niches of
+    /// this strategy's layouts. If not, the layouts still have niches, like
+    /// the unused values of a direct tag, but no tag is ever put there.
+    fn exposes_niches(&self) -> bool {
+        true
+    }
+}
+
+/// The default strategy.
+pub const DEFAULT: &dyn LayoutStrategy =
+    &NicheFilling { reorder_fields: false, niche_policy: NichePolicy::LargestRemaining };
+
+/// All built-in strategies, starting with [`DEFAULT`].
+pub const STRATEGIES: [&dyn LayoutStrategy; 6] = [
+    DEFAULT,
+    &NicheFilling { reorder_fields: false, niche_policy: NichePolicy::CheapestCheck },
+    &NicheFilling { reorder_fields: false, niche_policy: NichePolicy::Shallowest },
+    &NicheFilling { reorder_fields: true, niche_policy: NichePolicy::LargestRemaining },
+    &CLike,
+    &SpareBits,
+];
+
+pub fn strategy_by_name(name: &str) -> Option<&'static dyn LayoutStrategy> {
+    STRATEGIES.into_iter().find(|s| s.name() == name)
+}
+
+/// Store tags in niches wherever possible, like rustc.
+///
+/// If `reorder_fields` is set, fields are sorted to minimize padding, like
+/// rustc does for the default repr; otherwise, they are laid out in order of
+/// their names. `niche_policy` chooses which niche to store a tag in.
+pub struct NicheFilling {
+    pub reorder_fields: bool,
+    pub niche_policy: NichePolicy,
+}
+
+impl LayoutStrategy for NicheFilling {
+    fn name(&self) -> &'static str {
+        match (self.reorder_fields, self.niche_policy) {
+            (false, NichePolicy::LargestRemaining) => "niche",
+            (false, NichePolicy::CheapestCheck) => "niche-cheap",
+            (false, NichePolicy::Shallowest) => "niche-shallow",
+            (true, NichePolicy::LargestRemaining) => "rust",
+            (true, NichePolicy::CheapestCheck) => "rust-cheap",
+            (true, NichePolicy::Shallowest) => "rust-shallow",
+        }
+    }
+
+    fn order_fields(&self, fields: &mut [(Name, Layout)]) {
+        if self.reorder_fields {
+            reorder_fields(fields);
+        }
+    }
+
+    fn layout_of_tagged(&self, variants: Map<Name, Layout>, trace: &mut Trace) -> TaggedLayout {
+        match is_nicheable(&variants) {
+            Nicheable::Yes { field: _, nullary_variants } => layout_of_tagged_niche_type(
+                variants,
+                nullary_variants,
+                self.niche_policy,
+                self,
+                trace,
+            ),
+            Nicheable::Multi { dataful_variants } => {
+                let multi_niche = layout_of_tagged_multi_niche_type(
+                    &variants,
+                    dataful_variants,
+                    self.niche_policy,
+                    trace,
+                );
+                let direct = layout_of_tagged_direct_type(variants, self);
+                match multi_niche {
+                    // Like rustc, only use the niche if it actually saves space.
+                    Some(multi_niche) if tagged_size(&multi_niche) < tagged_size(&direct) => {
+                        trace.note(|| {
+                            let (niche, direct) = (tagged_size(&multi_niche), tagged_size(&direct));
+                            format!(
+                                "used the niche, since it takes {} instead of {}",
+                                niche, direct
+                            )
+                        });
+                        multi_niche
+                    }
+                    Some(multi_niche) => {
+                        trace.note(|| {
+                            let size = tagged_size(&multi_niche);
+                            format!(
+                                "fell back to direct tag because the niche saves no space ({})",
+                                size
+                            )
+                        });
+                        direct
+                    }
+                    None => {
+                        trace.note(|| "fell back to direct tag".to_owned());
+                        direct
+                    }
+                }
+            }
+            Nicheable::No => {
+                trace.note(|| "used a direct tag, since no variant has data".to_owned());
+                layout_of_tagged_direct_type(variants, self)
+            }
+        }
+    }
+}
+
+/// Like a C struct holding a tag and a union of the variants: fields in order
+/// of their names, and always a direct `U32` tag, like a C enum, before the
+/// data.
+pub struct CLike;
+
+impl LayoutStrategy for CLike {
+    fn name(&self) -> &'static str {
+        "c"
+    }
+
+    fn order_fields(&self, _fields: &mut [(Name, Layout)]) {}
+
+    fn tag_placement(&self) -> TagPlacement {
+        TagPlacement::First
+    }
+
+    /// An `int`, unless explicit discriminants don't fit in one.
+    fn direct_tag(&self, values: Map<Name, u64>) -> TagLayout {
+        let ty = IntTy::fitting(values.values().copied().max().unwrap_or(0)).max(IntTy::U32);
+        TagLayout::direct_of_ty(ty, values, self.tag_placement())
+    }
+
+    fn layout_of_tagged(&self, variants: Map<Name, Layout>, trace: &mut Trace) -> TaggedLayout {
+        trace.note(|| "used a direct tag, like every variant type of this strategy".to_owned());
+        layout_of_tagged_direct_type(variants, self)
+    }
+
+    fn exposes_niches(&self) -> bool {
+        false
+    }
+}
+
+/// Like Swift: if only one variant has data, the tag is stored in its niches
+/// ("extra inhabitants"); otherwise, it is stored in the spare low bits of the
+/// variants' pointers, if they all have some, or else right after the data.
+/// Record fields also go right after the previous field's size, rather than
+/// its stride.
+pub struct SpareBits;
+
+impl LayoutStrategy for SpareBits {
+    fn name(&self) -> &'static str {
+        "swift"
+    }
+
+    fn order_fields(&self, _fields: &mut [(Name, Layout)]) {}
+
+    fn record_repr(&self) -> RecordRepr {
+        RecordRepr::Compact
+    }
+
+    fn tag_placement(&self) -> TagPlacement {
+        TagPlacement::Tail
+    }
+
+    fn layout_of_tagged(&self, variants: Map<Name, Layout>, trace: &mut Trace) -> TaggedLayout {
+        match is_nicheable(&variants) {
+            Nicheable::Yes { field: _, nullary_variants } => layout_of_tagged_niche_type(
+                variants,
+                nullary_variants,
+                NichePolicy::default(),
+                self,
+                trace,
+            ),
+            Nicheable::Multi { .. } | Nicheable::No => {
+                match layout_of_tagged_pointer_tag_type(&variants, trace) {
+                    Some(pointer_tag) => pointer_tag,
+                    None => {
+                        trace.note(|| "fell back to direct tag".to_owned());
+                        layout_of_tagged_direct_type(variants, self)
+                    }
+                }
+            }
+        }
+    }
+}
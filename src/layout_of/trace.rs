@@ -0,0 +1,83 @@
+//! A record of the decisions made while computing a layout, for `:explain`.
+
+use std::fmt::{Display, Formatter, Result};
+
+use crate::name::Name;
+
+/// The decisions made while laying out each type, nested like the types.
+///
+/// A disabled trace records nothing, and its messages are never formatted.
+pub struct Trace {
+    /// The indented lines, or `None` if the trace is disabled.
+    lines: Option<Vec<String>>,
+    /// The types being laid out, outermost first, and whether their header
+    /// has been written yet. Types without decisions get no header.
+    open: Vec<(String, bool)>,
+}
+
+impl Trace {
+    pub fn disabled() -> Self {
+        Self { lines: None, open: vec![] }
+    }
+
+    pub fn enabled() -> Self {
+        Self { lines: Some(vec![]), open: vec![] }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.lines.is_some()
+    }
+
+    /// The recorded lines, indented by two spaces per nesting level.
+    pub fn lines(&self) -> &[String] {
+        self.lines.as_deref().unwrap_or_default()
+    }
+
+    /// Start laying out a type, described by `header`.
+    pub(super) fn enter(&mut self, header: impl FnOnce() -> String) {
+        if self.is_enabled() {
+            self.open.push((header(), false));
+        }
+    }
+
+    /// Finish laying out the type of the last [`Trace::enter()`].
+    pub(super) fn exit(&mut self) {
+        if self.is_enabled() {
+            self.open.pop().unwrap();
+        }
+    }
+
+    /// Record a decision about the type that is being laid out.
+    pub(super) fn note(&mut self, message: impl FnOnce() -> String) {
+        let lines = match &mut self.lines {
+            Some(lines) => lines,
+            None => return,
+        };
+        for (depth, (header, written)) in self.open.iter_mut().enumerate() {
+            if !*written {
+                lines.push(format!("{}{}:", "  ".repeat(depth), header));
+                *written = true;
+            }
+        }
+        lines.push(format!("{}{}", "  ".repeat(self.open.len()), message()));
+    }
+}
+
+/// `count` and `noun`, pluralized as needed.
+pub(super) fn count(count: u64, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+pub(super) fn display_names(names: &[Name]) -> String {
+    names.iter().map(Name::to_string).collect::<Vec<_>>().join(", ")
+}
+
+impl Display for Trace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match &self.lines {
+            Some(lines) if !lines.is_empty() => write!(f, "{}", lines.join("\n")),
+            Some(_) => write!(f, "(no decisions)"),
+            None => write!(f, "(tracing disabled)"),
+        }
+    }
+}
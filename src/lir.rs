@@ -3,31 +3,70 @@
 mod display;
 mod size;
 
-use crate::{debruijn::Debruijn, name::Name, util::Map};
+pub use self::size::{Align, RecordRepr, Shape, ShapeError, Size};
+
+use crate::{debruijn::Debruijn, int_ty::IntTy, name::Name, util::Map};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Expr {
     Var(Var),
 
-    U64(u64),
-    Record(Map<Name, Expr>),
-    UntaggedUnion { ty: Ty, field: Name, value: Box<Expr> },
+    Int(IntTy, u64),
+    /// The fields are in memory order.
+    Record(Vec<(Name, Expr)>),
+    UntaggedUnion {
+        ty: Ty,
+        field: Name,
+        value: Box<Expr>,
+    },
 
     Box(Box<Expr>),
+    // TODO: HIR has no explicit deref yet, so lowering never produces this.
+    #[allow(dead_code)]
     Deref(Box<Expr>),
 
-    Select { record: Box<Expr>, field: Name },
-
-    Switch { subj: Var, cases: Map<u64, Expr>, default: Option<Box<Expr>> },
-
-    Let { binder: Var, value: Box<Expr>, body: Box<Expr> },
+    /// Store `tag` in the low `bits` bits of the pointer `ptr`, which must be zero.
+    TagPtr {
+        ptr: Box<Expr>,
+        bits: u32,
+        tag: u64,
+    },
+    /// Read the tag from the low `bits` bits of the pointer `ptr`, as a `U64`.
+    PtrTag {
+        ptr: Box<Expr>,
+        bits: u32,
+    },
+    /// Clear the low `bits` bits of the pointer `ptr`, so it can be dereferenced.
+    UntagPtr {
+        ptr: Box<Expr>,
+        bits: u32,
+    },
+
+    Select {
+        record: Box<Expr>,
+        field: Name,
+    },
+
+    Switch {
+        subj: Var,
+        cases: Map<u64, Expr>,
+        default: Option<Box<Expr>>,
+    },
+
+    Let {
+        binder: Var,
+        value: Box<Expr>,
+        body: Box<Expr>,
+    },
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Value {
-    U64(u64),
+    Int(IntTy, u64),
     Record(Map<Name, Value>),
     Box(Box<Value>),
+    /// A [`Value::Box`] with a nonzero tag in its low bits.
+    TaggedBox(Box<Value>, u64),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -38,10 +77,11 @@ pub struct Var {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Ty {
-    U64,
+    Int(IntTy),
     Ptr(Box<Ty>),
 
-    Record(Map<Name, Ty>),
+    /// The fields are in memory order.
+    Record(Vec<(Name, Ty)>, RecordRepr),
     UntaggedUnion(Map<Name, Ty>),
 
     Recursive(Box<Ty>),
@@ -52,9 +92,10 @@ impl Expr {
     pub fn ty(&self) -> Ty {
         match self {
             Expr::Var(var) => var.ty.clone(),
-            Expr::U64(_) => Ty::U64,
+            Expr::Int(ty, _) => Ty::Int(*ty),
             Expr::Record(fields) => {
-                Ty::Record(fields.iter().map(|(n, e)| (n.clone(), e.ty())).collect())
+                let fields = fields.iter().map(|(n, e)| (n.clone(), e.ty())).collect();
+                Ty::Record(fields, RecordRepr::Padded)
             }
             // FIXME: check types?
             Expr::UntaggedUnion { ty, field: _, value: _ } => ty.clone(),
@@ -63,8 +104,12 @@ impl Expr {
                 Ty::Ptr(val_ty) => *val_ty,
                 _ => panic!(),
             },
+            Expr::TagPtr { ptr, bits: _, tag: _ } | Expr::UntagPtr { ptr, bits: _ } => ptr.ty(),
+            Expr::PtrTag { ptr: _, bits: _ } => Ty::Int(IntTy::U64),
             Expr::Select { record, field } => match record.ty() {
-                Ty::Record(field_tys) => field_tys[field].clone(),
+                Ty::Record(field_tys, _) => {
+                    field_tys.into_iter().find(|(n, _)| n == field).unwrap().1
+                }
                 _ => panic!(),
             },
             // FIXME: check types?
@@ -89,32 +134,38 @@ impl Var {
 }
 
 impl Ty {
-    // FIXME: remove this and use Layout::is_zst instead
-    pub fn is_zst(&self) -> bool {
+    /// Unfold a closed recursive type by one level, replacing the
+    /// back-references in its body with the type itself.
+    pub fn unfold(&self) -> Option<Ty> {
         match self {
-            Ty::U64 | Ty::Ptr(_) => false,
-            Ty::Record(fields) => fields.values().all(|t| t.is_zst()),
-            Ty::UntaggedUnion(fields) => fields.values().all(|t| t.is_zst()),
-            Ty::Recursive(body) => body.is_zst(),
-            // FIXME: is this correct?
-            Ty::RecurId(_) => false,
+            Ty::Recursive(body) => Some(body.subst(Debruijn::ZERO, self)),
+            _ => None,
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_is_zst() {
-        assert!(Ty::Record(map! {}).is_zst());
-        assert!(Ty::Record(map! { "x" => Ty::Record(map!{}), "y" => Ty::Record(map!{}) }).is_zst());
-        assert!(Ty::UntaggedUnion(map! {}).is_zst());
-        assert!(Ty::UntaggedUnion(map! { "x" => Ty::Record(map!{}), "y" => Ty::Record(map!{}) })
-            .is_zst());
+    /// Unfold a closed recursive type until it isn't one.
+    pub fn unfolded(&self) -> Ty {
+        match self.unfold() {
+            Some(body) => body.unfolded(),
+            None => self.clone(),
+        }
+    }
 
-        assert!(!Ty::U64.is_zst());
-        assert!(!Ty::UntaggedUnion(map! { "x" => Ty::Record(map! { "x" => Ty::U64 }) }).is_zst())
+    /// Replace the back-references to `id` with `ty`, which is closed.
+    fn subst(&self, id: Debruijn, ty: &Ty) -> Ty {
+        let fields = |fields: &[(Name, Ty)]| {
+            fields.iter().map(|(n, t)| (n.clone(), t.subst(id, ty))).collect()
+        };
+        match self {
+            Ty::Int(_) => self.clone(),
+            Ty::Ptr(pointee) => Ty::Ptr(Box::new(pointee.subst(id, ty))),
+            Ty::Record(fs, repr) => Ty::Record(fields(fs), *repr),
+            Ty::UntaggedUnion(members) => Ty::UntaggedUnion(
+                members.iter().map(|(n, t)| (n.clone(), t.subst(id, ty))).collect(),
+            ),
+            Ty::Recursive(body) => Ty::Recursive(Box::new(body.subst(id.shift_by(1), ty))),
+            Ty::RecurId(other) if *other == id => ty.clone(),
+            Ty::RecurId(_) => self.clone(),
+        }
     }
 }
@@ -11,9 +11,9 @@ impl Display for Expr {
         match self {
             Expr::Var(v) => v.fmt(f),
 
-            Expr::U64(n) => write!(f, "{}_u64", n),
+            Expr::Int(ty, n) => write!(f, "{}_{}", n, ty.suffix()),
             Expr::Record(fields) => {
-                write!(f, "{}", display_map_like(fields.iter(), " = ", ", "))
+                write!(f, "{}", display_map_like(fields.iter().map(|(n, e)| (n, e)), " = ", ", "))
             }
             Expr::UntaggedUnion { ty, field, value } => {
                 write!(f, "(<{} = {}> as {})", field, value, ty)
@@ -21,6 +21,11 @@ impl Display for Expr {
 
             Expr::Box(val) => write!(f, "Box({})", val),
             Expr::Deref(ptr) => write!(f, "Deref({})", ptr),
+            Expr::TagPtr { ptr, bits, tag } => {
+                write!(f, "TagPtr({}, bits: {}, tag: {})", ptr, bits, tag)
+            }
+            Expr::PtrTag { ptr, bits } => write!(f, "PtrTag({}, bits: {})", ptr, bits),
+            Expr::UntagPtr { ptr, bits } => write!(f, "UntagPtr({}, bits: {})", ptr, bits),
 
             Expr::Select { record, field } => write!(f, "({}).{}", record, field),
             Expr::Switch { subj, cases, default } => write!(
@@ -47,9 +52,10 @@ impl Display for Expr {
 impl Display for Value {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         match self {
-            Value::U64(n) => write!(f, "{}_u64", n),
+            Value::Int(ty, n) => write!(f, "{}_{}", n, ty.suffix()),
             Value::Record(fields) => write!(f, "{}", display_map_like(fields.iter(), " = ", ", ")),
             Value::Box(val) => write!(f, "Box({})", val),
+            Value::TaggedBox(val, tag) => write!(f, "(Box({}) | {})", val, tag),
         }
     }
 }
@@ -68,9 +74,14 @@ impl Display for Var {
 impl Display for Ty {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         match self {
-            Ty::U64 => write!(f, "U64"),
+            Ty::Int(ty) => write!(f, "{}", ty),
             Ty::Ptr(pointee) => write!(f, "Ptr({})", pointee),
-            Ty::Record(fields) => write!(f, "{}", display_map_like(fields.iter(), " : ", ", ")),
+            Ty::Record(fields, repr) => {
+                if *repr != RecordRepr::Padded {
+                    write!(f, "{} ", repr.descr())?;
+                }
+                write!(f, "{}", display_map_like(fields.iter().map(|(n, t)| (n, t)), " : ", ", "))
+            }
             Ty::UntaggedUnion(fields) => {
                 write!(f, "union {}", display_map_like(fields.iter(), " : ", " | "))
             }
@@ -1,3 +1,5 @@
+use crate::debruijn::Debruijn;
+
 use super::Ty;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -17,6 +19,16 @@ impl Size {
     pub fn bytes(self) -> u64 {
         self.bytes
     }
+
+    /// Round `self` up to the next multiple of `align`.
+    pub fn align_to(self, align: Align) -> Self {
+        let mask = align.bytes() - 1;
+        Self::from_bytes(self.bytes().checked_add(mask).unwrap() & !mask)
+    }
+
+    pub fn checked_add(self, other: Size) -> Option<Self> {
+        self.bytes.checked_add(other.bytes).map(Self::from_bytes)
+    }
 }
 
 impl std::fmt::Display for Size {
@@ -26,24 +38,325 @@ impl std::fmt::Display for Size {
     }
 }
 
+/// An alignment, in bytes. Always a power of two.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Align {
+    bytes: u64,
+}
+
+impl Align {
+    pub const ONE: Self = Self::from_bytes(1);
+
+    pub const BITS_64: Self = Self::from_bytes(8);
+
+    pub const fn from_bytes(bytes: u64) -> Self {
+        assert!(bytes.is_power_of_two());
+        Self { bytes }
+    }
+
+    pub fn bytes(self) -> u64 {
+        self.bytes
+    }
+}
+
+impl std::fmt::Display for Align {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Size::from_bytes(self.bytes).fmt(f)
+    }
+}
+
+/// How the fields of a record are placed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RecordRepr {
+    /// Like in C and Rust: each field is placed after the previous field's
+    /// stride, and the size includes trailing padding.
+    Padded,
+    /// Like in Swift: each field is placed right after the previous field's
+    /// size, so it can go in its trailing padding, and the size doesn't
+    /// include trailing padding.
+    Compact,
+    /// Without any padding, and with an alignment of one.
+    Packed,
+}
+
+impl RecordRepr {
+    pub fn descr(self) -> &'static str {
+        match self {
+            RecordRepr::Padded => "padded",
+            RecordRepr::Compact => "compact",
+            RecordRepr::Packed => "packed",
+        }
+    }
+}
+
+/// The size and alignment of a type.
+///
+/// The size only includes trailing padding if the type says so, like a
+/// [`RecordRepr::Padded`] record; see [`Shape::stride()`] for the size with it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Shape {
+    pub size: Size,
+    pub align: Align,
+}
+
+impl Shape {
+    pub const ZST: Self = Self { size: Size::ZERO, align: Align::ONE };
+
+    pub const BITS_64: Self = Self { size: Size::BITS_64, align: Align::BITS_64 };
+
+    /// The distance between consecutive elements in an array: the size
+    /// rounded up to the alignment.
+    pub fn stride(self) -> Size {
+        self.size.align_to(self.align)
+    }
+
+    /// Lay out `fields` one after another, in order, as `repr` says.
+    ///
+    /// Returns the offset of each field and the shape of the whole record.
+    pub fn record(fields: impl IntoIterator<Item = Shape>, repr: RecordRepr) -> (Vec<Size>, Shape) {
+        let fields = fields.into_iter();
+        match repr {
+            RecordRepr::Padded => {
+                let (offsets, whole) = Shape::compact_record(
+                    fields.map(|field| Shape { size: field.stride(), ..field }),
+                );
+                (offsets, Shape { size: whole.stride(), ..whole })
+            }
+            RecordRepr::Compact => Shape::compact_record(fields),
+            RecordRepr::Packed => {
+                let (offsets, whole) =
+                    Shape::compact_record(fields.map(|field| Shape { align: Align::ONE, ..field }));
+                (offsets, Shape { align: Align::ONE, ..whole })
+            }
+        }
+    }
+
+    fn compact_record(fields: impl IntoIterator<Item = Shape>) -> (Vec<Size>, Shape) {
+        let mut offsets = vec![];
+        let mut whole = Shape::ZST;
+        for field in fields {
+            let offset = whole.size.align_to(field.align);
+            offsets.push(offset);
+            whole.size = offset.checked_add(field.size).unwrap();
+            whole.align = whole.align.max(field.align);
+        }
+        (offsets, whole)
+    }
+
+    /// Lay out `fields` on top of each other, all at offset zero.
+    pub fn union(fields: impl IntoIterator<Item = Shape>) -> Shape {
+        fields.into_iter().fold(Shape::ZST, |whole, field| Shape {
+            size: whole.size.max(field.size),
+            align: whole.align.max(field.align),
+        })
+    }
+}
+
+impl std::fmt::Display for Shape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "size: {}, align: {}, stride: {}", self.size, self.align, self.stride())
+    }
+}
+
+/// Why a type has no shape.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShapeError {
+    /// A recursive type contains itself directly, rather than behind a
+    /// pointer, so it would have to be infinitely large.
+    Infinite,
+    /// The type refers to an enclosing recursive type that isn't part of it.
+    UnboundRecurId(Debruijn),
+}
+
+impl std::fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShapeError::Infinite => write!(f, "infinite recursive type; insert a pointer"),
+            ShapeError::UnboundRecurId(id) => {
+                write!(f, "type refers to an enclosing recursive type {}", id)
+            }
+        }
+    }
+}
+
 impl Ty {
-    /// The "packed" size of a type---i.e., the size of the actual data, ignoring padding.
+    /// The size and alignment of a type, including padding between fields.
     ///
-    /// This should correspond at least roughly to the Swift notion of size (not stride).
+    /// Record fields are laid out in order, as their [`RecordRepr`] says. Panics if the type is infinite or
+    /// not closed; see [`Ty::try_shape()`].
+    #[track_caller]
+    pub fn shape(&self) -> Shape {
+        match self.try_shape() {
+            Ok(shape) => shape,
+            Err(err) => panic!("type error: {}", err),
+        }
+    }
+
+    pub fn try_shape(&self) -> Result<Shape, ShapeError> {
+        self.shape_in(0, &[])
+    }
+
+    /// Like [`Ty::try_shape()`], for a type inside of recursive types whose
+    /// shapes are `outer`, innermost last. Back-references to them have their
+    /// shape.
+    pub fn try_shape_inside(&self, outer: &[Shape]) -> Result<Shape, ShapeError> {
+        self.shape_in(0, outer)
+    }
+
+    /// The size of the type if none of its records had padding, like
+    /// [`Ty::shape()`] with every record packed. Pointees don't count.
     pub fn packed_size(&self) -> Size {
+        self.packed_shape().size
+    }
+
+    fn packed_shape(&self) -> Shape {
+        match self {
+            Ty::Int(ty) => ty.shape(),
+            Ty::Ptr(_) => Shape::BITS_64,
+            Ty::Record(fields, _) => {
+                Shape::record(fields.iter().map(|(_, t)| t.packed_shape()), RecordRepr::Packed).1
+            }
+            Ty::UntaggedUnion(variants) => Shape::union(variants.values().map(Ty::packed_shape)),
+            Ty::Recursive(body) => body.packed_shape(),
+            // Back-references that aren't behind a pointer make the type
+            // infinite.
+            Ty::RecurId(id) => panic!("type error: infinite type at {}", id),
+        }
+    }
+
+    /// `depth` is the number of enclosing [`Ty::Recursive`]s that were
+    /// entered without going through a pointer.
+    fn shape_in(&self, depth: u64, outer: &[Shape]) -> Result<Shape, ShapeError> {
         match self {
-            Ty::U64 => Size::BITS_64,
-            Ty::Ptr(_) => Size::BITS_64,
-            Ty::Record(fields) => {
-                let sizes = fields.values().map(|t| t.packed_size().bytes());
-                Size::from_bytes(sizes.sum())
+            Ty::Int(ty) => Ok(ty.shape()),
+            // The pointee's size doesn't matter, so this is where recursion
+            // has to go through.
+            Ty::Ptr(_) => Ok(Shape::BITS_64),
+            Ty::Record(fields, repr) => {
+                let shapes = fields
+                    .iter()
+                    .map(|(_, t)| t.shape_in(depth, outer))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Shape::record(shapes, *repr).1)
             }
             Ty::UntaggedUnion(variants) => {
-                let sizes = variants.values().map(|t| t.packed_size().bytes());
-                sizes.max().map(Size::from_bytes).unwrap_or(Size::ZERO)
+                let shapes = variants.values().map(|t| t.shape_in(depth, outer)).collect::<Result<
+                    Vec<_>,
+                    _,
+                >>(
+                )?;
+                Ok(Shape::union(shapes))
             }
-            Ty::Recursive(body) => body.packed_size(),
-            Ty::RecurId(_) => todo!(),
+            Ty::Recursive(body) => body.shape_in(depth + 1, outer),
+            // Reaching a back-reference to an enclosing type means that the
+            // type contains itself.
+            Ty::RecurId(id) if id.index() < depth => Err(ShapeError::Infinite),
+            Ty::RecurId(id) => match outer.len().checked_sub((id.index() - depth) as usize + 1) {
+                Some(idx) => Ok(outer[idx]),
+                None => Err(ShapeError::UnboundRecurId(*id)),
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(size: u64, align: u64) -> Shape {
+        Shape { size: Size::from_bytes(size), align: Align::from_bytes(align) }
+    }
+
+    fn record(fields: Vec<(crate::name::Name, Ty)>) -> Ty {
+        Ty::Record(fields, RecordRepr::Padded)
+    }
+
+    #[test]
+    fn test_align_to() {
+        assert_eq!(Size::from_bytes(0).align_to(Align::BITS_64), Size::from_bytes(0));
+        assert_eq!(Size::from_bytes(1).align_to(Align::BITS_64), Size::from_bytes(8));
+        assert_eq!(Size::from_bytes(8).align_to(Align::BITS_64), Size::from_bytes(8));
+        assert_eq!(Size::from_bytes(9).align_to(Align::from_bytes(4)), Size::from_bytes(12));
+        assert_eq!(Size::from_bytes(9).align_to(Align::ONE), Size::from_bytes(9));
+    }
+
+    #[test]
+    fn test_record() {
+        let offsets_and_shape = |fields: &[Shape], repr| {
+            let (offsets, whole) = Shape::record(fields.iter().copied(), repr);
+            (offsets.into_iter().map(Size::bytes).collect::<Vec<_>>(), whole)
+        };
+        for repr in [RecordRepr::Padded, RecordRepr::Compact, RecordRepr::Packed] {
+            assert_eq!(Shape::record([], repr), (vec![], Shape::ZST));
+        }
+
+        let fields = [shape(1, 1), shape(8, 8), shape(2, 2)];
+        assert_eq!(offsets_and_shape(&fields, RecordRepr::Padded), (vec![0, 8, 16], shape(24, 8)));
+        assert_eq!(offsets_and_shape(&fields, RecordRepr::Compact), (vec![0, 8, 16], shape(18, 8)));
+        assert_eq!(offsets_and_shape(&fields, RecordRepr::Packed), (vec![0, 1, 9], shape(11, 1)));
+
+        // Compact records place fields right after the previous field's size,
+        // padded ones after its stride.
+        let fields = [shape(9, 8), shape(1, 1)];
+        assert_eq!(offsets_and_shape(&fields, RecordRepr::Padded), (vec![0, 16], shape(24, 8)));
+        assert_eq!(offsets_and_shape(&fields, RecordRepr::Compact), (vec![0, 9], shape(10, 8)));
+    }
+
+    #[test]
+    fn test_union() {
+        assert_eq!(Shape::union([]), Shape::ZST);
+        assert_eq!(Shape::union([shape(3, 1), shape(2, 2)]), shape(3, 2));
+    }
+
+    #[test]
+    fn test_packed_size() {
+        use crate::int_ty::IntTy;
+
+        let u8_ty = || Ty::Int(IntTy::U8);
+        let u64_ty = || Ty::Int(IntTy::U64);
+        let pair = record(vec![("a".into(), u8_ty()), ("b".into(), u64_ty())]);
+        assert_eq!(pair.shape().size, Size::from_bytes(16));
+        assert_eq!(pair.packed_size(), Size::from_bytes(9));
+        let union = Ty::UntaggedUnion(map! { "x" => pair, "y" => u8_ty() });
+        let outer = record(vec![("data".into(), union), ("tag".into(), u8_ty())]);
+        assert_eq!(outer.shape().size, Size::from_bytes(24));
+        assert_eq!(outer.packed_size(), Size::from_bytes(10));
+        // Pointees don't count.
+        let list = Ty::Recursive(Box::new(record(vec![
+            ("hd".into(), u8_ty()),
+            ("tl".into(), Ty::Ptr(Box::new(Ty::RecurId(Debruijn::ZERO)))),
+        ])));
+        assert_eq!(list.packed_size(), Size::from_bytes(9));
+    }
+
+    #[test]
+    fn test_recursive_shape() {
+        use crate::int_ty::IntTy;
+
+        let rec = |t| Ty::Recursive(Box::new(t));
+        let ptr = |t| Ty::Ptr(Box::new(t));
+        let id = |k| Ty::RecurId(Debruijn::new(k));
+        let u64_ty = || Ty::Int(IntTy::U64);
+
+        let list = rec(record(vec![("hd".into(), u64_ty()), ("tl".into(), ptr(id(0)))]));
+        assert_eq!(list.try_shape(), Ok(shape(16, 8)));
+
+        // The inner type refers to the outer one from behind the outer pointer.
+        let inner = rec(record(vec![("hd".into(), id(1)), ("tl".into(), ptr(id(0)))]));
+        let outer = rec(ptr(record(vec![("v".into(), u64_ty()), ("kids".into(), inner)])));
+        assert_eq!(outer.try_shape(), Ok(shape(8, 8)));
+
+        let infinite = rec(record(vec![("hd".into(), u64_ty()), ("tl".into(), id(0))]));
+        assert_eq!(infinite.try_shape(), Err(ShapeError::Infinite));
+        let infinite = rec(Ty::UntaggedUnion(map! { "a" => record(vec![]), "b" => id(0) }));
+        assert_eq!(infinite.try_shape(), Err(ShapeError::Infinite));
+
+        assert_eq!(id(0).try_shape(), Err(ShapeError::UnboundRecurId(Debruijn::ZERO)));
+        // Back-references to the recursive types outside take their shape.
+        let field = record(vec![("hd".into(), u64_ty()), ("tl".into(), id(0))]);
+        assert_eq!(field.try_shape_inside(&[shape(8, 8)]), Ok(shape(16, 8)));
+        let outer = [shape(2, 2), shape(8, 8)];
+        assert_eq!(rec(id(1)).try_shape_inside(&outer), Ok(shape(8, 8)));
+        assert_eq!(rec(id(2)).try_shape_inside(&outer), Ok(shape(2, 2)));
+    }
+}
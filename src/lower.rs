@@ -1,22 +1,33 @@
 use std::collections::BTreeMap;
 
-use crate::hir;
+use crate::hir::{self, TyId, TyKind};
+use crate::int_ty::IntTy;
 use crate::layout::{
-    Layout, TagLayout, TagPath, TaggedLayout, ValueProj, VariantLayout, VariantRepr,
+    Layout, TagLayout, TagPath, TagPlacement, TaggedLayout, ValueProj, VariantLayout, VariantRepr,
 };
-use crate::layout_of::{is_nicheable, layout_of};
+#[cfg(test)]
+use crate::layout_of::LayoutStrategy;
+use crate::layout_of::{is_nicheable, LayoutCx};
 use crate::lir;
 use crate::name::Name;
 use crate::util::expect_singleton_vec;
 
-#[derive(Debug)]
-pub struct Ctxt {
+pub struct Ctxt<'a, 's> {
     next_temp_var: u64,
+    layouts: &'a mut LayoutCx<'s>,
 }
 
-impl Ctxt {
-    pub fn new() -> Self {
-        Self { next_temp_var: 0 }
+impl<'a, 's> Ctxt<'a, 's> {
+    pub fn new(layouts: &'a mut LayoutCx<'s>) -> Self {
+        Self { next_temp_var: 0, layouts }
+    }
+
+    fn layout_of(&mut self, ty: &hir::Ty) -> Layout {
+        self.layouts.layout_of(ty)
+    }
+
+    fn layout_of_id(&mut self, id: TyId) -> Layout {
+        self.layouts.layout_of_id(id)
     }
 
     fn temp_var(&mut self, ty: lir::Ty) -> lir::Var {
@@ -26,32 +37,70 @@ impl Ctxt {
     }
 }
 
+/// Uses the default layout strategy.
+#[cfg(test)]
 pub fn lower_root_expr(expr: hir::Expr) -> lir::Expr {
-    lower_expr(&mut Ctxt::new(), expr)
+    lower_root_expr_with(expr, crate::layout_of::DEFAULT)
+}
+
+#[cfg(test)]
+pub fn lower_root_expr_with(expr: hir::Expr, strategy: &dyn LayoutStrategy) -> lir::Expr {
+    lower_root_expr_in(expr, &mut LayoutCx::new(strategy))
 }
 
-fn lower_expr(cx: &mut Ctxt, expr: hir::Expr) -> lir::Expr {
-    let hir_ty = expr.ty();
-    let layout = layout_of(hir_ty);
+/// Lowers `expr`, reusing and adding to the layouts in `layouts`.
+pub fn lower_root_expr_in(expr: hir::Expr, layouts: &mut LayoutCx) -> lir::Expr {
+    let (expr, _) = lower_expr(&mut Ctxt::new(layouts), expr);
+    expr
+}
 
+/// Lowers `expr`, and interns its type along the way. The types of records
+/// are made of the types of their fields, so they don't have to be computed
+/// and interned again for every enclosing record.
+fn lower_expr(cx: &mut Ctxt, expr: hir::Expr) -> (lir::Expr, TyId) {
     match expr {
-        hir::Expr::Var(var) => lir::Expr::Var(lower_var(var)),
-        hir::Expr::U64(u) => lir::Expr::U64(u),
-        hir::Expr::Box(boxed) => lir::Expr::Box(Box::new(lower_expr(cx, *boxed))),
+        hir::Expr::Var(var) => {
+            let (var, ty) = lower_var_and_ty(cx, var);
+            (lir::Expr::Var(var), ty)
+        }
+        hir::Expr::Int(ty, n) => (lir::Expr::Int(ty, n), cx.layouts.intern_kind(TyKind::Int(ty))),
+        hir::Expr::Box(boxed) => {
+            let (boxed, ty) = lower_expr(cx, *boxed);
+            (lir::Expr::Box(Box::new(boxed)), cx.layouts.intern_kind(TyKind::Box(ty)))
+        }
         hir::Expr::Record(fields) => {
-            lir::Expr::Record(fields.into_iter().map(|(n, e)| (n, lower_expr(cx, e))).collect())
+            let mut lowered: BTreeMap<_, _> =
+                fields.into_iter().map(|(n, e)| (n, lower_expr(cx, e))).collect();
+            let field_tys = lowered.iter().map(|(n, &(_, ty))| (n.clone(), ty)).collect();
+            let ty = cx.layouts.intern_kind(TyKind::Record(field_tys));
+            let fields = aggregate_field_names(&cx.layout_of_id(ty))
+                .into_iter()
+                .map(|n| {
+                    let (e, _) = lowered.remove(&n).unwrap();
+                    (n, e)
+                })
+                .collect();
+            (lir::Expr::Record(fields), ty)
         }
-        hir::Expr::Variant { ty: _, variant, field } => {
-            let variant_layout = layout.expect_variant();
-            lower_variant_expr(cx, variant_layout, variant, *field)
+        hir::Expr::Variant { ty, variant, field } => {
+            let ty = cx.layouts.intern(&ty);
+            let variant_layout = cx.layout_of_id(ty).expect_variant();
+            (lower_variant_expr(cx, variant_layout, variant, *field), ty)
+        }
+        hir::Expr::Fold { ty, value } => {
+            let (value, _) = lower_expr(cx, *value);
+            (value, cx.layouts.intern(&ty))
+        }
+        hir::Expr::Unfold { ty, value } => {
+            let (value, _) = lower_expr(cx, *value);
+            (value, cx.layouts.intern(&ty.unfold().unwrap()))
+        }
+        hir::Expr::Let { binder, value, body } => {
+            let binder = lower_var(cx, binder);
+            let (value, _) = lower_expr(cx, *value);
+            let (body, ty) = lower_expr(cx, *body);
+            (lir::Expr::Let { binder, value: Box::new(value), body: Box::new(body) }, ty)
         }
-        hir::Expr::Fold { ty: _, value } => lower_expr(cx, *value),
-        hir::Expr::Unfold { ty: _, value } => lower_expr(cx, *value),
-        hir::Expr::Let { binder, value, body } => lir::Expr::Let {
-            binder: lower_var(binder),
-            value: Box::new(lower_expr(cx, *value)),
-            body: Box::new(lower_expr(cx, *body)),
-        },
         hir::Expr::Match { subj, cases } => lower_match(cx, *subj, cases),
     }
 }
@@ -62,48 +111,165 @@ fn lower_variant_expr(
     variant: Name,
     field: hir::Expr,
 ) -> lir::Expr {
-    let field = lower_expr(cx, field);
+    let (field, _) = lower_expr(cx, field);
     let field_ty = field.ty();
     match layout {
         VariantLayout::Single { field: _ } => field,
         VariantLayout::Tagged(TaggedLayout { tag: tag_lyt, variants: variants_lyt }) => {
             match tag_lyt {
-                TagLayout::Direct { values: tag_vals, niches: _ } => {
-                    let tag_expr = lir::Expr::U64(tag_vals[&variant]);
+                TagLayout::Direct { ty, values: tag_vals, niches: _, placement } => {
+                    let tag_expr = lir::Expr::Int(ty, tag_vals[&variant]);
                     let union_ty = lir::Ty::UntaggedUnion(
                         variants_lyt.into_iter().map(|(n, l)| (n, lower_layout(l))).collect(),
                     );
                     let union_expr = lir::Expr::UntaggedUnion {
                         ty: union_ty,
-                        field: variant.clone(),
+                        field: variant,
                         value: Box::new(field),
                     };
-                    lir::Expr::Record(map! { "tag" => tag_expr, "data" => union_expr })
+                    let fields = data_and_tag(union_expr, tag_expr, placement);
+                    lir::Expr::Record(fields)
                 }
-                TagLayout::Niche { path, values } => {
-                    if field_ty.is_zst() {
-                        construct_niche_nullary_variant(path, values[&variant])
+                TagLayout::Niche { path, ty, values } => {
+                    if variants_lyt[&variant].is_zst() {
+                        construct_niche_nullary_variant(path, ty, values[&variant])
                     } else {
                         field
                     }
                 }
+                TagLayout::MultiNiche { holder, hoisted, field: _, path, ty, values } => {
+                    let rest_fields = aggregate_field_names(&variants_lyt[&holder]);
+                    let union_ty = lir::Ty::UntaggedUnion(
+                        variants_lyt.into_iter().map(|(n, l)| (n, lower_layout(l))).collect(),
+                    );
+                    if variant == holder {
+                        let data = cx.temp_var(field_ty);
+                        let (tag_expr, rest_expr) = split_multi_niche_holder(
+                            lir::Expr::Var(data.clone()),
+                            hoisted,
+                            rest_fields,
+                        );
+                        let union_expr = lir::Expr::UntaggedUnion {
+                            ty: union_ty,
+                            field: variant,
+                            value: Box::new(rest_expr),
+                        };
+                        lir::Expr::Let {
+                            binder: data,
+                            value: Box::new(field),
+                            body: Box::new(lir::Expr::Record(vec![
+                                ("data".into(), union_expr),
+                                ("tag".into(), tag_expr),
+                            ])),
+                        }
+                    } else {
+                        // Strip the leading `{tag}` projection, since we're building the tag itself.
+                        let (_, tag_path) = path.split_outermost().unwrap();
+                        let tag_expr =
+                            construct_niche_nullary_variant(tag_path, ty, values[&variant]);
+                        let union_expr = lir::Expr::UntaggedUnion {
+                            ty: union_ty,
+                            field: variant,
+                            value: Box::new(field),
+                        };
+                        lir::Expr::Record(vec![
+                            ("data".into(), union_expr),
+                            ("tag".into(), tag_expr),
+                        ])
+                    }
+                }
+                TagLayout::PointerTag { bits, values } => {
+                    // Zero-sized variants are stored as a tagged null pointer.
+                    let zst = variants_lyt[&variant].is_zst();
+                    let ptr = if zst { lir::Expr::Int(IntTy::U64, 0) } else { field };
+                    lir::Expr::TagPtr { ptr: Box::new(ptr), bits, tag: values[&variant] }
+                }
             }
         }
     }
 }
 
+/// The fields of the record that stores a direct tag next to the variants'
+/// data, in memory order.
+// FIXME: these should be represented differently from user-written fields.
+// Otherwise, there could be name conflicts in some situations.
+fn data_and_tag<T>(data: T, tag: T, placement: TagPlacement) -> Vec<(Name, T)> {
+    match placement {
+        TagPlacement::After | TagPlacement::Tail => {
+            vec![("data".into(), data), ("tag".into(), tag)]
+        }
+        TagPlacement::First => vec![("tag".into(), tag), ("data".into(), data)],
+    }
+}
+
+/// Split the data of a [`TagLayout::MultiNiche`]'s holder variant into the
+/// part that is stored in the tag and the rest.
+fn split_multi_niche_holder(
+    value: lir::Expr,
+    hoisted: Option<Name>,
+    rest_fields: Vec<Name>,
+) -> (lir::Expr, lir::Expr) {
+    match hoisted {
+        Some(hoisted) => {
+            let rest = rest_fields
+                .into_iter()
+                .map(|n| {
+                    (n.clone(), lir::Expr::Select { record: Box::new(value.clone()), field: n })
+                })
+                .collect();
+            let tag = lir::Expr::Select { record: Box::new(value), field: hoisted };
+            (tag, lir::Expr::Record(rest))
+        }
+        None => (value, lir::Expr::Record(vec![])),
+    }
+}
+
+/// The inverse of [`split_multi_niche_holder`].
+///
+/// `holder_fields` are the fields of the holder's data, including `hoisted`.
+fn join_multi_niche_holder(
+    tag: lir::Expr,
+    rest: lir::Expr,
+    hoisted: Option<Name>,
+    holder_fields: Vec<Name>,
+) -> lir::Expr {
+    match hoisted {
+        Some(hoisted) => lir::Expr::Record(
+            holder_fields
+                .into_iter()
+                .map(|n| {
+                    let value = if n == hoisted {
+                        tag.clone()
+                    } else {
+                        lir::Expr::Select { record: Box::new(rest.clone()), field: n.clone() }
+                    };
+                    (n, value)
+                })
+                .collect(),
+        ),
+        None => tag,
+    }
+}
+
+fn aggregate_field_names(layout: &Layout) -> Vec<Name> {
+    match layout {
+        Layout::Aggregate { fields, repr: _ } => fields.iter().map(|(n, _)| n.clone()).collect(),
+        _ => panic!("expected aggregate layout"),
+    }
+}
+
 /// This is like a "reverse projection".
-fn construct_niche_nullary_variant(path: TagPath, tag_value: u64) -> lir::Expr {
-    path.rfold(lir::Expr::U64(tag_value), |prev_expr, proj| {
+fn construct_niche_nullary_variant(path: TagPath, ty: IntTy, tag_value: u64) -> lir::Expr {
+    path.rfold(lir::Expr::Int(ty, tag_value), |prev_expr, proj| {
         match proj {
             // FIXME: what about the other fields of the record's type?
-            ValueProj::Field(name) => lir::Expr::Record(map! { name => prev_expr }),
+            ValueProj::Field(name) => lir::Expr::Record(vec![(name, prev_expr)]),
             ValueProj::Variant { repr, name: _ } => match repr {
                 // FIXME: is this correct? is it even reachable?
-                VariantRepr::Wrapper => lir::Expr::Record(map! { "data" => prev_expr }),
+                VariantRepr::Wrapper => lir::Expr::Record(vec![("data".into(), prev_expr)]),
                 VariantRepr::Transparent => prev_expr,
             },
-            ValueProj::Tag => lir::Expr::Record(map! { "tag" => prev_expr }),
+            ValueProj::Tag => lir::Expr::Record(vec![("tag".into(), prev_expr)]),
         }
     })
 }
@@ -125,23 +291,28 @@ fn select_value_at_path(root_value: lir::Expr, path: TagPath) -> lir::Expr {
 fn lower_match(
     cx: &mut Ctxt,
     hir_subj: hir::Expr,
-    hir_cases: Vec<(hir::Pat, hir::Expr)>,
-) -> lir::Expr {
-    let subj_hir_ty = hir_subj.ty();
-    let subj_layout = layout_of(subj_hir_ty);
+    mut hir_cases: Vec<(hir::Pat, hir::Expr)>,
+) -> (lir::Expr, TyId) {
+    // Arms for variants with uninhabited fields can never be taken, and their
+    // variants don't have a tag value.
+    hir_cases.retain(|(pat, _)| match pat {
+        hir::Pat::Variant { ty: _, variant: _, field } => !cx.layouts.is_uninhabited(&field.ty),
+    });
 
-    let lir_subj_expr = lower_expr(cx, hir_subj);
+    let (lir_subj_expr, subj_ty) = lower_expr(cx, hir_subj);
+    let subj_layout = cx.layout_of_id(subj_ty);
     let lir_subj = cx.temp_var(lir_subj_expr.ty());
 
-    let match_lir = match subj_layout {
-        Layout::U64(..) => todo!(),
-        Layout::Aggregate { fields: _ } => todo!(),
+    let (match_lir, ty) = match subj_layout {
+        Layout::Int(..) => todo!(),
+        Layout::Aggregate { .. } => todo!(),
         Layout::Ptr { .. } => panic!(),
-        Layout::Recursive(_) | Layout::RecurId(_) => panic!(),
+        Layout::Recursive(_) | Layout::RecurId { .. } => panic!(),
         Layout::Variant(layout) => lower_variant_match(cx, layout, lir_subj.clone(), hir_cases),
     };
 
-    lir::Expr::Let { binder: lir_subj, value: Box::new(lir_subj_expr), body: Box::new(match_lir) }
+    let value = Box::new(lir_subj_expr);
+    (lir::Expr::Let { binder: lir_subj, value, body: Box::new(match_lir) }, ty)
 }
 
 fn lower_variant_match(
@@ -149,7 +320,7 @@ fn lower_variant_match(
     layout: VariantLayout,
     lir_subj: lir::Var,
     hir_cases: Vec<(hir::Pat, hir::Expr)>,
-) -> lir::Expr {
+) -> (lir::Expr, TyId) {
     match layout {
         VariantLayout::Single { field: _ } => lower_single_variant_match(cx, lir_subj, hir_cases),
         VariantLayout::Tagged(lyt) => lower_tagged_variant_match(cx, lyt, lir_subj, hir_cases),
@@ -160,7 +331,7 @@ fn lower_single_variant_match(
     cx: &mut Ctxt,
     lir_subj: lir::Var,
     hir_cases: Vec<(hir::Pat, hir::Expr)>,
-) -> lir::Expr {
+) -> (lir::Expr, TyId) {
     let (pat, body) = expect_singleton_vec(hir_cases);
     match pat {
         hir::Pat::Variant { ty: _, variant: _, field } => {
@@ -174,39 +345,44 @@ fn lower_tagged_variant_match(
     lyt: TaggedLayout,
     lir_subj: lir::Var,
     hir_cases: Vec<(hir::Pat, hir::Expr)>,
-) -> lir::Expr {
+) -> (lir::Expr, TyId) {
     let mut default = None;
+    // All arms have the same type.
+    let mut ty = None;
     let cases: BTreeMap<_, _> = hir_cases
         .into_iter()
         .map(|(p, e)| lower_tagged_variant_match_arm(cx, &lyt, lir_subj.clone(), p, e))
-        .filter_map(|(v, e)| match v {
-            Some(v) => Some((v, e)),
-            None => {
-                assert!(default.is_none());
-                default = Some(e);
-                None
+        .filter_map(|(v, (e, arm_ty))| {
+            ty.get_or_insert(arm_ty);
+            match v {
+                Some(v) => Some((v, e)),
+                None => {
+                    assert!(default.is_none());
+                    default = Some(e);
+                    None
+                }
             }
         })
         .collect();
 
     let switch_subj_expr = match lyt.tag {
-        TagLayout::Direct { .. } => lir::Expr::Select {
-            record: Box::new(lir::Expr::Var(lir_subj.clone())),
-            field: "tag".into(),
-        },
-        TagLayout::Niche { path, values: _ } => {
+        TagLayout::Direct { .. } => {
+            lir::Expr::Select { record: Box::new(lir::Expr::Var(lir_subj)), field: "tag".into() }
+        }
+        TagLayout::Niche { path, .. } | TagLayout::MultiNiche { path, .. } => {
             select_value_at_path(lir::Expr::Var(lir_subj), path)
         }
+        TagLayout::PointerTag { bits, values: _ } => {
+            lir::Expr::PtrTag { ptr: Box::new(lir::Expr::Var(lir_subj)), bits }
+        }
     };
     let switch_subj = cx.temp_var(switch_subj_expr.ty());
 
     let default = default.map(Box::new);
     let switch_expr = Box::new(lir::Expr::Switch { subj: switch_subj.clone(), cases, default });
-    lir::Expr::Let {
-        binder: switch_subj.clone(),
-        value: Box::new(switch_subj_expr),
-        body: switch_expr,
-    }
+    let value = Box::new(switch_subj_expr);
+    let ty = ty.expect("empty match is unsupported");
+    (lir::Expr::Let { binder: switch_subj, value, body: switch_expr }, ty)
 }
 
 fn lower_tagged_variant_match_arm(
@@ -215,9 +391,9 @@ fn lower_tagged_variant_match_arm(
     lir_subj: lir::Var,
     pat: hir::Pat,
     body: hir::Expr,
-) -> (Option<u64>, lir::Expr) {
+) -> (Option<u64>, (lir::Expr, TyId)) {
     match &lyt.tag {
-        TagLayout::Direct { values: tag_vals, niches: _ } => match pat {
+        TagLayout::Direct { ty: _, values: tag_vals, niches: _, placement: _ } => match pat {
             hir::Pat::Variant { ty: _, variant, field } => {
                 let select_field = lir::Expr::Select {
                     record: Box::new(lir::Expr::Var(lir_subj)),
@@ -227,12 +403,49 @@ fn lower_tagged_variant_match_arm(
                 (Some(tag_vals[&variant]), body)
             }
         },
-        TagLayout::Niche { path: _, values: tag_vals } => match pat {
+        TagLayout::Niche { path: _, ty: _, values: tag_vals } => match pat {
             hir::Pat::Variant { ty: _, variant, field } => {
                 let body = lower_match_arm_body(cx, (field, lir::Expr::Var(lir_subj)), body);
                 (tag_vals.get(&variant).copied(), body)
             }
         },
+        TagLayout::MultiNiche { holder, hoisted, values: tag_vals, .. } => match pat {
+            hir::Pat::Variant { ty: _, variant, field } => {
+                let select_field = |field: &str| lir::Expr::Select {
+                    record: Box::new(lir::Expr::Var(lir_subj.clone())),
+                    field: Name::from(field),
+                };
+                let value = if variant == *holder {
+                    let holder_fields = match hoisted {
+                        Some(_) => aggregate_field_names(&cx.layout_of(&field.ty)),
+                        None => vec![],
+                    };
+                    join_multi_niche_holder(
+                        select_field("tag"),
+                        select_field("data"),
+                        hoisted.clone(),
+                        holder_fields,
+                    )
+                } else {
+                    select_field("data")
+                };
+                let body = lower_match_arm_body(cx, (field, value), body);
+                (tag_vals.get(&variant).copied(), body)
+            }
+        },
+        TagLayout::PointerTag { bits, values: tag_vals } => match pat {
+            hir::Pat::Variant { ty: _, variant, field } => {
+                // Zero-sized variants are stored as a tagged null pointer, so
+                // there's nothing to dereference.
+                let value = if lyt.variants[&variant].is_zst() {
+                    lir::Expr::Record(vec![])
+                } else {
+                    lir::Expr::UntagPtr { ptr: Box::new(lir::Expr::Var(lir_subj)), bits: *bits }
+                };
+                let body = lower_match_arm_body(cx, (field, value), body);
+                (Some(tag_vals[&variant]), body)
+            }
+        },
     }
 }
 
@@ -240,47 +453,71 @@ fn lower_match_arm_body(
     cx: &mut Ctxt,
     binding: (hir::Var, lir::Expr),
     body: hir::Expr,
-) -> lir::Expr {
+) -> (lir::Expr, TyId) {
     let (binder, value) = binding;
-    let binder = lower_var(binder);
-    let body = lower_expr(cx, body);
-    lir::Expr::Let { binder, value: Box::new(value), body: Box::new(body) }
+    let binder = lower_var(cx, binder);
+    let (body, ty) = lower_expr(cx, body);
+    (lir::Expr::Let { binder, value: Box::new(value), body: Box::new(body) }, ty)
+}
+
+fn lower_var(cx: &mut Ctxt, var: hir::Var) -> lir::Var {
+    let (var, _) = lower_var_and_ty(cx, var);
+    var
 }
 
-fn lower_var(var: hir::Var) -> lir::Var {
+fn lower_var_and_ty(cx: &mut Ctxt, var: hir::Var) -> (lir::Var, TyId) {
     let hir::Var { name, ty } = var;
-    lir::Var::new(name, lower_layout(layout_of(ty)))
+    let ty = cx.layouts.intern(&ty);
+    (lir::Var::new(name, lower_layout(cx.layout_of_id(ty))), ty)
 }
 
 // FIXME: this is only pub(crate) because it's used in a crate-level test
 pub(crate) fn lower_layout(layout: Layout) -> lir::Ty {
     match layout {
-        Layout::U64(_) => lir::Ty::U64,
+        Layout::Int(ty, _) => lir::Ty::Int(ty),
         Layout::Ptr { pointee, niches: _ } => lir::Ty::Ptr(Box::new(lower_layout(*pointee))),
-        Layout::Aggregate { fields } => {
-            lir::Ty::Record(fields.into_iter().map(|(n, l)| (n, lower_layout(l))).collect())
+        Layout::Aggregate { fields, repr } => {
+            let fields = fields.into_iter().map(|(n, l)| (n, lower_layout(l))).collect();
+            lir::Ty::Record(fields, repr)
         }
         Layout::Variant(VariantLayout::Single { field }) => lower_layout(*field),
-        Layout::Variant(VariantLayout::Tagged(TaggedLayout { tag, variants })) => {
+        Layout::Variant(VariantLayout::Tagged(tagged)) => {
+            let repr = tagged.record_repr();
+            let TaggedLayout { tag, variants } = tagged;
             match tag {
-                TagLayout::Direct { values: _, niches: _ } => {
-                    let tag_ty = lir::Ty::U64;
+                TagLayout::Direct { ty, values: _, niches: _, placement } => {
+                    let tag_ty = lir::Ty::Int(ty);
+                    let variant_tys =
+                        variants.into_iter().map(|(n, l)| (n, lower_layout(l))).collect();
+                    let data_ty = lir::Ty::UntaggedUnion(variant_tys);
+                    lir::Ty::Record(data_and_tag(data_ty, tag_ty, placement), repr)
+                }
+                TagLayout::Niche { .. } => {
+                    let field_lyt = is_nicheable(&variants).into_field().unwrap().clone();
+                    lower_layout(field_lyt)
+                }
+                TagLayout::MultiNiche { field, .. } => {
+                    let tag_ty = lower_layout(*field);
                     let variant_tys =
                         variants.into_iter().map(|(n, l)| (n, lower_layout(l))).collect();
                     let data_ty = lir::Ty::UntaggedUnion(variant_tys);
-                    // FIXME: these should be represented differently from user-written fields.
-                    // Otherwise, there could be name conflicts in some situations.
-                    lir::Ty::Record(map! { "tag" => tag_ty, "data" => data_ty })
+                    lir::Ty::Record(vec![("data".into(), data_ty), ("tag".into(), tag_ty)], repr)
                 }
-                TagLayout::Niche { path: _, values: _ } => {
-                    let field_lyt = is_nicheable(&variants).as_field().unwrap().clone();
-                    let field_ty = lower_layout(field_lyt);
-                    field_ty
+                TagLayout::PointerTag { .. } => {
+                    // A pointer to any of the variants' pointees.
+                    let pointee_tys = variants
+                        .into_iter()
+                        .map(|(n, l)| match l {
+                            Layout::Ptr { pointee, niches: _ } => (n, lower_layout(*pointee)),
+                            _ => (n, lower_layout(l)),
+                        })
+                        .collect();
+                    lir::Ty::Ptr(Box::new(lir::Ty::UntaggedUnion(pointee_tys)))
                 }
             }
         }
         Layout::Recursive(body) => lir::Ty::Recursive(Box::new(lower_layout(*body))),
-        Layout::RecurId(k) => lir::Ty::RecurId(k),
+        Layout::RecurId { id, unrolled: _ } => lir::Ty::RecurId(id),
     }
 }
 
@@ -3,13 +3,13 @@ use super::*;
 #[test]
 fn test_construct_niche_nullary_variant() {
     fn c(p: TagPath, t: u64) -> lir::Expr {
-        construct_niche_nullary_variant(p, t)
+        construct_niche_nullary_variant(p, IntTy::U8, t)
     }
 
-    assert_eq!(c(TagPath::empty(), 123), lir::Expr::U64(123));
+    assert_eq!(c(TagPath::empty(), 123), lir::Expr::Int(IntTy::U8, 123));
     assert_eq!(
         c(TagPath::singleton(ValueProj::Tag), 123),
-        lir::Expr::Record(map! { "tag" => lir::Expr::U64(123) })
+        lir::Expr::Record(vec![("tag".into(), lir::Expr::Int(IntTy::U8, 123))])
     );
     assert_eq!(
         c(
@@ -18,7 +18,7 @@ fn test_construct_niche_nullary_variant() {
             )),
             123
         ),
-        lir::Expr::Record(map! { "tag" => lir::Expr::U64(123) })
+        lir::Expr::Record(vec![("tag".into(), lir::Expr::Int(IntTy::U8, 123))])
     );
     assert_eq!(
         c(
@@ -27,8 +27,9 @@ fn test_construct_niche_nullary_variant() {
             )),
             123
         ),
-        lir::Expr::Record(
-            map! { "data" => lir::Expr::Record(map! { "tag" => lir::Expr::U64(123) }) }
-        )
+        lir::Expr::Record(vec![(
+            "data".into(),
+            lir::Expr::Record(vec![("tag".into(), lir::Expr::Int(IntTy::U8, 123))])
+        )])
     );
 }
@@ -1,75 +1,318 @@
 #![feature(iter_intersperse)]
+#![cfg_attr(test, feature(test))]
 
 #[macro_use]
 mod util;
 
+#[cfg(test)]
+mod benches;
+mod c_header;
+mod check;
+mod compare;
 mod debruijn;
+mod decode;
+mod draw;
 mod eval;
 mod hir;
+mod int_ty;
+mod json;
 mod layout;
 mod layout_of;
 mod lir;
 mod lower;
+mod memory;
 mod name;
 mod parser;
+mod rust;
+mod vm;
 
+use std::fmt::Display;
 use std::io::{BufRead, Write};
 
-use crate::eval::eval_root_expr;
+use crate::c_header::c_header;
+use crate::check::check_round_trip;
+use crate::compare::{compare_heap_use, compare_layouts};
+use crate::decode::{decode_value, DecodeError};
+use crate::draw::draw_layout;
+use crate::eval::{eval_root_expr, eval_with_stats};
+use crate::json::{Json, ToJson};
+#[cfg(test)]
 use crate::layout_of::layout_of;
-use crate::lower::{lower_layout, lower_root_expr};
+#[cfg(test)]
+use crate::layout_of::LayoutStrategy;
+use crate::layout_of::{strategy_by_name, LayoutCx, STRATEGIES};
+#[cfg(test)]
+use crate::lower::lower_root_expr;
+use crate::lower::{lower_layout, lower_root_expr_in};
+use crate::memory::Memory;
 use crate::parser::Parser;
+use crate::rust::{cross_check, rust_program, test_values};
+use crate::vm::{compile, differential};
 
 fn main() {
-    print_prompt();
+    // With `--json`, every command prints one line of JSON, without prompts.
+    let json = match std::env::args().nth(1).as_deref() {
+        None => false,
+        Some("--json") => true,
+        Some(arg) => {
+            eprintln!("error: unknown argument '{}' (usage: type-layout [--json])", arg);
+            std::process::exit(2)
+        }
+    };
+    // Layouts are reused across inputs.
+    let mut layouts = LayoutCx::new(layout_of::DEFAULT);
+    print_prompt(json);
 
+    // The stdin lock is meant to be held for the whole loop.
+    #[allow(clippy::significant_drop_in_scrutinee)]
     for line in std::io::stdin().lock().lines().map(Result::unwrap) {
         let line = line.trim();
 
         match line {
             "q" | "quit" | ":q" | ":quit" => break,
-            _ => handle_input(line),
+            _ => handle_input(&mut layouts, line, json),
         }
 
-        print_prompt();
+        print_prompt(json);
     }
 }
 
-fn handle_input(line: &str) {
-    match line.split_once(" ") {
+fn handle_input(layouts: &mut LayoutCx<'static>, line: &str, json: bool) {
+    if line == ":strategy" {
+        let names: Vec<_> = STRATEGIES.iter().map(|s| s.name()).collect();
+        let text = format!("{} (available: {})", layouts.strategy().name(), names.join(", "));
+        let value = || {
+            let strategy = Json::string(layouts.strategy().name());
+            Json::record([("strategy", strategy), ("available", Json::array(names))])
+        };
+        emit(json, text, value);
+        return;
+    }
+
+    match line.split_once(' ') {
+        Some((":check", src)) => match check_round_trip(&parse_ty(src), layouts) {
+            Ok(count) => {
+                let text = format!("ok: all {} values round-trip", count);
+                emit(json, text, || Json::record([("values", (count as u64).to_json())]))
+            }
+            Err(err) => emit_error(json, err),
+        },
+        Some((":compare", src)) => {
+            let comparison = compare_layouts(parse_ty(src));
+            emit(json, &comparison, || comparison.to_json())
+        }
+        Some((":strategy", name)) => match strategy_by_name(name.trim()) {
+            Some(new) => {
+                layouts.set_strategy(new);
+                let text = format!("switched to {}", new.name());
+                emit(json, text, || Json::record([("strategy", Json::string(new.name()))]));
+            }
+            None => emit_error(json, format!("unknown layout strategy '{}'", name.trim())),
+        },
+        Some((":explain", src)) => {
+            let (_, trace) = layouts.explain(&parse_ty(src));
+            emit(json, &trace, || trace.to_json())
+        }
+        Some((":draw", src)) => {
+            let (format, src) = match src.trim_start().split_once(' ') {
+                Some((format @ ("ascii" | "svg" | "dot"), src)) => (format, src),
+                _ => ("ascii", src),
+            };
+            let diagram = draw_layout(&layouts.layout_of(&parse_ty(src)));
+            let text = match format {
+                "svg" => diagram.to_svg(),
+                "dot" => diagram.to_dot(),
+                _ => diagram.to_ascii(),
+            };
+            emit(json, text.trim_end(), || diagram.to_json())
+        }
+        Some((":c", src)) => {
+            let header = c_header(&parse_ty(src), layouts);
+            emit(json, header.trim_end(), || Json::record([("header", Json::string(&header))]))
+        }
+        Some((":rust", src)) => {
+            let ty = parse_ty(src);
+            let program = rust_program(&ty, &test_values(&ty));
+            emit(json, &program, || Json::record([("program", Json::string(&program))]))
+        }
+        Some((":rustc", src)) => match cross_check(&parse_ty(src)) {
+            Ok(check) => emit(json, &check, || check.to_json()),
+            Err(err) => emit_error(json, err),
+        },
+        // There is no JSON serialization of expressions, so they are
+        // serialized as their text.
         Some((":hir", src)) => {
-            println!("{}", parse(src))
+            let expr = parse(src);
+            emit(json, &expr, || Json::record([("expr", Json::string(&expr))]))
         }
         Some((":lir", src)) => {
-            println!("{}", lower_root_expr(parse(src)))
+            let expr = lower_root_expr_in(parse(src), layouts);
+            emit(json, &expr, || Json::record([("expr", Json::string(&expr))]))
+        }
+        Some((":bytes", src)) => {
+            let expr = parse(src);
+            // The expression's own LIR type can be narrower, like that of
+            // just one variant.
+            let layout = layouts.layout_of(&expr.ty());
+            let value = eval_root_expr(lower_root_expr_in(expr, layouts));
+            let ty = lower_layout(layout.clone());
+            let mut memory = Memory::new();
+            let addr = match memory.encode(&value, &ty) {
+                Ok(addr) => addr,
+                Err(err) => return emit_error(json, err),
+            };
+            let read_back = memory.decode(addr, &ty, &layout);
+            let text = match &read_back {
+                Ok(read_back) => format!("{}\nread back: {}", memory, read_back),
+                Err(err) => format!("{}\nread back failed: {}", memory, err),
+            };
+            let value = || {
+                let (read_back, error) = match &read_back {
+                    Ok(read_back) => (read_back.to_json(), Json::Null),
+                    Err(err) => (Json::Null, Json::string(err)),
+                };
+                Json::record([
+                    ("root", addr.to_json()),
+                    ("memory", memory.to_json()),
+                    ("read_back", read_back),
+                    ("error", error),
+                ])
+            };
+            emit(json, &text, value)
+        }
+        Some((":bytecode", src)) => {
+            let expr = parse(src);
+            let ty = lower_layout(layouts.layout_of(&expr.ty()));
+            let program = compile(&lower_root_expr_in(expr, layouts), &ty);
+            emit(json, &program, || program.to_json())
         }
+        Some((":vm", src)) => {
+            let expr = parse(src);
+            let layout = layouts.layout_of(&expr.ty());
+            match differential(lower_root_expr_in(expr, layouts), &layout) {
+                Ok(result) => emit(json, &result, || result.to_json()),
+                Err(err) => emit_error(json, err),
+            }
+        }
+        Some((":stats", src)) => match src.trim_start().split_once(' ') {
+            Some(("all", src)) => {
+                let comparison = compare_heap_use(parse(src));
+                emit(json, &comparison, || comparison.to_json())
+            }
+            _ => {
+                let expr = parse(src);
+                let ty = lower_layout(layouts.layout_of(&expr.ty()));
+                let (_, stats) = eval_with_stats(lower_root_expr_in(expr, layouts), &ty);
+                emit(json, &stats, || stats.to_json())
+            }
+        },
         Some((":lyt" | ":layout", src)) => {
-            println!("{}", layout_of(parse_ty(src)))
+            let layout = layouts.layout_of(&parse_ty(src));
+            emit(json, &layout, || layout.to_json())
         }
         Some((":t" | ":hty" | ":hirty", src)) => {
-            println!("{}", parse(src).ty())
+            let ty = parse(src).ty();
+            emit(json, &ty, || ty.to_json())
         }
         Some((":lty" | ":lirty", src)) => {
-            println!("{}", lower_root_expr(parse(src)).ty())
+            let ty = lower_root_expr_in(parse(src), layouts).ty();
+            emit(json, &ty, || ty.to_json())
         }
         Some((":size", src)) => {
-            println!("Size: {}", lower_layout(layout_of(parse_ty(src))).packed_size())
+            let layout = layouts.layout_of(&parse_ty(src));
+            let shape = layout.shape();
+            debug_assert_eq!(shape, lower_layout(layout.clone()).shape());
+            let offsets = layout.field_offsets();
+            let text = match &offsets {
+                Some(offsets) => {
+                    format!("{}\noffsets: {}", shape, util::display_map(offsets.iter()))
+                }
+                None => shape.to_string(),
+            };
+            let value = || {
+                let offsets = offsets.as_ref().map_or(Json::Null, Json::map);
+                Json::record([("shape", shape.to_json()), ("offsets", offsets)])
+            };
+            emit(json, text, value)
         }
         Some((cmd, _)) if cmd.trim_start().starts_with(':') => {
-            eprintln!("error: unknown REPL command '{}'", cmd)
+            emit_error(json, format!("unknown REPL command '{}'", cmd))
         }
         _ => {
-            println!("{}", parse_and_eval(line))
+            let (value, decoded) = parse_eval_and_decode_in(line, layouts);
+            if json {
+                let (hir, error) = match &decoded {
+                    Ok(decoded) => (decoded.to_json(), Json::Null),
+                    Err(err) => (Json::Null, Json::string(err)),
+                };
+                let value =
+                    Json::record([("lir", value.to_json()), ("hir", hir), ("error", error)]);
+                println!("{}", value);
+                return;
+            }
+            println!("lir: {}", value);
+            match decoded {
+                Ok(decoded) => println!("hir: {}", decoded),
+                Err(err) => eprintln!("error: {}", err),
+            }
         }
     }
 }
 
+/// Print `text`, or `value` as JSON with `--json`.
+fn emit(json: bool, text: impl Display, value: impl FnOnce() -> Json) {
+    if json {
+        println!("{}", value())
+    } else {
+        println!("{}", text)
+    }
+}
+
+fn emit_error(json: bool, err: impl Display) {
+    if json {
+        eprintln!("{}", Json::record([("error", Json::string(err))]))
+    } else {
+        eprintln!("error: {}", err)
+    }
+}
+
+#[cfg(test)]
 fn parse_and_eval(src: &str) -> lir::Value {
+    parse_and_eval_with(src, layout_of::DEFAULT)
+}
+
+#[cfg(test)]
+fn parse_and_eval_with(src: &str, strategy: &dyn LayoutStrategy) -> lir::Value {
+    parse_and_eval_in(src, &mut LayoutCx::new(strategy))
+}
+
+#[cfg(test)]
+fn parse_and_eval_in(src: &str, layouts: &mut LayoutCx) -> lir::Value {
     let hir_expr = parse(src);
-    let lir_expr = lower_root_expr(hir_expr);
+    let lir_expr = lower_root_expr_in(hir_expr, layouts);
     eval_root_expr(lir_expr)
 }
 
+#[cfg(test)]
+fn parse_eval_and_decode(src: &str) -> hir::Value {
+    let (_, decoded) = parse_eval_and_decode_in(src, &mut LayoutCx::new(layout_of::DEFAULT));
+    decoded.unwrap()
+}
+
+/// Evaluate `src`, and also decode the machine value into the high-level
+/// value that it represents.
+fn parse_eval_and_decode_in(
+    src: &str,
+    layouts: &mut LayoutCx,
+) -> (lir::Value, Result<hir::Value, DecodeError>) {
+    let hir_expr = parse(src);
+    let ty = hir_expr.ty();
+    let lir_expr = lower_root_expr_in(hir_expr, layouts);
+    let value = eval_root_expr(lir_expr);
+    let decoded = decode_value(&value, &ty, &layouts.layout_of(&ty));
+    (value, decoded)
+}
+
 fn parse(src: &str) -> hir::Expr {
     Parser::parse(src.to_owned())
 }
@@ -78,7 +321,10 @@ fn parse_ty(src: &str) -> hir::Ty {
     Parser::parse_ty_toplevel(src.to_owned())
 }
 
-fn print_prompt() {
+fn print_prompt(json: bool) {
+    if json {
+        return;
+    }
     print!("> ");
     std::io::stdout().flush().unwrap();
 }
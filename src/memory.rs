@@ -0,0 +1,594 @@
+//! A byte-level memory model for LIR values.
+//!
+//! [`Memory::encode()`] stores a machine value in flat, little-endian bytes,
+//! laid out like its LIR type says, and puts the pointees of boxes in
+//! allocations on a simulated heap. [`Memory::decode()`] reads a value with a
+//! given layout back. Bytes that no scalar was stored in, like padding, are
+//! zero.
+//!
+//! LIR values don't say which member of an untagged union they are, so
+//! encoding picks the first member, in name order, that the value fits.
+//! Decoding follows the layout instead: the tag of each variant type says
+//! which member is stored, and which values of niche-encoded variants only
+//! have the fields that hold the tag.
+
+use std::fmt::{Display, Formatter};
+
+use crate::int_ty::IntTy;
+use crate::layout::{
+    Layout, TagLayout, TagPath, TaggedLayout, ValueProj, VariantLayout, VariantRepr,
+};
+use crate::layout_of::is_nicheable;
+use crate::lir::{Align, Shape, ShapeError, Size, Ty, Value};
+use crate::name::Name;
+use crate::util::{display_table, Map};
+
+/// The address of the first allocation. Anything below it is never a
+/// pointer, like the null pointer and the niche values next to it.
+pub const HEAP_START: u64 = 0x1000;
+
+const PTR_SIZE: u64 = 8;
+
+#[derive(Debug, Clone)]
+pub struct Memory {
+    /// By address.
+    allocations: Map<u64, Allocation>,
+    /// Where the next allocation can start.
+    end: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    pub align: Align,
+    pub bytes: Vec<u8>,
+    /// The scalars that were stored, in the order they were stored.
+    pub scalars: Vec<Scalar>,
+}
+
+/// An integer or pointer that was stored by [`Memory::encode()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scalar {
+    /// From the start of the allocation.
+    pub offset: u64,
+    pub size: u64,
+    /// Where the scalar is in the allocation's value, like `{root}.tl`.
+    pub path: String,
+    pub descr: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryError {
+    /// `value` is not a value of type `ty`.
+    Mismatch {
+        value: Value,
+        ty: String,
+    },
+    Shape(ShapeError),
+    /// The `size` bytes at `addr` aren't all in one allocation.
+    OutOfBounds {
+        addr: u64,
+        size: u64,
+    },
+    /// The pointer `ptr` at `addr` doesn't point to the start of an
+    /// allocation.
+    Dangling {
+        addr: u64,
+        ptr: u64,
+    },
+    /// The tag at `addr` doesn't belong to any variant.
+    UnknownTag {
+        addr: u64,
+        tag: u64,
+    },
+    /// Following pointers from the value leads back to the allocation at
+    /// `addr`.
+    Cycle {
+        addr: u64,
+    },
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self { allocations: Map::new(), end: HEAP_START }
+    }
+
+    pub fn allocations(&self) -> impl Iterator<Item = (u64, &Allocation)> {
+        self.allocations.iter().map(|(&addr, alloc)| (addr, alloc))
+    }
+
+    /// Reserve zeroed bytes for a value of `shape`, and return their address.
+    ///
+    /// Allocations are at least their alignment apart, so a pointer with a
+    /// tag in its low bits never points into the next allocation.
+    pub fn allocate(&mut self, shape: Shape) -> u64 {
+        let align = shape.align.bytes();
+        let addr = (self.end + align - 1) & !(align - 1);
+        let size = shape.size.bytes();
+        self.end = addr + shape.stride().bytes().max(align);
+        let alloc =
+            Allocation { align: shape.align, bytes: vec![0; size as usize], scalars: vec![] };
+        self.allocations.insert(addr, alloc);
+        addr
+    }
+
+    /// Store `value` of type `ty` in a new allocation, and the values it
+    /// points to in allocations after it. Returns the value's address.
+    pub fn encode(&mut self, value: &Value, ty: &Ty) -> Result<u64, MemoryError> {
+        let shape = ty.try_shape().map_err(MemoryError::Shape)?;
+        let addr = self.allocate(shape);
+        let mut cx = Encoder { memory: self, exact: false };
+        cx.encode(addr, "{root}", value, ty)?;
+        Ok(addr)
+    }
+
+    /// Read a value of type `ty` at `addr`, where `ty` is the lowering of the
+    /// closed `layout`.
+    pub fn decode(&self, addr: u64, ty: &Ty, layout: &Layout) -> Result<Value, MemoryError> {
+        Decoder { memory: self, recursive: vec![], pointees: vec![] }.decode(addr, ty, layout)
+    }
+
+    /// The address and bytes of each allocation.
+    pub fn contents(&self) -> Vec<(u64, Vec<u8>)> {
+        self.allocations().map(|(addr, alloc)| (addr, alloc.bytes.clone())).collect()
+    }
+
+    /// The bytes of all allocations, in hex, like `0x1000: 01 00, 0x1008: 2a`.
+    pub fn hex_bytes(&self) -> String {
+        let allocs = self.allocations().map(|(addr, alloc)| {
+            let bytes = alloc.bytes.iter().map(|b| format!(" {:02x}", b)).collect::<String>();
+            format!("{:#x}:{}", addr, bytes)
+        });
+        allocs.collect::<Vec<_>>().join(", ")
+    }
+
+    /// The start of the allocation that `addr` is in, if the `size` bytes
+    /// from there on are all in it too.
+    fn allocation_at(&self, addr: u64, size: u64) -> Result<u64, MemoryError> {
+        match self.allocations.range(..=addr).next_back() {
+            Some((&start, alloc)) if addr + size <= start + alloc.bytes.len() as u64 => Ok(start),
+            _ => Err(MemoryError::OutOfBounds { addr, size }),
+        }
+    }
+
+    /// `addr + offset`, which must still be in the allocation that `addr`
+    /// is in: addresses that run past its end don't reach the next one.
+    pub fn offset(&self, addr: u64, offset: u64) -> Result<u64, MemoryError> {
+        self.allocation_at(addr, offset)?;
+        Ok(addr + offset)
+    }
+
+    pub fn read(&self, addr: u64, size: u64) -> Result<&[u8], MemoryError> {
+        let start = self.allocation_at(addr, size)?;
+        let offset = (addr - start) as usize;
+        Ok(&self.allocations[&start].bytes[offset..offset + size as usize])
+    }
+
+    pub fn write(&mut self, addr: u64, bytes: &[u8]) -> Result<(), MemoryError> {
+        let start = self.allocation_at(addr, bytes.len() as u64)?;
+        let offset = (addr - start) as usize;
+        let alloc = self.allocations.get_mut(&start).unwrap();
+        alloc.bytes[offset..offset + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Read a little-endian integer of `size` bytes.
+    pub fn read_int(&self, addr: u64, size: u64) -> Result<u64, MemoryError> {
+        let bytes = self.read(addr, size)?;
+        Ok(bytes.iter().rev().fold(0, |n, &b| n << 8 | u64::from(b)))
+    }
+
+    /// Write the low `size` bytes of `n`, little-endian.
+    pub fn write_int(&mut self, addr: u64, size: u64, n: u64) -> Result<(), MemoryError> {
+        self.write(addr, &n.to_le_bytes()[..size as usize])
+    }
+
+    fn store_scalar(
+        &mut self,
+        addr: u64,
+        size: u64,
+        n: u64,
+        path: &str,
+        descr: String,
+    ) -> Result<(), MemoryError> {
+        self.write_int(addr, size, n)?;
+        let start = self.allocation_at(addr, size)?;
+        let scalar = Scalar { offset: addr - start, size, path: path.to_owned(), descr };
+        self.allocations.get_mut(&start).unwrap().scalars.push(scalar);
+        Ok(())
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The shape of `ty`, which is closed.
+fn shape(ty: &Ty) -> Result<Shape, MemoryError> {
+    ty.try_shape().map_err(MemoryError::Shape)
+}
+
+/// The offsets of the fields of a record type.
+fn field_offsets(ty: &Ty, fields: &[(Name, Ty)]) -> Result<Vec<u64>, MemoryError> {
+    let shapes = fields.iter().map(|(_, t)| shape(t)).collect::<Result<Vec<_>, _>>()?;
+    let repr = match ty {
+        Ty::Record(_, repr) => *repr,
+        _ => unreachable!("not a record: {}", ty),
+    };
+    let (offsets, _) = Shape::record(shapes, repr);
+    Ok(offsets.into_iter().map(Size::bytes).collect())
+}
+
+/// Recursive types are unfolded when they're reached, so the types are
+/// always closed.
+struct Encoder<'m> {
+    memory: &'m mut Memory,
+    /// Whether records must have all of their fields. Values of variants
+    /// whose tag is in a niche only have the fields that hold the tag.
+    exact: bool,
+}
+
+impl<'m> Encoder<'m> {
+    fn encode(&mut self, addr: u64, path: &str, value: &Value, ty: &Ty) -> Result<(), MemoryError> {
+        let mismatch = || MemoryError::Mismatch { value: value.clone(), ty: ty.to_string() };
+        match (ty, value) {
+            (Ty::Int(int_ty), Value::Int(value_ty, n)) if int_ty == value_ty => {
+                let size = int_ty.shape().size.bytes();
+                self.memory.store_scalar(addr, size, *n, path, value.to_string())
+            }
+            (Ty::Ptr(pointee), Value::Box(boxed) | Value::TaggedBox(boxed, _)) => {
+                let tag = match value {
+                    Value::TaggedBox(_, tag) => *tag,
+                    _ => 0,
+                };
+                let shape = shape(pointee)?;
+                if tag >= shape.align.bytes() {
+                    return Err(mismatch());
+                }
+                let target = self.memory.allocate(shape);
+                let descr = match tag {
+                    0 => format!("box at {:#x}", target),
+                    _ => format!("box at {:#x}, tag {}", target, tag),
+                };
+                self.memory.store_scalar(addr, PTR_SIZE, target | tag, path, descr)?;
+                self.encode(target, "{root}", boxed, pointee)
+            }
+            // A null pointer, maybe with a tag.
+            (Ty::Ptr(_), Value::Int(IntTy::U64, n)) => {
+                let descr = match n {
+                    0 => "null".to_owned(),
+                    _ => format!("null, tag {}", n),
+                };
+                self.memory.store_scalar(addr, PTR_SIZE, *n, path, descr)
+            }
+            (Ty::Record(fields, _), Value::Record(values)) => {
+                let known = |name| fields.iter().any(|(n, _)| n == name);
+                if !values.keys().all(known) || (self.exact && values.len() != fields.len()) {
+                    return Err(mismatch());
+                }
+                for ((name, field_ty), offset) in fields.iter().zip(field_offsets(ty, fields)?) {
+                    if let Some(field) = values.get(name) {
+                        let path = format!("{}.{}", path, name);
+                        self.encode(addr + offset, &path, field, field_ty)?;
+                    }
+                }
+                Ok(())
+            }
+            (Ty::UntaggedUnion(members), _) => {
+                let outer_exact = self.exact;
+                for exact in [true, false] {
+                    for (name, member_ty) in members {
+                        let saved = self.memory.clone();
+                        self.exact = exact;
+                        let path = format!("{}.{}", path, name);
+                        match self.encode(addr, &path, value, member_ty) {
+                            Ok(()) => {
+                                self.exact = outer_exact;
+                                return Ok(());
+                            }
+                            Err(_) => *self.memory = saved,
+                        }
+                    }
+                }
+                self.exact = outer_exact;
+                Err(mismatch())
+            }
+            (Ty::Recursive(_), _) => self.encode(addr, path, value, &ty.unfold().unwrap()),
+            (Ty::RecurId(id), _) => Err(MemoryError::Shape(ShapeError::UnboundRecurId(*id))),
+            _ => Err(mismatch()),
+        }
+    }
+}
+
+/// The offset and type of the field `field` of the record type `ty`.
+fn field_at(ty: &Ty, field: &Name) -> Result<(u64, Ty), MemoryError> {
+    match ty.unfolded() {
+        Ty::Record(fields, _) => {
+            let offsets = field_offsets(ty, &fields)?;
+            let idx = fields.iter().position(|(n, _)| n == field);
+            let idx = idx.unwrap_or_else(|| panic!("type {} has no field {}", ty, field));
+            Ok((offsets[idx], fields[idx].1.clone()))
+        }
+        ty => panic!("type {} is not a record", ty),
+    }
+}
+
+/// The `data` and `tag` of a variant type whose tag is stored next to the
+/// union of its variants' data: their offsets, the union's members, and the
+/// tag's type.
+fn data_and_tag(ty: &Ty) -> Result<(u64, Map<Name, Ty>, u64, Ty), MemoryError> {
+    let (data_offset, data_ty) = field_at(ty, &"data".into())?;
+    let (tag_offset, tag_ty) = field_at(ty, &"tag".into())?;
+    match data_ty {
+        Ty::UntaggedUnion(members) => Ok((data_offset, members, tag_offset, tag_ty)),
+        ty => panic!("type {} is not a union", ty),
+    }
+}
+
+fn variant_named(values: &Map<Name, u64>, tag: u64) -> Option<&Name> {
+    values.iter().find(|&(_, &v)| v == tag).map(|(n, _)| n)
+}
+
+/// The value of a niche-encoded variant without data: just the tag, at
+/// `path`. This is what lowering constructs for it.
+fn niche_value(path: TagPath, ty: IntTy, tag: u64) -> Value {
+    path.rfold(Value::Int(ty, tag), |value, proj| match proj {
+        ValueProj::Field(name) => Value::Record(map! { name => value }),
+        ValueProj::Variant { repr: VariantRepr::Wrapper, name: _ } => {
+            Value::Record(map! { "data" => value })
+        }
+        ValueProj::Variant { repr: VariantRepr::Transparent, name: _ } => value,
+        ValueProj::Tag => Value::Record(map! { "tag" => value }),
+    })
+}
+
+/// Reads values by following their layout, next to its lowered type, which
+/// says where things are. Recursive types are unfolded when they're reached.
+struct Decoder<'m, 'l> {
+    memory: &'m Memory,
+    /// The bodies of the enclosing [`Layout::Recursive`]s, innermost last.
+    recursive: Vec<&'l Layout>,
+    /// The allocations that the pointers followed to get here point to.
+    pointees: Vec<u64>,
+}
+
+impl<'m, 'l> Decoder<'m, 'l> {
+    fn decode(&mut self, addr: u64, ty: &Ty, layout: &'l Layout) -> Result<Value, MemoryError> {
+        match (layout, ty) {
+            (Layout::Int(int_ty, _), _) => {
+                Ok(Value::Int(*int_ty, self.memory.read_int(addr, int_ty.shape().size.bytes())?))
+            }
+            (Layout::Ptr { pointee, niches: _ }, Ty::Ptr(pointee_ty)) => {
+                let ptr = self.memory.read_int(addr, PTR_SIZE)?;
+                if !self.memory.allocations.contains_key(&ptr) {
+                    return Err(MemoryError::Dangling { addr, ptr });
+                }
+                Ok(Value::Box(Box::new(self.follow(ptr, pointee_ty, pointee)?)))
+            }
+            (Layout::Aggregate { fields: field_lyts, repr: _ }, Ty::Record(fields, _)) => {
+                let fields = field_lyts
+                    .iter()
+                    .zip(fields)
+                    .zip(field_offsets(ty, fields)?)
+                    .map(|(((name, field_lyt), (_, field_ty)), offset)| {
+                        Ok((name.clone(), self.decode(addr + offset, field_ty, field_lyt)?))
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Record(fields))
+            }
+            (Layout::Variant(VariantLayout::Single { field }), _) => self.decode(addr, ty, field),
+            (Layout::Variant(VariantLayout::Tagged(tagged)), _) => {
+                self.decode_tagged(addr, ty, tagged)
+            }
+            (Layout::Recursive(body), Ty::Recursive(_)) => {
+                self.recursive.push(body);
+                let result = self.decode(addr, &ty.unfold().unwrap(), body);
+                self.recursive.pop();
+                result
+            }
+            // The type has already been unfolded down to here, so it's the
+            // whole recursive type again.
+            (Layout::RecurId { id, unrolled: _ }, Ty::Recursive(_)) => {
+                let unbound = MemoryError::Shape(ShapeError::UnboundRecurId(*id));
+                let idx =
+                    self.recursive.len().checked_sub(id.index() as usize + 1).ok_or(unbound)?;
+                // The body's own back-references are relative to where it
+                // was defined.
+                let body = self.recursive[idx];
+                let inner = self.recursive.split_off(idx + 1);
+                let result = self.decode(addr, &ty.unfold().unwrap(), body);
+                self.recursive.extend(inner);
+                result
+            }
+            (_, Ty::RecurId(id)) => Err(MemoryError::Shape(ShapeError::UnboundRecurId(*id))),
+            _ => panic!("type {} is not the lowered layout {}", ty, layout),
+        }
+    }
+
+    fn decode_tagged(
+        &mut self,
+        addr: u64,
+        ty: &Ty,
+        tagged: &'l TaggedLayout,
+    ) -> Result<Value, MemoryError> {
+        let TaggedLayout { tag, variants } = tagged;
+        match tag {
+            TagLayout::Direct { ty: tag_ty, values, niches: _, placement: _ } => {
+                let (data_offset, members, tag_offset, _) = data_and_tag(ty)?;
+                let tag_addr = addr + tag_offset;
+                let tag = self.memory.read_int(tag_addr, tag_ty.shape().size.bytes())?;
+                let variant = variant_named(values, tag)
+                    .ok_or(MemoryError::UnknownTag { addr: tag_addr, tag })?;
+                let data =
+                    self.decode(addr + data_offset, &members[variant], &variants[variant])?;
+                Ok(Value::Record(map! { "data" => data, "tag" => Value::Int(*tag_ty, tag) }))
+            }
+            TagLayout::Niche { path, ty: tag_ty, values } => {
+                let tag = self.read_at_path(addr, ty, path.clone(), *tag_ty)?;
+                if variant_named(values, tag).is_some() {
+                    return Ok(niche_value(path.clone(), *tag_ty, tag));
+                }
+                let dataful = is_nicheable(variants).into_field().unwrap();
+                self.decode(addr, ty, dataful)
+            }
+            TagLayout::MultiNiche { holder, hoisted: _, field, path, ty: tag_ty, values } => {
+                let (data_offset, members, tag_offset, tag_field_ty) = data_and_tag(ty)?;
+                let tag = self.read_at_path(addr, ty, path.clone(), *tag_ty)?;
+                let (variant, tag) = match variant_named(values, tag) {
+                    Some(variant) => {
+                        // Strip the leading `{tag}` projection, like lowering does.
+                        let (_, tag_path) = path.clone().split_outermost().unwrap();
+                        (variant, niche_value(tag_path, *tag_ty, tag))
+                    }
+                    None => (holder, self.decode(addr + tag_offset, &tag_field_ty, field)?),
+                };
+                let data =
+                    self.decode(addr + data_offset, &members[variant], &variants[variant])?;
+                Ok(Value::Record(map! { "data" => data, "tag" => tag }))
+            }
+            TagLayout::PointerTag { bits, values } => {
+                let members = match ty.unfolded() {
+                    Ty::Ptr(pointee) => match *pointee {
+                        Ty::UntaggedUnion(members) => members,
+                        ty => panic!("type {} is not a union", ty),
+                    },
+                    ty => panic!("type {} is not a pointer", ty),
+                };
+                let ptr = self.memory.read_int(addr, PTR_SIZE)?;
+                let tag = ptr & ((1 << bits) - 1);
+                let variant =
+                    variant_named(values, tag).ok_or(MemoryError::UnknownTag { addr, tag })?;
+                match &variants[variant] {
+                    Layout::Ptr { pointee, niches: _ } => {
+                        let start = ptr - tag;
+                        if !self.memory.allocations.contains_key(&start) {
+                            return Err(MemoryError::Dangling { addr, ptr });
+                        }
+                        let boxed = Box::new(self.follow(start, &members[variant], pointee)?);
+                        match tag {
+                            0 => Ok(Value::Box(boxed)),
+                            _ => Ok(Value::TaggedBox(boxed, tag)),
+                        }
+                    }
+                    // A null pointer standing in for a zero-sized variant.
+                    _ => Ok(Value::Int(IntTy::U64, ptr)),
+                }
+            }
+        }
+    }
+
+    fn follow(&mut self, start: u64, ty: &Ty, layout: &'l Layout) -> Result<Value, MemoryError> {
+        if self.pointees.contains(&start) {
+            return Err(MemoryError::Cycle { addr: start });
+        }
+        self.pointees.push(start);
+        let result = self.decode(start, ty, layout);
+        self.pointees.pop();
+        result
+    }
+
+    /// Read the scalar of type `int_ty` at `path` in the value of type `ty`
+    /// at `addr`.
+    fn read_at_path(
+        &self,
+        mut addr: u64,
+        ty: &Ty,
+        path: TagPath,
+        int_ty: IntTy,
+    ) -> Result<u64, MemoryError> {
+        let mut projs = path.rfold(vec![], |mut projs, proj| {
+            projs.push(proj);
+            projs
+        });
+        let mut ty = ty.clone();
+        while let Some(proj) = projs.pop() {
+            let (offset, field_ty) = match proj {
+                ValueProj::Field(name) => field_at(&ty, &name)?,
+                ValueProj::Tag => field_at(&ty, &"tag".into())?,
+                // All members start at the start of the union.
+                ValueProj::Variant { repr: VariantRepr::Wrapper, name } => {
+                    let (data_offset, mut members, _, _) = data_and_tag(&ty)?;
+                    (data_offset, members.remove(&name).unwrap())
+                }
+                ValueProj::Variant { repr: VariantRepr::Transparent, name: _ } => continue,
+            };
+            addr += offset;
+            ty = field_ty;
+        }
+        self.memory.read_int(addr, int_ty.shape().size.bytes())
+    }
+}
+
+/// A hex dump of each allocation, one line per stored scalar or run of
+/// unused bytes, or per eight bytes if nothing was stored by
+/// [`Memory::encode()`].
+impl Display for Memory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, (start, alloc)) in self.allocations().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let size = alloc.bytes.len() as u64;
+            write!(f, "{:#x}: {}, align {}", start, Size::from_bytes(size), alloc.align)?;
+            let row = |offset: u64, size: u64, descr: String| {
+                let bytes = &alloc.bytes[offset as usize..(offset + size) as usize];
+                let hex = bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>();
+                vec![format!("  {:#x}", start + offset), hex.join(" "), descr]
+            };
+            let mut rows = vec![];
+            // Bytes that weren't stored by `encode` have no annotations.
+            if alloc.scalars.is_empty() {
+                for offset in (0..size).step_by(8) {
+                    rows.push(row(offset, (size - offset).min(8), String::new()));
+                }
+            }
+            let mut scalars: Vec<_> = alloc.scalars.iter().collect();
+            scalars.sort_by_key(|s| s.offset);
+            let mut offset = if scalars.is_empty() { size } else { 0 };
+            for scalar in scalars {
+                if scalar.offset > offset {
+                    rows.push(row(offset, scalar.offset - offset, "(unused)".to_owned()));
+                }
+                rows.push(row(
+                    scalar.offset,
+                    scalar.size,
+                    format!("{} = {}", scalar.path, scalar.descr),
+                ));
+                offset = scalar.offset + scalar.size;
+            }
+            if size > offset {
+                rows.push(row(offset, size - offset, "(unused)".to_owned()));
+            }
+            if !rows.is_empty() {
+                write!(f, "\n{}", display_table(&rows))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for MemoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MemoryError::Mismatch { value, ty } => {
+                write!(f, "value {} doesn't have type {}", value, ty)
+            }
+            MemoryError::Shape(err) => err.fmt(f),
+            MemoryError::OutOfBounds { addr, size } => {
+                write!(f, "{} bytes at {:#x} are outside of any allocation", size, addr)
+            }
+            MemoryError::Dangling { addr, ptr } => write!(
+                f,
+                "the pointer {:#x} at {:#x} doesn't point to the start of an allocation",
+                ptr, addr
+            ),
+            MemoryError::UnknownTag { addr, tag } => {
+                write!(f, "the tag {} at {:#x} doesn't belong to any variant", tag, addr)
+            }
+            MemoryError::Cycle { addr } => {
+                write!(f, "the pointers from the value lead back to the allocation at {:#x}", addr)
+            }
+        }
+    }
+}
@@ -2,7 +2,8 @@ mod scope;
 
 use std::iter::Peekable;
 
-use crate::hir::{Expr, Pat, Ty, Var};
+use crate::hir::{Expr, Pat, Repr, Ty, Var};
+use crate::int_ty::IntTy;
 use crate::name::Name;
 use crate::util::Map;
 
@@ -13,6 +14,8 @@ pub struct Parser {
     tokens: Peekable<Tokenizer>,
     aliases: Map<Name, Ty>,
     ty_scopes: ScopeStack,
+    /// The declaration order of the fields of the last record type parsed.
+    record_order: Vec<Name>,
 }
 
 impl Parser {
@@ -35,6 +38,7 @@ impl Parser {
             tokens: Tokenizer::new(src).peekable(),
             aliases: Map::new(),
             ty_scopes: ScopeStack::empty(),
+            record_order: vec![],
         }
     }
 
@@ -52,12 +56,7 @@ impl Parser {
                 self.parse_expr()
             }
             Token::KwMatch => self.parse_expr_match(),
-            Token::Number(text) => {
-                let u = text.parse().unwrap_or_else(|_| {
-                    error(format!("invalid number: {}", text.escape_default()))
-                });
-                Expr::U64(u)
-            }
+            Token::Number(text) => parse_int_literal(&text),
             Token::Ident(name) => Expr::Var(self.parse_var_after_name(Name::from(name))),
             tok => error(format!("expected expression, found {}", tok)),
         }
@@ -177,13 +176,14 @@ impl Parser {
             Token::LBrace => self.parse_ty_record(),
             Token::LAngle => self.parse_ty_variant(),
             Token::KwMu => self.parse_ty_recur(),
+            Token::Hash => self.parse_ty_with_attrs(),
             Token::Ident(s) => {
                 let name = Name::from(s);
                 self.aliases
                     .get(&name)
                     .cloned()
                     .or_else(|| self.ty_scopes.lookup(&name).map(Ty::Named))
-                    .or_else(|| if name.as_user() == Some("U64") { Some(Ty::U64) } else { None })
+                    .or_else(|| name.as_user().and_then(IntTy::from_name).map(Ty::Int))
                     .unwrap_or_else(|| error(format!("name not found: {}", name)))
             }
             tok => error(format!("expected type, found {}", tok)),
@@ -199,28 +199,111 @@ impl Parser {
 
     fn parse_ty_record(&mut self) -> Ty {
         let mut fields = map! {};
+        let mut order = vec![];
 
         self.parse_delimited(Token::Comma, Token::RBrace, |this| {
             let name = this.parse_name();
             this.eat(Token::Colon);
             let ty = this.parse_ty();
+            order.push(name.clone());
             fields.insert(name, ty);
         });
 
+        self.record_order = order;
         Ty::Record(fields)
     }
 
     fn parse_ty_variant(&mut self) -> Ty {
         let mut variants = map! {};
+        let mut discriminants = map! {};
 
         self.parse_delimited(Token::VertPipe, Token::RAngle, |this| {
             let name = this.parse_name();
+            if this.check(Token::Eq) {
+                this.bump();
+                discriminants.insert(name.clone(), this.parse_discriminant());
+            }
             this.eat(Token::KwOf);
             let ty = this.parse_ty();
             variants.insert(name, ty);
         });
 
-        Ty::Variant(variants)
+        if discriminants.is_empty() {
+            Ty::Variant(variants)
+        } else {
+            Ty::Repr(Box::new(Ty::Variant(variants)), Repr { discriminants, ..Repr::default() })
+        }
+    }
+
+    fn parse_discriminant(&mut self) -> u64 {
+        match self.bump() {
+            Token::Number(text) => text.parse().unwrap_or_else(|_| {
+                error(format!("invalid discriminant: {}", text.escape_default()))
+            }),
+            tok => error(format!("expected discriminant, found {}", tok)),
+        }
+    }
+
+    /// Parse attributes, like `#[repr(C)] #[packed]`, after the first `#`,
+    /// and the record or variant type that they apply to.
+    fn parse_ty_with_attrs(&mut self) -> Ty {
+        let mut repr = Repr::default();
+        loop {
+            self.eat(Token::LBracket);
+            match self.bump() {
+                Token::Ident(attr) if attr == "repr" => {
+                    self.eat(Token::LParen);
+                    self.parse_delimited(Token::Comma, Token::RParen, |this| match this.bump() {
+                        Token::Ident(arg) if arg == "C" => repr.c = true,
+                        Token::Ident(arg) => match IntTy::from_suffix(&arg) {
+                            Some(ty) => repr.tag = Some(ty),
+                            None => error(format!("unknown repr: {}", arg)),
+                        },
+                        tok => error(format!("expected repr, found {}", tok)),
+                    });
+                }
+                Token::Ident(attr) if attr == "no_niche" => repr.no_niche = true,
+                Token::Ident(attr) if attr == "packed" => repr.packed = true,
+                tok => error(format!("unknown attribute: {}", tok)),
+            }
+            self.eat(Token::RBracket);
+            if !self.check(Token::Hash) {
+                break;
+            }
+            self.bump();
+        }
+
+        // Only records parsed here, not aliases, have a declaration order.
+        self.record_order.clear();
+        let (inner, mut repr) = match self.parse_ty() {
+            // Explicit discriminants, or attributes of an alias.
+            Ty::Repr(inner, inner_repr) => (
+                inner,
+                Repr {
+                    c: repr.c || inner_repr.c,
+                    tag: repr.tag.or(inner_repr.tag),
+                    no_niche: repr.no_niche || inner_repr.no_niche,
+                    packed: repr.packed || inner_repr.packed,
+                    discriminants: inner_repr.discriminants,
+                    field_order: inner_repr.field_order,
+                },
+            ),
+            ty => (Box::new(ty), repr),
+        };
+        let order = std::mem::take(&mut self.record_order);
+        if repr.c && matches!(*inner, Ty::Record(_)) && !order.windows(2).all(|w| w[0] < w[1]) {
+            repr.field_order = order;
+        }
+        match *inner {
+            Ty::Record(_) if repr.tag.is_some() => {
+                error("integer reprs only apply to variant types".to_owned())
+            }
+            Ty::Variant(_) if repr.packed => {
+                error("`packed` only applies to record types".to_owned())
+            }
+            Ty::Record(_) | Ty::Variant(_) => Ty::Repr(inner, repr),
+            ty => error(format!("attributes only apply to record and variant types, not {}", ty)),
+        }
     }
 
     fn parse_ty_recur(&mut self) -> Ty {
@@ -329,9 +412,7 @@ impl Iterator for Tokenizer {
 
         self.start = self.current;
 
-        if self.peek().is_none() {
-            return None;
-        }
+        self.peek()?;
 
         match self.bump().unwrap() {
             '=' if self.check('>') => {
@@ -342,6 +423,7 @@ impl Iterator for Tokenizer {
             ':' => Some(Token::Colon),
             ',' => Some(Token::Comma),
             ';' => Some(Token::Semi),
+            '#' => Some(Token::Hash),
             '.' => Some(Token::Dot),
             '|' => Some(Token::VertPipe),
 
@@ -358,6 +440,10 @@ impl Iterator for Tokenizer {
                 while self.check_fn(|c| ('0'..='9').contains(&c)) {
                     self.bump();
                 }
+                // An optional suffix, like `_u8` or `u8`.
+                while self.check_fn(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    self.bump();
+                }
                 let lexeme = self.mk_lexeme();
                 Some(Token::Number(lexeme))
             }
@@ -436,6 +522,29 @@ impl Tokenizer {
     }
 }
 
+/// Parse an integer literal with an optional type suffix, like `5`, `5u8`, or `5_u8`.
+///
+/// Unsuffixed literals are `U64`s.
+fn parse_int_literal(text: &str) -> Expr {
+    let digits_end = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (digits, suffix) = text.split_at(digits_end);
+    let suffix = suffix.strip_prefix('_').unwrap_or(suffix);
+    let ty = if suffix.is_empty() {
+        IntTy::U64
+    } else {
+        IntTy::from_suffix(suffix).unwrap_or_else(|| {
+            error(format!("invalid integer suffix: {}", suffix.escape_default()))
+        })
+    };
+    let value: u64 = digits
+        .parse()
+        .unwrap_or_else(|_| error(format!("invalid number: {}", text.escape_default())));
+    if value > ty.max_value() {
+        error(format!("literal out of range for {}: {}", ty, value));
+    }
+    Expr::Int(ty, value)
+}
+
 // TODO: handle properly instead of panicking
 #[track_caller]
 fn error(msg: String) -> ! {
@@ -450,6 +559,7 @@ enum Token {
     Colon,
     Comma,
     Semi,
+    Hash,
     Dot,
     VertPipe,
 
@@ -494,6 +604,7 @@ impl std::fmt::Display for Token {
                 Token::Colon => ":",
                 Token::Comma => ",",
                 Token::Semi => ";",
+                Token::Hash => "#",
                 Token::Dot => ".",
                 Token::VertPipe => "|",
                 Token::LParen => "(",
@@ -0,0 +1,488 @@
+//! Translating types to Rust, to check our layouts against rustc's.
+//!
+//! Records become structs, and variant types become enums whose variants hold
+//! their field as a single tuple field (or nothing, if it's `{}`). Recursive
+//! types become named types: the record or enum that is their body, or a tuple
+//! struct wrapping it.
+//!
+//! The generated test program prints `size_of`, `align_of`, and the bytes of
+//! some values. [`cross_check()`] compiles and runs it with the local `rustc`,
+//! and compares the results with the `rust` layout strategy.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter, Write};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::check::{all_values, value_to_expr};
+use crate::eval::eval_root_expr;
+use crate::hir::{self, Repr};
+use crate::int_ty::IntTy;
+use crate::layout_of::{strategy_by_name, LayoutCx};
+use crate::lir::{self, Shape};
+use crate::lower::lower_root_expr_in;
+use crate::name::Name;
+use crate::util::Map;
+
+/// At most this many values are printed by the test program.
+pub const MAX_VALUES: usize = 16;
+
+/// How many times recursive types are unfolded to make sample values.
+const SAMPLE_DEPTH: u32 = 3;
+
+/// The Rust declarations of a type.
+pub struct RustDecls {
+    /// The structs and enums, in order of their names.
+    pub decls: Vec<String>,
+    /// The root type, like `T0` or `u8`.
+    pub root: String,
+    /// The names of the types that have been declared, by the type and the
+    /// names of the enclosing recursive types.
+    names: HashMap<(String, Vec<String>), String>,
+    /// Whether an enum with fields has explicit discriminants, which needs a
+    /// feature on older toolchains.
+    needs_discriminant_feature: bool,
+}
+
+/// The enclosing recursive types, innermost last, with their Rust names.
+type Env<'a> = Vec<(String, &'a hir::Ty)>;
+
+fn key(ty: &hir::Ty, env: &Env) -> (String, Vec<String>) {
+    (ty.to_string(), env.iter().map(|(name, _)| name.clone()).collect())
+}
+
+pub fn rust_decls(ty: &hir::Ty) -> RustDecls {
+    let mut decls = RustDecls {
+        decls: vec![],
+        root: String::new(),
+        names: HashMap::new(),
+        needs_discriminant_feature: false,
+    };
+    decls.root = decls.rust_ty(ty, &mut vec![]);
+    decls
+}
+
+impl RustDecls {
+    /// The Rust type of `ty`, declaring structs and enums as needed.
+    fn rust_ty<'a>(&mut self, ty: &'a hir::Ty, env: &mut Env<'a>) -> String {
+        match ty.without_repr() {
+            hir::Ty::Int(int_ty) => int_ty.suffix().to_owned(),
+            hir::Ty::Box(boxed) => format!("Box<{}>", self.rust_ty(boxed, env)),
+            hir::Ty::Record(fields) if fields.is_empty() => "()".to_owned(),
+            hir::Ty::Record(_) | hir::Ty::Variant(_) => {
+                self.declare(ty, env, |this, name, env| this.aggregate_decl(ty, name, env))
+            }
+            hir::Ty::Recursive(body) => self.declare(ty, env, |this, name, env| {
+                env.push((name.to_owned(), ty));
+                let decl = if names_body(body) {
+                    this.aggregate_decl(body, name, env)
+                } else {
+                    format!("struct {}({});", name, this.rust_ty(body, env))
+                };
+                env.pop();
+                decl
+            }),
+            hir::Ty::Named(id) => env[env.len() - 1 - id.index() as usize].0.clone(),
+            hir::Ty::Repr(..) => unreachable!("nested representation attributes"),
+        }
+    }
+
+    /// Declare a struct or enum for `ty`, unless it has been declared
+    /// already, and return its name.
+    fn declare<'a>(
+        &mut self,
+        ty: &'a hir::Ty,
+        env: &mut Env<'a>,
+        decl: impl FnOnce(&mut Self, &str, &mut Env<'a>) -> String,
+    ) -> String {
+        if let Some(name) = self.names.get(&key(ty, env)) {
+            return name.clone();
+        }
+        let name = format!("T{}", self.names.len());
+        self.names.insert(key(ty, env), name.clone());
+        // Reserve a slot, so that the declarations are in order of their names.
+        let idx = self.decls.len();
+        self.decls.push(String::new());
+        self.decls[idx] = decl(self, &name, env);
+        name
+    }
+
+    /// The declaration of a record or variant type, named `name`.
+    fn aggregate_decl<'a>(&mut self, ty: &'a hir::Ty, name: &str, env: &mut Env<'a>) -> String {
+        let (inner, repr) = match ty {
+            hir::Ty::Repr(inner, repr) => (&**inner, repr.clone()),
+            ty => (ty, Repr::default()),
+        };
+        let mut decl = String::new();
+        match inner {
+            hir::Ty::Record(fields) => {
+                decl.push_str(&repr_attrs(&repr, None));
+                writeln!(decl, "struct {} {{", name).unwrap();
+                for (field, ty) in repr.in_declaration_order(fields.iter().collect()) {
+                    writeln!(decl, "    {}: {},", ident(field), self.rust_ty(ty, env)).unwrap();
+                }
+            }
+            hir::Ty::Variant(variants) => {
+                let dataful = variants.values().any(|ty| !is_unit(ty));
+                // Rust numbers variants from the previous one, rather than
+                // from the smallest unused value, so all of them get explicit
+                // discriminants.
+                let discriminants = if repr.discriminants.is_empty() {
+                    Map::new()
+                } else {
+                    repr.tag_values(variants.keys())
+                };
+                // Rust only allows discriminants on enums with fields if they
+                // have a primitive representation.
+                let implied_tag = match (&repr, discriminants.values().max()) {
+                    (Repr { c: false, tag: None, .. }, Some(&max)) if dataful => {
+                        Some(IntTy::fitting(max))
+                    }
+                    _ => None,
+                };
+                if dataful && !discriminants.is_empty() {
+                    self.needs_discriminant_feature = true;
+                }
+                // Enums without variants can't have a representation.
+                if variants.is_empty() {
+                    return format!("enum {} {{}}", name);
+                }
+                decl.push_str(&repr_attrs(&repr, implied_tag));
+                writeln!(decl, "enum {} {{", name).unwrap();
+                for (variant, ty) in variants {
+                    write!(decl, "    {}", ident(variant)).unwrap();
+                    if !is_unit(ty) {
+                        write!(decl, "({})", self.rust_ty(ty, env)).unwrap();
+                    }
+                    if let Some(value) = discriminants.get(variant) {
+                        write!(decl, " = {}", value).unwrap();
+                    }
+                    decl.push_str(",\n");
+                }
+            }
+            ty => unreachable!("not a record or variant type: {}", ty),
+        }
+        decl.push('}');
+        decl
+    }
+
+    /// A Rust expression that evaluates to `value`, of type `ty`.
+    fn rust_value<'a>(&self, value: &hir::Value, ty: &'a hir::Ty, env: &mut Env<'a>) -> String {
+        match (value, ty.without_repr()) {
+            (hir::Value::Int(int_ty, n), _) => format!("{}{}", n, int_ty.suffix()),
+            (hir::Value::Box(boxed), hir::Ty::Box(ty)) => {
+                format!("Box::new({})", self.rust_value(boxed, ty, env))
+            }
+            (hir::Value::Record(fields), hir::Ty::Record(_)) if fields.is_empty() => {
+                "()".to_owned()
+            }
+            (_, hir::Ty::Record(_) | hir::Ty::Variant(_)) => {
+                let name = &self.names[&key(ty, env)];
+                self.aggregate_value(value, ty, name, env)
+            }
+            (_, hir::Ty::Recursive(body)) => {
+                let name = self.names[&key(ty, env)].clone();
+                env.push((name.clone(), ty));
+                let value = if names_body(body) {
+                    self.aggregate_value(value, body, &name, env)
+                } else {
+                    format!("{}({})", name, self.rust_value(value, body, env))
+                };
+                env.pop();
+                value
+            }
+            (_, hir::Ty::Named(id)) => {
+                // Go back to where the recursive type was declared.
+                let idx = env.len() - 1 - id.index() as usize;
+                let recursive = env[idx].1;
+                self.rust_value(value, recursive, &mut env[..idx].to_vec())
+            }
+            (value, ty) => unreachable!("value {} is not of type {}", value, ty),
+        }
+    }
+
+    /// A value of a record or variant type, named `name`.
+    fn aggregate_value<'a>(
+        &self,
+        value: &hir::Value,
+        ty: &'a hir::Ty,
+        name: &str,
+        env: &mut Env<'a>,
+    ) -> String {
+        match (value, ty.without_repr()) {
+            (hir::Value::Record(values), hir::Ty::Record(fields)) => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(field, ty)| {
+                        format!("{}: {}", ident(field), self.rust_value(&values[field], ty, env))
+                    })
+                    .collect();
+                format!("{} {{ {} }}", name, fields.join(", "))
+            }
+            (hir::Value::Variant { variant, field }, hir::Ty::Variant(variants)) => {
+                let ty = &variants[variant];
+                if is_unit(ty) {
+                    format!("{}::{}", name, ident(variant))
+                } else {
+                    let field = self.rust_value(field, ty, env);
+                    format!("{}::{}({})", name, ident(variant), field)
+                }
+            }
+            (value, ty) => unreachable!("value {} is not of type {}", value, ty),
+        }
+    }
+}
+
+/// Whether the recursive type with this body is named like its body, rather
+/// than wrapping it.
+fn names_body(body: &hir::Ty) -> bool {
+    match body.without_repr() {
+        hir::Ty::Record(fields) => !fields.is_empty(),
+        hir::Ty::Variant(_) => true,
+        _ => false,
+    }
+}
+
+fn is_unit(ty: &hir::Ty) -> bool {
+    matches!(ty.without_repr(), hir::Ty::Record(fields) if fields.is_empty())
+}
+
+fn repr_attrs(repr: &Repr, implied_tag: Option<IntTy>) -> String {
+    let mut reprs = vec![];
+    if repr.c {
+        reprs.push("C");
+    }
+    if let Some(tag) = repr.tag.or(implied_tag) {
+        reprs.push(tag.suffix());
+    }
+    if repr.packed {
+        reprs.push("packed");
+    }
+    let mut attrs = String::new();
+    if !reprs.is_empty() {
+        writeln!(attrs, "#[repr({})]", reprs.join(", ")).unwrap();
+    }
+    if repr.no_niche {
+        attrs.push_str("// `#[no_niche]` has no stable equivalent in Rust.\n");
+    }
+    attrs
+}
+
+/// `name` as a Rust identifier, escaped if it's a keyword.
+fn ident(name: &Name) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "box", "break", "const", "continue", "dyn", "else", "enum",
+        "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "static", "struct", "trait", "true", "try", "type",
+        "unsafe", "use", "where", "while", "yield",
+    ];
+    let name = name.to_string();
+    if KEYWORDS.contains(&name.as_str()) {
+        format!("r#{}", name)
+    } else {
+        name
+    }
+}
+
+/// The values that the test program prints: all of them if there are only a
+/// few, and otherwise some samples.
+pub fn test_values(ty: &hir::Ty) -> Vec<hir::Value> {
+    let mut values = match all_values(ty) {
+        Some(values) if values.len() <= MAX_VALUES => values,
+        _ => sample_values(ty, SAMPLE_DEPTH),
+    };
+    values.truncate(MAX_VALUES);
+    values
+}
+
+/// Some values of `ty`: the smallest and largest integers, every variant,
+/// and recursive types unfolded up to `depth` times.
+fn sample_values(ty: &hir::Ty, depth: u32) -> Vec<hir::Value> {
+    let mut values = match ty {
+        hir::Ty::Int(int_ty) => {
+            vec![hir::Value::Int(*int_ty, 0), hir::Value::Int(*int_ty, int_ty.max_value())]
+        }
+        hir::Ty::Box(boxed) => sample_values(boxed, depth)
+            .into_iter()
+            .map(|value| hir::Value::Box(Box::new(value)))
+            .collect(),
+        // Vary all fields at once, rather than trying every combination.
+        hir::Ty::Record(fields) => {
+            let field_values: Vec<_> =
+                fields.iter().map(|(name, ty)| (name, sample_values(ty, depth))).collect();
+            if field_values.iter().any(|(_, values)| values.is_empty()) {
+                return vec![];
+            }
+            let count = field_values.iter().map(|(_, values)| values.len()).max().unwrap_or(1);
+            (0..count)
+                .map(|i| {
+                    let record = field_values
+                        .iter()
+                        .map(|(name, values)| {
+                            ((*name).clone(), values[i.min(values.len() - 1)].clone())
+                        })
+                        .collect();
+                    hir::Value::Record(record)
+                })
+                .collect()
+        }
+        hir::Ty::Variant(variants) => variants
+            .iter()
+            .flat_map(|(variant, ty)| {
+                sample_values(ty, depth).into_iter().map(|field| hir::Value::Variant {
+                    variant: variant.clone(),
+                    field: Box::new(field),
+                })
+            })
+            .collect(),
+        // Past the depth, only values that don't recurse are left.
+        hir::Ty::Recursive(_) if depth == 0 => vec![],
+        hir::Ty::Recursive(_) => sample_values(&ty.unfold().unwrap(), depth - 1),
+        hir::Ty::Named(_) => unreachable!("unfolded types are closed"),
+        hir::Ty::Repr(inner, _) => sample_values(inner, depth),
+    };
+    values.truncate(MAX_VALUES);
+    values
+}
+
+/// A Rust program that declares `ty` and prints its size, its alignment, and
+/// the bytes of each of `values`, one per line.
+pub fn rust_program(ty: &hir::Ty, values: &[hir::Value]) -> String {
+    let decls = rust_decls(ty);
+    let mut program = String::from("#![allow(dead_code, non_camel_case_types)]\n");
+    if decls.needs_discriminant_feature {
+        program.push_str("#![feature(arbitrary_enum_discriminant)]\n");
+    }
+    writeln!(program, "\n// {}", ty).unwrap();
+    for decl in &decls.decls {
+        writeln!(program, "\n{}", decl).unwrap();
+    }
+    program.push_str(
+        "
+/// The bytes of `value`, in hex. Padding is zero, unless the move copies
+/// whatever was in the padding of `value`.
+fn bytes<T>(value: T) -> String {
+    let mut slot = std::mem::MaybeUninit::<T>::zeroed();
+    slot.write(value);
+    let size = std::mem::size_of::<T>();
+    let bytes = unsafe { std::slice::from_raw_parts(slot.as_ptr().cast::<u8>(), size) };
+    bytes.iter().map(|b| format!(\"{:02x}\", b)).collect::<Vec<_>>().join(\" \")
+}
+",
+    );
+    writeln!(program, "\nfn main() {{").unwrap();
+    writeln!(program, "    println!(\"size {{}}\", std::mem::size_of::<{}>());", decls.root)
+        .unwrap();
+    writeln!(program, "    println!(\"align {{}}\", std::mem::align_of::<{}>());", decls.root)
+        .unwrap();
+    for value in values {
+        writeln!(program, "    // {}", value).unwrap();
+        let expr = decls.rust_value(value, ty, &mut vec![]);
+        writeln!(program, "    println!(\"value {{}}\", bytes::<{}>({}));", decls.root, expr)
+            .unwrap();
+    }
+    program.push('}');
+    program
+}
+
+/// The results of running the test program, next to our predictions.
+pub struct CrossCheck {
+    /// The size (including trailing padding, like `size_of`) and alignment
+    /// according to rustc.
+    pub rustc: (u64, u64),
+    /// The stride and alignment in the `rust` layout strategy.
+    pub predicted: (u64, u64),
+    /// Each value, with its bytes according to rustc, and its machine value
+    /// in the `rust` layout strategy.
+    pub values: Vec<(hir::Value, String, lir::Value)>,
+}
+
+impl CrossCheck {
+    pub fn layouts_match(&self) -> bool {
+        self.rustc == self.predicted
+    }
+}
+
+/// Compile and run the test program of `ty` with `rustc` (or `$RUSTC`), and
+/// compare the results with the `rust` layout strategy.
+pub fn cross_check(ty: &hir::Ty) -> Result<CrossCheck, String> {
+    let values = test_values(ty);
+    let output = run_program(&rust_program(ty, &values))?;
+    let mut lines = output.lines();
+    let mut number = |label: &str| {
+        let line = lines.next().unwrap_or_default();
+        line.strip_prefix(label).and_then(|n| n.trim().parse().ok()).ok_or_else(|| {
+            format!("expected '{} <n>' from the test program, got '{}'", label, line)
+        })
+    };
+    let rustc = (number("size")?, number("align")?);
+    let bytes: Vec<_> = lines.filter_map(|line| line.strip_prefix("value ")).collect();
+    if bytes.len() != values.len() {
+        return Err(format!("expected {} values from the test program", values.len()));
+    }
+
+    let mut layouts = LayoutCx::new(strategy_by_name("rust").unwrap());
+    let shape: Shape = layouts.layout_of(ty).shape();
+    let predicted = (shape.stride().bytes(), shape.align.bytes());
+    let values = values
+        .into_iter()
+        .zip(bytes)
+        .map(|(value, bytes)| {
+            let expr = lower_root_expr_in(value_to_expr(&value, ty), &mut layouts);
+            let machine = eval_root_expr(expr);
+            (value, bytes.to_owned(), machine)
+        })
+        .collect();
+    Ok(CrossCheck { rustc, predicted, values })
+}
+
+/// Compile `program` in a fresh temporary directory, run it, and return what
+/// it printed.
+fn run_program(program: &str) -> Result<String, String> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "type-layout-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let run = || {
+        std::fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+        let (src, exe) = (dir.join("main.rs"), dir.join("main"));
+        std::fs::write(&src, program).map_err(|err| err.to_string())?;
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+        let compiled = Command::new(&rustc)
+            .args(["--edition", "2021", "-o"])
+            .args([&exe, &src])
+            .output()
+            .map_err(|err| format!("couldn't run {}: {}", rustc, err))?;
+        if !compiled.status.success() {
+            let stderr = String::from_utf8_lossy(&compiled.stderr);
+            return Err(format!("rustc failed:\n{}", stderr.trim_end()));
+        }
+        let ran = Command::new(&exe).output().map_err(|err| err.to_string())?;
+        if !ran.status.success() {
+            return Err(format!("the test program failed with {}", ran.status));
+        }
+        Ok(String::from_utf8_lossy(&ran.stdout).into_owned())
+    };
+    let output = run();
+    let _ = std::fs::remove_dir_all(&dir);
+    output
+}
+
+impl Display for CrossCheck {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Self { rustc: (size, align), predicted: (stride, our_align), values } = self;
+        writeln!(f, "rustc:  size {}, align {}", size, align)?;
+        writeln!(f, "layout: size {}, align {}", stride, our_align)?;
+        if self.layouts_match() {
+            write!(f, "ok: the sizes and alignments match")?;
+        } else {
+            write!(f, "mismatch: the sizes or alignments differ")?;
+        }
+        for (value, bytes, machine) in values {
+            write!(f, "\n{}\n  rustc: {}\n  lir:   {}", value, bytes, machine)?;
+        }
+        Ok(())
+    }
+}
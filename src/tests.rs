@@ -6,6 +6,10 @@ use super::*;
 
 // HELPERS
 
+fn u64_ty() -> hir::Ty {
+    hir::Ty::Int(crate::int_ty::IntTy::U64)
+}
+
 fn unit_ty() -> hir::Ty {
     parse_ty("{}")
 }
@@ -73,7 +77,7 @@ fn empty_variant_lty() {
 #[test]
 fn bool_layout() {
     assert_display_snapshot!(layout_of(bool_ty()), @r###"
-    Variant(Tagged(tag: Direct(values: { False => 0, True => 1 }, niches: 2..=18446744073709551615), variants:
+    Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 2..=255), variants:
     | False => Aggregate {}
     | True => Aggregate {}
     ))
@@ -82,25 +86,20 @@ fn bool_layout() {
 
 #[test]
 fn maybe_empty_layout() {
-    assert_display_snapshot!(layout_of(maybe_empty_ty()), @r###"
-    Variant(Tagged(tag: Direct(values: { None => 0, Some => 1 }, niches: 2..=18446744073709551615), variants:
-    | None => Aggregate {}
-    | Some => Variant(Single(field: Aggregate {}))
-    ))
-    "###);
+    assert_display_snapshot!(layout_of(maybe_empty_ty()), @"Variant(Single(field: Aggregate {}))");
 }
 
 #[test]
 fn maybe_empty_lty() {
-    assert_display_snapshot!(crate::lower::lower_layout(layout_of(maybe_empty_ty())), @"{ data : union { None : {} | Some : {} }, tag : U64 }");
+    assert_display_snapshot!(crate::lower::lower_layout(layout_of(maybe_empty_ty())), @"{}");
 }
 
 #[test]
 fn maybe_bool_layout() {
     assert_display_snapshot!(layout_of(maybe_bool_ty()), @r###"
-    Variant(Tagged(tag: Niche(path: ({root} as(transparent) Some).{tag}, values: { None => 2 }), variants:
+    Variant(Tagged(tag: Niche(path: ({root} as(transparent) Some).{tag}, ty: U8, values: { None => 2 }), variants:
     | None => Aggregate {}
-    | Some => Variant(Tagged(tag: Direct(values: { False => 0, True => 1 }, niches: 3..=18446744073709551615), variants:
+    | Some => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 3..=255), variants:
     | False => Aggregate {}
     | True => Aggregate {}
     ))
@@ -111,7 +110,7 @@ fn maybe_bool_layout() {
 #[test]
 fn either_unit_unit_layout() {
     assert_display_snapshot!(layout_of(either_of(unit_ty(), unit_ty())), @r###"
-    Variant(Tagged(tag: Direct(values: { Left => 0, Right => 1 }, niches: 2..=18446744073709551615), variants:
+    Variant(Tagged(tag: Direct(ty: U8, values: { Left => 0, Right => 1 }, niches: 2..=255), variants:
     | Left => Aggregate {}
     | Right => Aggregate {}
     ))
@@ -121,9 +120,9 @@ fn either_unit_unit_layout() {
 #[test]
 fn either_unit_bool_layout() {
     assert_display_snapshot!(layout_of(either_of(unit_ty(), bool_ty())), @r###"
-    Variant(Tagged(tag: Niche(path: ({root} as(transparent) Right).{tag}, values: { Left => 2 }), variants:
+    Variant(Tagged(tag: Niche(path: ({root} as(transparent) Right).{tag}, ty: U8, values: { Left => 2 }), variants:
     | Left => Aggregate {}
-    | Right => Variant(Tagged(tag: Direct(values: { False => 0, True => 1 }, niches: 3..=18446744073709551615), variants:
+    | Right => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 3..=255), variants:
     | False => Aggregate {}
     | True => Aggregate {}
     ))
@@ -134,8 +133,8 @@ fn either_unit_bool_layout() {
 #[test]
 fn either_bool_unit_layout() {
     assert_display_snapshot!(layout_of(either_of(bool_ty(), unit_ty())), @r###"
-    Variant(Tagged(tag: Niche(path: ({root} as(transparent) Left).{tag}, values: { Right => 2 }), variants:
-    | Left => Variant(Tagged(tag: Direct(values: { False => 0, True => 1 }, niches: 3..=18446744073709551615), variants:
+    Variant(Tagged(tag: Niche(path: ({root} as(transparent) Left).{tag}, ty: U8, values: { Right => 2 }), variants:
+    | Left => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 3..=255), variants:
     | False => Aggregate {}
     | True => Aggregate {}
     ))
@@ -147,12 +146,12 @@ fn either_bool_unit_layout() {
 #[test]
 fn either_bool_bool_layout() {
     assert_display_snapshot!(layout_of(either_of(bool_ty(), bool_ty())), @r###"
-    Variant(Tagged(tag: Direct(values: { Left => 0, Right => 1 }, niches: 2..=18446744073709551615), variants:
-    | Left => Variant(Tagged(tag: Direct(values: { False => 0, True => 1 }, niches: 2..=18446744073709551615), variants:
+    Variant(Tagged(tag: Direct(ty: U8, values: { Left => 0, Right => 1 }, niches: 2..=255), variants:
+    | Left => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 2..=255), variants:
     | False => Aggregate {}
     | True => Aggregate {}
     ))
-    | Right => Variant(Tagged(tag: Direct(values: { False => 0, True => 1 }, niches: 2..=18446744073709551615), variants:
+    | Right => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 2..=255), variants:
     | False => Aggregate {}
     | True => Aggregate {}
     ))
@@ -163,11 +162,11 @@ fn either_bool_bool_layout() {
 #[test]
 fn either_unit_maybe_bool_layout() {
     assert_display_snapshot!(layout_of(either_of(unit_ty(), maybe_of(bool_ty()))), @r###"
-    Variant(Tagged(tag: Niche(path: (({root} as(transparent) Right) as(transparent) Some).{tag}, values: { Left => 3 }), variants:
+    Variant(Tagged(tag: Niche(path: (({root} as(transparent) Right) as(transparent) Some).{tag}, ty: U8, values: { Left => 3 }), variants:
     | Left => Aggregate {}
-    | Right => Variant(Tagged(tag: Niche(path: ({root} as(transparent) Some).{tag}, values: { None => 2 }), variants:
+    | Right => Variant(Tagged(tag: Niche(path: ({root} as(transparent) Some).{tag}, ty: U8, values: { None => 2 }), variants:
     | None => Aggregate {}
-    | Some => Variant(Tagged(tag: Direct(values: { False => 0, True => 1 }, niches: 4..=18446744073709551615), variants:
+    | Some => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 4..=255), variants:
     | False => Aggregate {}
     | True => Aggregate {}
     ))
@@ -176,12 +175,24 @@ fn either_unit_maybe_bool_layout() {
     "###);
 }
 
-// TODO: add tests for values of type Either<(), Maybe<Bool>>
+#[test]
+fn either_unit_maybe_bool_values() {
+    let prelude = "alias Bool = < False of {} | True of {} > in \
+                   alias MaybeBool = < None of {} | Some of Bool > in \
+                   alias T = < Left of {} | Right of MaybeBool > in";
+    let eval = |value: &str| {
+        let src = format!("{} {}", prelude, value);
+        format!("{} => {}", parse_and_eval(&src), parse_eval_and_decode(&src))
+    };
+    assert_display_snapshot!(eval("<Left = {}> as T"), @"{ tag = 3_u8 } => <Left = {}>");
+    assert_display_snapshot!(eval("<Right = <None = {}> as MaybeBool> as T"), @"{ tag = 2_u8 } => <Right = <None = {}>>");
+    assert_display_snapshot!(eval("<Right = <Some = <True = {}> as Bool> as MaybeBool> as T"), @"{ data = {}, tag = 1_u8 } => <Right = <Some = <True = {}>>>");
+}
 
 #[test]
 fn maybe_of_pair_of_unit_and_unit_layout() {
     assert_display_snapshot!(layout_of(maybe_of(pair_of(unit_ty(), unit_ty()))), @r###"
-    Variant(Tagged(tag: Direct(values: { None => 0, Some => 1 }, niches: 2..=18446744073709551615), variants:
+    Variant(Tagged(tag: Direct(ty: U8, values: { None => 0, Some => 1 }, niches: 2..=255), variants:
     | None => Aggregate {}
     | Some => Aggregate { 0 => Aggregate {}, 1 => Aggregate {} }
     ))
@@ -191,9 +202,9 @@ fn maybe_of_pair_of_unit_and_unit_layout() {
 #[test]
 fn maybe_of_pair_of_unit_and_bool_layout() {
     assert_display_snapshot!(layout_of(maybe_of(pair_of(unit_ty(), bool_ty()))), @r###"
-    Variant(Tagged(tag: Niche(path: ({root} as(transparent) Some).1.{tag}, values: { None => 2 }), variants:
+    Variant(Tagged(tag: Niche(path: ({root} as(transparent) Some).1.{tag}, ty: U8, values: { None => 2 }), variants:
     | None => Aggregate {}
-    | Some => Aggregate { 0 => Aggregate {}, 1 => Variant(Tagged(tag: Direct(values: { False => 0, True => 1 }, niches: 3..=18446744073709551615), variants:
+    | Some => Aggregate { 0 => Aggregate {}, 1 => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 3..=255), variants:
     | False => Aggregate {}
     | True => Aggregate {}
     )) }
@@ -204,9 +215,9 @@ fn maybe_of_pair_of_unit_and_bool_layout() {
 #[test]
 fn maybe_of_pair_of_bool_and_unit_layout() {
     assert_display_snapshot!(layout_of(maybe_of(pair_of(bool_ty(), unit_ty()))), @r###"
-    Variant(Tagged(tag: Niche(path: ({root} as(transparent) Some).0.{tag}, values: { None => 2 }), variants:
+    Variant(Tagged(tag: Niche(path: ({root} as(transparent) Some).0.{tag}, ty: U8, values: { None => 2 }), variants:
     | None => Aggregate {}
-    | Some => Aggregate { 0 => Variant(Tagged(tag: Direct(values: { False => 0, True => 1 }, niches: 3..=18446744073709551615), variants:
+    | Some => Aggregate { 0 => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 3..=255), variants:
     | False => Aggregate {}
     | True => Aggregate {}
     )), 1 => Aggregate {} }
@@ -217,12 +228,12 @@ fn maybe_of_pair_of_bool_and_unit_layout() {
 #[test]
 fn maybe_of_pair_of_bool_and_bool_layout() {
     assert_display_snapshot!(layout_of(maybe_of(pair_of(bool_ty(), bool_ty()))), @r###"
-    Variant(Tagged(tag: Niche(path: ({root} as(transparent) Some).0.{tag}, values: { None => 2 }), variants:
+    Variant(Tagged(tag: Niche(path: ({root} as(transparent) Some).0.{tag}, ty: U8, values: { None => 2 }), variants:
     | None => Aggregate {}
-    | Some => Aggregate { 0 => Variant(Tagged(tag: Direct(values: { False => 0, True => 1 }, niches: 3..=18446744073709551615), variants:
+    | Some => Aggregate { 0 => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 3..=255), variants:
     | False => Aggregate {}
     | True => Aggregate {}
-    )), 1 => Variant(Tagged(tag: Direct(values: { False => 0, True => 1 }, niches: 2..=18446744073709551615), variants:
+    )), 1 => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 2..=255), variants:
     | False => Aggregate {}
     | True => Aggregate {}
     )) }
@@ -233,7 +244,7 @@ fn maybe_of_pair_of_bool_and_bool_layout() {
 #[test]
 fn list_of_unit_layout() {
     assert_display_snapshot!(layout_of(list_of(unit_ty())), @r###"
-    Recursive(Variant(Tagged(tag: Niche(path: ({root} as(transparent) Cons).tl, values: { Nil => 0 }), variants:
+    Recursive(Variant(Tagged(tag: Niche(path: ({root} as(transparent) Cons).tl, ty: U64, values: { Nil => 0 }), variants:
     | Cons => Aggregate { hd => Aggregate {}, tl => Ptr(pointee: recur#0, niches: none) }
     | Nil => Aggregate {}
     )))
@@ -243,11 +254,11 @@ fn list_of_unit_layout() {
 #[test]
 fn list_of_bool_layout() {
     assert_display_snapshot!(layout_of(list_of(bool_ty())), @r###"
-    Recursive(Variant(Tagged(tag: Niche(path: ({root} as(transparent) Cons).hd.{tag}, values: { Nil => 2 }), variants:
-    | Cons => Aggregate { hd => Variant(Tagged(tag: Direct(values: { False => 0, True => 1 }, niches: 3..=18446744073709551615), variants:
+    Recursive(Variant(Tagged(tag: Niche(path: ({root} as(transparent) Cons).tl, ty: U64, values: { Nil => 0 }), variants:
+    | Cons => Aggregate { hd => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 2..=255), variants:
     | False => Aggregate {}
     | True => Aggregate {}
-    )), tl => Ptr(pointee: recur#0, niches: 0..=0) }
+    )), tl => Ptr(pointee: recur#0, niches: none) }
     | Nil => Aggregate {}
     )))
     "###);
@@ -256,11 +267,11 @@ fn list_of_bool_layout() {
 #[test]
 fn list_of_maybe_unit_layout() {
     assert_display_snapshot!(layout_of(list_of(maybe_of(unit_ty()))), @r###"
-    Recursive(Variant(Tagged(tag: Niche(path: ({root} as(transparent) Cons).hd.{tag}, values: { Nil => 2 }), variants:
-    | Cons => Aggregate { hd => Variant(Tagged(tag: Direct(values: { None => 0, Some => 1 }, niches: 3..=18446744073709551615), variants:
+    Recursive(Variant(Tagged(tag: Niche(path: ({root} as(transparent) Cons).tl, ty: U64, values: { Nil => 0 }), variants:
+    | Cons => Aggregate { hd => Variant(Tagged(tag: Direct(ty: U8, values: { None => 0, Some => 1 }, niches: 2..=255), variants:
     | None => Aggregate {}
     | Some => Aggregate {}
-    )), tl => Ptr(pointee: recur#0, niches: 0..=0) }
+    )), tl => Ptr(pointee: recur#0, niches: none) }
     | Nil => Aggregate {}
     )))
     "###);
@@ -269,15 +280,1351 @@ fn list_of_maybe_unit_layout() {
 #[test]
 fn list_of_maybe_bool_layout() {
     assert_display_snapshot!(layout_of(list_of(maybe_of(bool_ty()))), @r###"
-    Recursive(Variant(Tagged(tag: Niche(path: (({root} as(transparent) Cons).hd as(transparent) Some).{tag}, values: { Nil => 3 }), variants:
-    | Cons => Aggregate { hd => Variant(Tagged(tag: Niche(path: ({root} as(transparent) Some).{tag}, values: { None => 2 }), variants:
+    Recursive(Variant(Tagged(tag: Niche(path: ({root} as(transparent) Cons).tl, ty: U64, values: { Nil => 0 }), variants:
+    | Cons => Aggregate { hd => Variant(Tagged(tag: Niche(path: ({root} as(transparent) Some).{tag}, ty: U8, values: { None => 2 }), variants:
     | None => Aggregate {}
-    | Some => Variant(Tagged(tag: Direct(values: { False => 0, True => 1 }, niches: 4..=18446744073709551615), variants:
+    | Some => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 3..=255), variants:
     | False => Aggregate {}
     | True => Aggregate {}
     ))
-    )), tl => Ptr(pointee: recur#0, niches: 0..=0) }
+    )), tl => Ptr(pointee: recur#0, niches: none) }
     | Nil => Aggregate {}
     )))
     "###);
 }
+
+fn multi_dataful_ty() -> hir::Ty {
+    hir::Ty::Variant(map! {
+        "A" => hir::Ty::Record(map! { "x" => u64_ty(), "y" => bool_ty() }),
+        "B" => u64_ty(),
+        "C" => unit_ty(),
+    })
+}
+
+#[test]
+fn multi_dataful_layout() {
+    assert_display_snapshot!(layout_of(multi_dataful_ty()), @r###"
+    Variant(Tagged(tag: MultiNiche(holder: A.y, path: {root}.{tag}.{tag}, ty: U8, values: { B => 2, C => 3 }, field: Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 4..=255), variants:
+    | False => Aggregate {}
+    | True => Aggregate {}
+    ))), variants:
+    | A => Aggregate { x => U64(niches: none) }
+    | B => U64(niches: none)
+    | C => Aggregate {}
+    ))
+    "###);
+}
+
+#[test]
+fn multi_dataful_lty() {
+    assert_display_snapshot!(crate::lower::lower_layout(layout_of(multi_dataful_ty())), @"{ data : union { A : { x : U64 } | B : U64 | C : {} }, tag : { data : union { False : {} | True : {} }, tag : U8 } }");
+}
+
+#[test]
+fn multi_dataful_values() {
+    let prelude = "alias Bool = < False of {} | True of {} > in \
+                   alias T = < A of { x : U64, y : Bool } | B of U64 | C of {} > in";
+    let unpack = |value: &str| {
+        parse_and_eval(&format!(
+            "{} match {} {{ \
+               <A = a : {{ x : U64, y : Bool }}> as T => a : {{ x : U64, y : Bool }}, \
+               <B = b : U64> as T => {{ x = b : U64, y = <False = {{}}> as Bool }}, \
+               <C = c : {{}}> as T => {{ x = 0, y = <True = {{}}> as Bool }} \
+             }}",
+            prelude, value
+        ))
+    };
+
+    let a = "<A = { x = 5, y = <True = {}> as Bool }> as T";
+    assert_display_snapshot!(parse_and_eval(&format!("{} {}", prelude, a)), @"{ data = { x = 5_u64 }, tag = { data = {}, tag = 1_u8 } }");
+    assert_display_snapshot!(unpack(a), @"{ x = 5_u64, y = { data = {}, tag = 1_u8 } }");
+
+    let b = "<B = 7> as T";
+    assert_display_snapshot!(parse_and_eval(&format!("{} {}", prelude, b)), @"{ data = 7_u64, tag = { tag = 2_u8 } }");
+    assert_display_snapshot!(unpack(b), @"{ x = 7_u64, y = { data = {}, tag = 0_u8 } }");
+
+    let c = "<C = {}> as T";
+    assert_display_snapshot!(parse_and_eval(&format!("{} {}", prelude, c)), @"{ data = {}, tag = { tag = 3_u8 } }");
+    assert_display_snapshot!(unpack(c), @"{ x = 0_u64, y = { data = {}, tag = 1_u8 } }");
+}
+
+fn display_shape(ty: hir::Ty) -> String {
+    let layout = layout_of(ty);
+    let shape = layout.shape();
+    assert_eq!(shape, crate::lower::lower_layout(layout.clone()).shape());
+    match layout.field_offsets() {
+        Some(offsets) => format!("{}; offsets: {}", shape, util::display_map(offsets.iter())),
+        None => shape.to_string(),
+    }
+}
+
+#[test]
+fn shapes() {
+    assert_display_snapshot!(display_shape(unit_ty()), @"size: 0 bytes, align: 1 byte, stride: 0 bytes; offsets: {}");
+    assert_display_snapshot!(display_shape(bool_ty()), @"size: 1 byte, align: 1 byte, stride: 1 byte; offsets: { data => 0 bytes, tag => 0 bytes }");
+    assert_display_snapshot!(display_shape(maybe_bool_ty()), @"size: 1 byte, align: 1 byte, stride: 1 byte");
+    assert_display_snapshot!(display_shape(pair_of(u64_ty(), bool_ty())), @"size: 16 bytes, align: 8 bytes, stride: 16 bytes; offsets: { 0 => 0 bytes, 1 => 8 bytes }");
+    assert_display_snapshot!(display_shape(maybe_of(pair_of(u64_ty(), u64_ty()))), @"size: 24 bytes, align: 8 bytes, stride: 24 bytes; offsets: { data => 0 bytes, tag => 16 bytes }");
+    assert_display_snapshot!(display_shape(list_of(bool_ty())), @"size: 16 bytes, align: 8 bytes, stride: 16 bytes");
+    assert_display_snapshot!(display_shape(multi_dataful_ty()), @"size: 16 bytes, align: 8 bytes, stride: 16 bytes; offsets: { data => 0 bytes, tag => 8 bytes }");
+}
+
+const REORDER: &dyn crate::layout_of::LayoutStrategy = &crate::layout_of::NicheFilling {
+    reorder_fields: true,
+    niche_policy: crate::layout::extract::NichePolicy::LargestRemaining,
+};
+
+#[test]
+fn reordered_record_layout() {
+    let ty = hir::Ty::Record(map! { "a" => u64_ty(), "b" => bool_ty(), "c" => unit_ty() });
+    let layout = crate::layout_of::layout_of_with(ty, REORDER);
+    assert_display_snapshot!(layout, @r###"
+    Aggregate { c => Aggregate {}, a => U64(niches: none), b => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 2..=255), variants:
+    | False => Aggregate {}
+    | True => Aggregate {}
+    )) }
+    "###);
+    assert_display_snapshot!(crate::lower::lower_layout(layout), @"{ c : {}, a : U64, b : { data : union { False : {} | True : {} }, tag : U8 } }");
+}
+
+#[test]
+fn reordered_values() {
+    let eval = |src: &str| {
+        let lir_expr = crate::lower::lower_root_expr_with(parse(src), REORDER);
+        (lir_expr.ty(), eval_root_expr(lir_expr))
+    };
+    let prelude = "alias Bool = < False of {} | True of {} > in \
+                   alias R = { a : U64, b : Bool, c : {} } in \
+                   alias T = < A of R | B of U64 | C of {} > in";
+
+    let (ty, value) =
+        eval(&format!("{} {{ a = 1, b = <True = {{}}> as Bool, c = {{}} }}", prelude));
+    assert_display_snapshot!(ty, @"{ c : {}, a : U64, b : { data : union { False : {} | True : {} }, tag : U8 } }");
+    assert_display_snapshot!(value, @"{ a = 1_u64, b = { data = {}, tag = 1_u8 }, c = {} }");
+
+    let a = format!(
+        "{} match <A = {{ a = 1, b = <True = {{}}> as Bool, c = {{}} }}> as T {{ \
+           <A = r : R> as T => r : R, \
+           <B = n : U64> as T => {{ a = n : U64, b = <False = {{}}> as Bool, c = {{}} }}, \
+           <C = u : {{}}> as T => {{ a = 0, b = <False = {{}}> as Bool, c = {{}} }} \
+         }}",
+        prelude
+    );
+    let (ty, value) = eval(&a);
+    assert_display_snapshot!(ty, @"{ c : {}, a : U64, b : { data : union { False : {} | True : {} }, tag : U8 } }");
+    assert_display_snapshot!(value, @"{ a = 1_u64, b = { data = {}, tag = 1_u8 }, c = {} }");
+}
+
+#[test]
+fn sub_word_shapes() {
+    let ty = || parse_ty("{ a : U8, b : U64, c : U16, d : U32 }");
+    assert_display_snapshot!(display_shape(ty()), @"size: 24 bytes, align: 8 bytes, stride: 24 bytes; offsets: { a => 0 bytes, b => 8 bytes, c => 16 bytes, d => 20 bytes }");
+    let reordered = crate::layout_of::layout_of_with(ty(), REORDER);
+    assert_display_snapshot!(crate::lower::lower_layout(reordered.clone()), @"{ b : U64, d : U32, c : U16, a : U8 }");
+    assert_display_snapshot!(reordered.shape(), @"size: 16 bytes, align: 8 bytes, stride: 16 bytes");
+}
+
+#[test]
+fn sub_word_values() {
+    assert_display_snapshot!(parse_and_eval("{ a = 1u8, b = 2_u16, c = 3_u32, d = 4 }"), @"{ a = 1_u8, b = 2_u16, c = 3_u32, d = 4_u64 }");
+    assert_display_snapshot!(parse("255_u8").ty(), @"U8");
+}
+
+#[test]
+#[should_panic = "syntax error encountered"]
+fn int_literal_out_of_range() {
+    parse("256_u8");
+}
+
+#[test]
+fn many_variants_tag_ty() {
+    let variants = (0..300).map(|i| (format!("V{}", i).into(), unit_ty())).collect();
+    assert_display_snapshot!(display_shape(hir::Ty::Variant(variants)), @"size: 2 bytes, align: 2 bytes, stride: 2 bytes; offsets: { data => 0 bytes, tag => 0 bytes }");
+}
+
+const SPARE_BITS: &dyn crate::layout_of::LayoutStrategy = &crate::layout_of::SpareBits;
+
+#[test]
+fn pointer_tag_layout() {
+    let ty = || parse_ty("< A of Box[U64] | B of Box[{ x : U64 }] | C of {} >");
+    let layout = crate::layout_of::layout_of_with(ty(), SPARE_BITS);
+    assert_display_snapshot!(layout, @r###"
+    Variant(Tagged(tag: PointerTag(bits: 2, values: { A => 0, B => 1, C => 2 }), variants:
+    | A => Ptr(pointee: U64(niches: none), niches: 0..=0)
+    | B => Ptr(pointee: Aggregate(compact) { x => U64(niches: none) }, niches: 0..=0)
+    | C => Aggregate(compact) {}
+    ))
+    "###);
+    assert_display_snapshot!(layout.shape(), @"size: 8 bytes, align: 8 bytes, stride: 8 bytes");
+    assert_display_snapshot!(crate::lower::lower_layout(layout), @"Ptr(union { A : U64 | B : compact { x : U64 } | C : compact {} })");
+    assert_display_snapshot!(display_shape(ty()), @"size: 16 bytes, align: 8 bytes, stride: 16 bytes; offsets: { data => 0 bytes, tag => 8 bytes }");
+
+    // `U8`s are only byte-aligned, so there are no spare bits.
+    let ty = parse_ty("< A of Box[U8] | B of Box[U64] >");
+    let layout = crate::layout_of::layout_of_with(ty, SPARE_BITS);
+    assert_display_snapshot!(layout.shape(), @"size: 9 bytes, align: 8 bytes, stride: 16 bytes");
+}
+
+#[test]
+fn pointer_tag_values() {
+    let eval = |src: &str| {
+        let lir_expr = crate::lower::lower_root_expr_with(parse(src), SPARE_BITS);
+        eval_root_expr(lir_expr)
+    };
+    let prelude = "alias T = < A of Box[U64] | B of Box[{ x : U64 }] | C of {} > in";
+
+    let construct = |value: &str| eval(&format!("{} {}", prelude, value));
+    assert_display_snapshot!(construct("<A = box (1)> as T"), @"Box(1_u64)");
+    assert_display_snapshot!(construct("<B = box ({ x = 2 })> as T"), @"(Box({ x = 2_u64 }) | 1)");
+    assert_display_snapshot!(construct("<C = {}> as T"), @"2_u64");
+
+    let round_trip = |value: &str| {
+        eval(&format!(
+            "{} match {} as T {{ \
+               <A = a : Box[U64]> as T => {{ a = a : Box[U64], b = box ({{ x = 0 }}), tag = 0 }}, \
+               <B = b : Box[{{ x : U64 }}]> as T => {{ a = box (0), b = b : Box[{{ x : U64 }}], tag = 1 }}, \
+               <C = c : {{}}> as T => {{ a = box (0), b = box ({{ x = 0 }}), tag = 2 }} \
+             }}",
+            prelude, value
+        ))
+    };
+    assert_display_snapshot!(round_trip("<A = box (5)>"), @"{ a = Box(5_u64), b = Box({ x = 0_u64 }), tag = 0_u64 }");
+    assert_display_snapshot!(round_trip("<B = box ({ x = 2 })>"), @"{ a = Box(0_u64), b = Box({ x = 2_u64 }), tag = 1_u64 }");
+    assert_display_snapshot!(round_trip("<C = {}>"), @"{ a = Box(0_u64), b = Box({ x = 0_u64 }), tag = 2_u64 }");
+
+    // The binding of a zero-sized variant isn't read through the pointer.
+    let zst_binding = eval(&format!(
+        "{} match <C = {{}}> as T {{ \
+           <A = a : Box[U64]> as T => {{}}, \
+           <B = b : Box[{{ x : U64 }}]> as T => {{}}, \
+           <C = c : {{}}> as T => c : {{}} \
+         }}",
+        prelude
+    ));
+    assert_display_snapshot!(zst_binding, @"{}");
+}
+
+#[test]
+fn uninhabited_variants_layout() {
+    // `A`'s field contains an uninhabited field, so it can't be constructed.
+    let ty = parse_ty("< A of { x : U64, y : <> } | B of U64 | C of {} >");
+    assert_display_snapshot!(layout_of(ty), @r###"
+    Variant(Tagged(tag: Direct(ty: U8, values: { B => 0, C => 1 }, niches: 2..=255), variants:
+    | B => U64(niches: none)
+    | C => Aggregate {}
+    ))
+    "###);
+    let ty = parse_ty("< A of <> | B of < C of <> > >");
+    assert_display_snapshot!(layout_of(ty), @"Variant(Single(field: Aggregate {}))");
+}
+
+#[test]
+fn uninhabited_variants_values() {
+    let src = "alias T = < A of { x : U64, y : <> } | B of U64 | C of {} > in \
+               match <B = 5> as T { \
+                 <A = a : { x : U64, y : <> }> as T => 0, \
+                 <B = b : U64> as T => b : U64, \
+                 <C = c : {}> as T => 1 \
+               }";
+    assert_display_snapshot!(lower_root_expr(parse(src)), @r###"
+    let t#0 = { data = (<B = 5_u64> as union { B : U64 | C : {} }), tag = 0_u8 }
+    in  let t#1 = (t#0).tag
+    in  switch t#1 {
+    0 => {
+    let b = (t#0).data
+    in  b
+    }
+    1 => {
+    let c = (t#0).data
+    in  1_u64
+    }
+    }
+    "###);
+    assert_display_snapshot!(parse_and_eval(src), @"5_u64");
+}
+
+/// A list that is a pointer to its first node, so that the back-reference in
+/// `tl` is not behind a pointer in the node type.
+const BOXED_LIST: &str = "µL. Box[< Nil of {} | Cons of { hd : U64, tl : L } >]";
+const BOXED_TREE: &str = "µT. Box[< Leaf of {} | Node of { l : T, v : U64, r : T } >]";
+/// `-1 + 1` as an expression tree whose variants have payloads of different
+/// sizes behind boxes.
+const EXPR_TREE: &str =
+    "alias E = µE. < Lit of U8 | Neg of Box[E] | Add of Box[{ l : E, r : E }] > in \
+     alias EN = < Lit of U8 | Neg of Box[E] | Add of Box[{ l : E, r : E }] > in \
+     let one : E = fold [E] (<Lit = 1_u8> as EN) in \
+     let n : E = fold [E] (<Neg = box (one : E)> as EN) in \
+     fold [E] (<Add = box ({ l = n : E, r = one : E })> as EN)";
+
+#[test]
+fn boxed_list_layout() {
+    assert_display_snapshot!(layout_of(parse_ty(BOXED_LIST)), @r###"
+    Recursive(Ptr(pointee: Variant(Tagged(tag: Niche(path: ({root} as(transparent) Cons).tl, ty: U64, values: { Nil => 0 }), variants:
+    | Cons => Aggregate { hd => U64(niches: none), tl => recur#0 }
+    | Nil => Aggregate {}
+    )), niches: 0..=0))
+    "###);
+    // The node type, as seen after unfolding, agrees with the list's pointee.
+    let node = format!("< Nil of {{}} | Cons of {{ hd : U64, tl : {} }} >", BOXED_LIST);
+    assert_display_snapshot!(display_shape(parse_ty(&node)), @"size: 16 bytes, align: 8 bytes, stride: 16 bytes");
+    match layout_of(parse_ty(BOXED_LIST)) {
+        crate::layout::Layout::Recursive(body) => match *body {
+            crate::layout::Layout::Ptr { pointee, niches: _ } => {
+                assert_display_snapshot!(pointee.shape(), @"size: 16 bytes, align: 8 bytes, stride: 16 bytes")
+            }
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn zero_sized_layouts() {
+    let is_zst = |ty: &str| layout_of(parse_ty(ty)).is_zst();
+    assert!(is_zst("{}"));
+    assert!(is_zst("{ x : {}, y : {} }"));
+    assert!(is_zst("<>"));
+    assert!(is_zst("< A of { x : {} } >"));
+    assert!(is_zst("µT. { x : {} }"));
+    assert!(!is_zst("U8"));
+    assert!(!is_zst("< A of {} | B of {} >"));
+    assert!(!is_zst("{ x : {}, y : U64 }"));
+    assert!(!is_zst(BOXED_LIST));
+}
+
+#[test]
+fn boxed_tree_layout() {
+    assert_display_snapshot!(layout_of(parse_ty(BOXED_TREE)), @r###"
+    Recursive(Ptr(pointee: Variant(Tagged(tag: Niche(path: ({root} as(transparent) Node).l, ty: U64, values: { Leaf => 0 }), variants:
+    | Leaf => Aggregate {}
+    | Node => Aggregate { l => recur#0, r => recur#0, v => U64(niches: none) }
+    )), niches: 0..=0))
+    "###);
+}
+
+#[test]
+fn nested_recursive_layout() {
+    // `hd` refers to the outer type directly, from inside the inner one.
+    let ty = "µT. Box[{ v : U64, kids : µL. < Nil of {} | Cons of { hd : T, tl : Box[L] } > }]";
+    assert_display_snapshot!(layout_of(parse_ty(ty)), @r###"
+    Recursive(Ptr(pointee: Aggregate { kids => Recursive(Variant(Tagged(tag: Niche(path: ({root} as(transparent) Cons).hd, ty: U64, values: { Nil => 0 }), variants:
+    | Cons => Aggregate { hd => recur#1, tl => Ptr(pointee: recur#0, niches: 0..=0) }
+    | Nil => Aggregate {}
+    ))), v => U64(niches: none) }, niches: 0..=0))
+    "###);
+    let ty = "µT. Box[{ v : U64, parent : < None of {} | Some of T > }]";
+    assert_display_snapshot!(layout_of(parse_ty(ty)), @r###"
+    Recursive(Ptr(pointee: Aggregate { parent => Variant(Tagged(tag: Niche(path: ({root} as(transparent) Some), ty: U64, values: { None => 0 }), variants:
+    | None => Aggregate {}
+    | Some => recur#0
+    )), v => U64(niches: none) }, niches: 0..=0))
+    "###);
+}
+
+#[test]
+fn boxed_list_values() {
+    let src = format!(
+        "alias L = {} in \
+         alias N = < Nil of {{}} | Cons of {{ hd : U64, tl : L }} > in \
+         let nil : L = fold [L] box (<Nil = {{}}> as N) in \
+         let list : N = <Cons = {{ hd = 1, tl = nil : L }}> as N in \
+         match list : N {{ \
+           <Nil = u : {{}}> as N => 0, \
+           <Cons = c : {{ hd : U64, tl : L }}> as N => 1 \
+         }}",
+        BOXED_LIST
+    );
+    assert_display_snapshot!(parse_and_eval(&src), @"1_u64");
+}
+
+#[test]
+fn decode_recursive_values() {
+    let list = format!(
+        "alias L = {} in \
+         alias N = < Nil of {{}} | Cons of {{ hd : U64, tl : L }} > in \
+         let nil : L = fold [L] box (<Nil = {{}}> as N) in \
+         let one : L = fold [L] box (<Cons = {{ hd = 1, tl = nil : L }}> as N) in \
+         fold [L] box (<Cons = {{ hd = 2, tl = one : L }}> as N)",
+        BOXED_LIST
+    );
+    assert_display_snapshot!(parse_eval_and_decode(&list), @"box(<Cons = { hd = 2_u64, tl = box(<Cons = { hd = 1_u64, tl = box(<Nil = {}>) }>) }>)");
+
+    // `hd` refers to the outer type from inside the inner one.
+    let tree = "alias T = µT. Box[{ v : U64, kids : µL. < Nil of {} | Cons of { hd : T, tl : Box[L] } > }] in \
+                alias L = µL. < Nil of {} | Cons of { hd : T, tl : Box[L] } > in \
+                alias LN = < Nil of {} | Cons of { hd : T, tl : Box[L] } > in \
+                let nil : L = fold [L] (<Nil = {}> as LN) in \
+                let leaf : T = fold [T] box ({ v = 1, kids = nil : L }) in \
+                let kids : L = fold [L] (<Cons = { hd = leaf : T, tl = box (nil : L) }> as LN) in \
+                fold [T] box ({ v = 0, kids = kids : L })";
+    assert_display_snapshot!(parse_eval_and_decode(tree), @"box({ kids = <Cons = { hd = box({ kids = <Nil = {}>, v = 1_u64 }), tl = box(<Nil = {}>) }>, v = 0_u64 })");
+}
+
+#[test]
+fn strategies() {
+    use crate::layout_of::{layout_of_with, strategy_by_name, STRATEGIES};
+
+    let sizes = |ty: hir::Ty| {
+        let sizes = STRATEGIES
+            .iter()
+            .map(|s| format!("{}: {}", s.name(), layout_of_with(ty.clone(), *s).shape().size));
+        sizes.collect::<Vec<_>>().join(", ")
+    };
+    assert_display_snapshot!(sizes(maybe_bool_ty()), @"niche: 1 byte, niche-cheap: 1 byte, niche-shallow: 1 byte, rust: 1 byte, c: 8 bytes, swift: 1 byte");
+    assert_display_snapshot!(sizes(multi_dataful_ty()), @"niche: 16 bytes, niche-cheap: 16 bytes, niche-shallow: 16 bytes, rust: 16 bytes, c: 24 bytes, swift: 10 bytes");
+    assert_display_snapshot!(sizes(parse_ty("{ a : U8, b : U64, c : U8 }")), @"niche: 24 bytes, niche-cheap: 24 bytes, niche-shallow: 24 bytes, rust: 16 bytes, c: 24 bytes, swift: 17 bytes");
+    // Not even under `swift`.
+    assert_display_snapshot!(sizes(parse_ty("#[repr(C)] { a : #[repr(C)] { x : U64, y : U8 }, b : U8 }")), @"niche: 24 bytes, niche-cheap: 24 bytes, niche-shallow: 24 bytes, rust: 24 bytes, c: 24 bytes, swift: 24 bytes");
+    assert_display_snapshot!(sizes(parse_ty("< A of Box[U64] | B of Box[U64] >")), @"niche: 16 bytes, niche-cheap: 16 bytes, niche-shallow: 16 bytes, rust: 16 bytes, c: 16 bytes, swift: 8 bytes");
+
+    let c = strategy_by_name("c").unwrap();
+    let src = "alias MaybeBool = < None of {} | Some of < False of {} | True of {} > > in \
+               <Some = <True = {}> as < False of {} | True of {} >> as MaybeBool";
+    assert_display_snapshot!(parse_and_eval_with(src, c), @"{ data = { data = {}, tag = 1_u32 }, tag = 1_u32 }");
+    assert!(strategy_by_name("java").is_none());
+}
+
+#[test]
+fn compare_strategies() {
+    let compare = |src: &str| crate::compare::compare_layouts(parse_ty(src));
+    assert_display_snapshot!(compare("< None of {} | Some of < False of {} | True of {} > >"), @r###"
+    strategy       size     packed size  stride   tag     tag location                         niches left
+    niche          1 byte   1 byte       1 byte   niche   ({root} as(transparent) Some).{tag}  253
+    niche-cheap    1 byte   1 byte       1 byte   niche   ({root} as(transparent) Some).{tag}  253
+    niche-shallow  1 byte   1 byte       1 byte   niche   ({root} as(transparent) Some).{tag}  253
+    rust           1 byte   1 byte       1 byte   niche   ({root} as(transparent) Some).{tag}  253
+    c              8 bytes  8 bytes      8 bytes  direct  {root}.{tag}                         0
+    swift          1 byte   1 byte       1 byte   niche   ({root} as(transparent) Some).{tag}  253
+    "###);
+    assert_display_snapshot!(compare("< A of Box[U64] | B of Box[U64] | C of {} >"), @r###"
+    strategy       size      packed size  stride    tag          tag location         niches left
+    niche          16 bytes  9 bytes      16 bytes  direct       {root}.{tag}         253
+    niche-cheap    16 bytes  9 bytes      16 bytes  direct       {root}.{tag}         253
+    niche-shallow  16 bytes  9 bytes      16 bytes  direct       {root}.{tag}         253
+    rust           16 bytes  9 bytes      16 bytes  direct       {root}.{tag}         253
+    c              16 bytes  12 bytes     16 bytes  direct       {root}.{tag}         0
+    swift          8 bytes   8 bytes      8 bytes   pointer tag  {root} (low 2 bits)  0
+    "###);
+    assert_display_snapshot!(compare("{ a : U8, b : U64, c : U8 }"), @r###"
+    strategy       size      packed size  stride    tag   tag location  niches left
+    niche          24 bytes  10 bytes     24 bytes  none  -             0
+    niche-cheap    24 bytes  10 bytes     24 bytes  none  -             0
+    niche-shallow  24 bytes  10 bytes     24 bytes  none  -             0
+    rust           16 bytes  10 bytes     16 bytes  none  -             0
+    c              24 bytes  10 bytes     24 bytes  none  -             0
+    swift          17 bytes  10 bytes     24 bytes  none  -             0
+    "###);
+}
+
+#[test]
+fn niche_policies() {
+    use crate::layout::extract::NichePolicy;
+    use crate::layout_of::{layout_of_with, NicheFilling};
+
+    let tag_path = |src: &str, niche_policy| {
+        let strategy = NicheFilling { reorder_fields: false, niche_policy };
+        let layout = layout_of_with(parse_ty(src), &strategy);
+        let niches = layout.max_niche_values_count();
+        match layout.expect_variant() {
+            crate::layout::VariantLayout::Tagged(tagged) => match tagged.tag {
+                crate::layout::TagLayout::Niche { path, .. } => {
+                    format!("{}; niches left: {}", path, niches)
+                }
+                tag => panic!("expected a niche tag, got {}", tag),
+            },
+            _ => unreachable!(),
+        }
+    };
+    let ty = |a: &str, b: &str| {
+        format!(
+            "< None of {{}} | Some of {{ {} : < F of {{}} | T of {{}} >, {} : {{ c : {{ d : Box[U64] }} }} }} >",
+            a, b
+        )
+    };
+
+    for (a, b) in [("a", "b"), ("z", "b")] {
+        let ty = ty(a, b);
+        // Uses the pointer's single niche value and keeps the boolean's.
+        assert_eq!(
+            tag_path(&ty, NichePolicy::LargestRemaining),
+            format!("({{root}} as(transparent) Some).{}.c.d; niches left: 254", b)
+        );
+        // Both are single values, but the boolean's tag is smaller.
+        assert_eq!(
+            tag_path(&ty, NichePolicy::CheapestCheck),
+            format!("({{root}} as(transparent) Some).{}.{{tag}}; niches left: 253", a)
+        );
+        // The boolean's tag is nested less deeply.
+        assert_eq!(
+            tag_path(&ty, NichePolicy::Shallowest),
+            format!("({{root}} as(transparent) Some).{}.{{tag}}; niches left: 253", a)
+        );
+    }
+}
+
+#[test]
+fn explain_layouts() {
+    let explain = |strategy, src: &str| LayoutCx::new(strategy).explain(&parse_ty(src)).1;
+    assert_display_snapshot!(explain(layout_of::DEFAULT, "< None of {} | Some of < False of {} | True of {} > >"), @r###"
+    < None of {} | Some of < False of {} | True of {} > >:
+      < False of {} | True of {} >:
+        used a direct tag, since no variant has data
+      variant Some is the only dataful arm
+      extracted 1 niche value at ({root} as(transparent) Some).{tag}
+    "###);
+    assert_display_snapshot!(explain(layout_of::DEFAULT, &multi_dataful_ty().to_string()), @r###"
+    < A of { x : U64, y : < False of {} | True of {} > } | B of U64 | C of {} >:
+      { x : U64, y : < False of {} | True of {} > }:
+        < False of {} | True of {} >:
+          used a direct tag, since no variant has data
+      variants A, B are dataful
+      extracted 2 niche values at {root}.y.{tag} of the largest variant, A
+      used the niche, since it takes 16 bytes instead of 24 bytes
+    "###);
+    assert_display_snapshot!(explain(layout_of::DEFAULT, "< A of {} | B of {} | C of {} | D of #[no_niche] < X of {} | Y of {} > >"), @r###"
+    < A of {} | B of {} | C of {} | D of #[no_niche] < X of {} | Y of {} > >:
+      #[no_niche] < X of {} | Y of {} >:
+        used a direct tag, since no variant has data
+        cleared niches with up to 254 values for #[no_niche]
+      variant D is the only dataful arm
+      fell back to direct tag because no niche had 3 values
+    "###);
+    assert_display_snapshot!(explain(layout_of::DEFAULT, "< A of U64 | B of < > | C of {} >"), @r###"
+    < A of U64 | B of <  > | C of {} >:
+      dropped uninhabited variant B
+      variant A is the only dataful arm
+      fell back to direct tag because no niche had 1 value
+    "###);
+    assert_display_snapshot!(explain(layout_of::DEFAULT, "#[repr(u16)] < A of {} | B of U8 >"), @r###"
+    #[repr(u16)] < A of {} | B of U8 >:
+      used a direct tag for #[repr(u16)]
+    "###);
+    assert_display_snapshot!(explain(REORDER, "{ a : U8, b : U64, c : U8 }"), @r###"
+    { a : U8, b : U64, c : U8 }:
+      reordered the fields to b, a, c
+    "###);
+    assert_display_snapshot!(explain(SPARE_BITS, "< A of Box[U64] | B of Box[U64] | C of {} >"), @r###"
+    < A of Box[U64] | B of Box[U64] | C of {} >:
+      stored the tag in the low 2 bits of the pointers
+    "###);
+    assert_display_snapshot!(explain(SPARE_BITS, "< A of U64 | B of Box[U64] >"), @r###"
+    < A of U64 | B of Box[U64] >:
+      variant A is neither zero-sized nor a pointer with 1 spare bit
+      fell back to direct tag
+    "###);
+    assert_display_snapshot!(explain(layout_of::STRATEGIES[4], "< A of U64 | B of {} >"), @r###"
+    < A of U64 | B of {} >:
+      used a direct tag, like every variant type of this strategy
+    "###);
+    assert_display_snapshot!(explain(layout_of::DEFAULT, BOXED_LIST), @r###"
+    µ. Box[< Cons of { hd : U64, tl : #0 } | Nil of {} >]:
+      provisional layout, with opaque back-references:
+        < Cons of { hd : U64, tl : #0 } | Nil of {} >:
+          variant Cons is the only dataful arm
+          fell back to direct tag because no niche had 1 value
+      final layout, with unrolled back-references:
+        < Cons of { hd : U64, tl : #0 } | Nil of {} >:
+          variant Cons is the only dataful arm
+          extracted 1 niche value at ({root} as(transparent) Cons).tl
+    "###);
+    assert_display_snapshot!(explain(layout_of::DEFAULT, "U64"), @"(no decisions)");
+
+    // Cached layouts are explained again.
+    let mut layouts = LayoutCx::new(layout_of::DEFAULT);
+    let ty = maybe_bool_ty();
+    let (layout, first) = layouts.explain(&ty);
+    let (cached, second) = layouts.explain(&ty);
+    assert_eq!(layout.to_string(), cached.to_string());
+    assert_eq!(first.to_string(), second.to_string());
+}
+
+#[test]
+fn draw_layouts() {
+    use crate::draw::draw_layout;
+
+    let draw = |strategy, src: &str| {
+        draw_layout(&LayoutCx::new(strategy).layout_of(&parse_ty(src))).to_ascii()
+    };
+    assert_display_snapshot!(draw(layout_of::DEFAULT, "< None of {} | Some of < False of {} | True of {} > >"), @r###"
+    size: 1 byte, align: 1 byte, stride: 1 byte
+         0
+    None [a]
+    Some [b]
+    a  tag    ({root} as(transparent) Some).{tag}  U8 = 2
+    b  niche  ({root} as(transparent) Some).{tag}  U8 tag: { False = 0, True = 1 } (niches: 3..=255); niche for { None = 2 }
+    "###);
+    assert_display_snapshot!(draw(layout_of::DEFAULT, &multi_dataful_ty().to_string()), @r###"
+    size: 16 bytes, align: 8 bytes, stride: 16 bytes
+      0           4           8           12
+    A [a---------------------][b] .  .  .  .  .  .  .
+    B [c---------------------][d] .  .  .  .  .  .  .
+    C  .  .  .  .  .  .  .  . [e] .  .  .  .  .  .  .
+    a  data   ({root} as(wrapper) A).x  U64 (niches: none)
+    b  niche  {root}.{tag}.{tag}        U8 tag: { False = 0, True = 1 } (niches: 4..=255); niche for { B = 2, C = 3 }
+    c  data   ({root} as(wrapper) B)    U64 (niches: none)
+    d  tag    {root}.{tag}.{tag}        U8 = 2
+    e  tag    {root}.{tag}.{tag}        U8 = 3
+    "###);
+    assert_display_snapshot!(draw(layout_of::DEFAULT, "{ a : U8, b : U64, c : < X of {} | Y of U16 > }"), @r###"
+    size: 24 bytes, align: 8 bytes, stride: 24 bytes
+     0           4           8           12          16          20
+     [a] .  .  .  .  .  .  . [b---------------------][c---][d] .  .  .  .  .
+    a  data  {root}.a        U8 (niches: none)
+    b  data  {root}.b        U64 (niches: none)
+    c  data  {root}.c        data of X | Y
+    d  tag   {root}.c.{tag}  U8 tag: { X = 0, Y = 1 } (niches: 2..=255)
+    "###);
+    assert_display_snapshot!(draw(REORDER, "{ a : U8, b : U64, c : U8 }"), @r###"
+    size: 16 bytes, align: 8 bytes, stride: 16 bytes
+     0           4           8           12
+     [a---------------------][b][c] .  .  .  .  .  .
+    a  data  {root}.b  U64 (niches: none)
+    b  data  {root}.a  U8 (niches: none)
+    c  data  {root}.c  U8 (niches: none)
+    "###);
+    assert_display_snapshot!(draw(SPARE_BITS, "< A of Box[U64] | B of Box[U64] | C of {} >"), @r###"
+    size: 8 bytes, align: 8 bytes, stride: 8 bytes
+      0           4
+    A [a=====================]
+    B [b=====================]
+    C [c=====================]
+    a  tag  ({root} as(transparent) A)  Ptr, low 2 bits = 0
+    b  tag  ({root} as(transparent) B)  Ptr, low 2 bits = 1
+    c  tag  ({root} as(transparent) C)  null Ptr, low 2 bits = 2
+    "###);
+    assert_display_snapshot!(draw(layout_of::DEFAULT, "µL. < Nil of {} | Cons of { hd : U16, tl : Box[L] } >"), @r###"
+    size: 16 bytes, align: 8 bytes, stride: 16 bytes
+         0           4           8           12
+    Cons [a---] .  .  .  .  .  . [b~~~~~~~~~~~~~~~~~~~~~]
+    Nil   .  .  .  .  .  .  .  . [c=====================]
+    a  data   ({root} as(transparent) Cons).hd  U16 (niches: none)
+    b  niche  ({root} as(transparent) Cons).tl  Ptr (niches: none); niche for { Nil = 0 }
+    c  tag    ({root} as(transparent) Cons).tl  U64 = 0
+    "###);
+    assert_display_snapshot!(draw(layout_of::DEFAULT, "<>"), @r###"
+    size: 0 bytes, align: 1 byte, stride: 0 bytes
+    (no bytes)
+    "###);
+}
+
+#[test]
+fn draw_svg_and_dot() {
+    use crate::draw::draw_layout;
+
+    let diagram = draw_layout(&layout_of(maybe_bool_ty()));
+    assert_display_snapshot!(diagram.to_svg(), @r###"
+    <svg xmlns="http://www.w3.org/2000/svg" width="480" height="152" viewBox="0 0 480 152" font-family="monospace" font-size="12">
+      <text x="64" y="16" text-anchor="middle">0</text>
+      <text x="8" y="44">None</text>
+      <rect x="48" y="24" width="32" height="32" fill="#eeeeee" stroke="#999999"/>
+      <rect x="48" y="24" width="32" height="32" fill="#fdae6b" stroke="#333333"><title>({root} as(transparent) Some).{tag}: U8 = 2</title></rect>
+      <text x="64" y="44" text-anchor="middle">a</text>
+      <text x="8" y="76">Some</text>
+      <rect x="48" y="56" width="32" height="32" fill="#eeeeee" stroke="#999999"/>
+      <rect x="48" y="56" width="32" height="32" fill="#a1d99b" stroke="#333333"><title>({root} as(transparent) Some).{tag}: U8 tag: { False = 0, True = 1 } (niches: 3..=255); niche for { None = 2 }</title></rect>
+      <text x="64" y="76" text-anchor="middle">b</text>
+      <text x="8" y="118">a tag: ({root} as(transparent) Some).{tag} U8 = 2</text>
+      <text x="8" y="138">b niche: ({root} as(transparent) Some).{tag} U8 tag: { False = 0, True = 1 } (niches: 3..=255); niche for { None = 2 }</text>
+    </svg>
+
+    "###);
+    assert_display_snapshot!(diagram.to_dot(), @r###"
+    digraph layout {
+      node [shape=plaintext];
+      layout [label=<
+        <table border="0" cellborder="1" cellspacing="0">
+          <tr><td></td><td>0</td></tr>
+          <tr><td>None</td><td colspan="1" bgcolor="#fdae6b" tooltip="U8 = 2">a</td></tr>
+          <tr><td>Some</td><td colspan="1" bgcolor="#a1d99b" tooltip="U8 tag: { False = 0, True = 1 } (niches: 3..=255); niche for { None = 2 }">b</td></tr>
+          <tr><td>a</td><td colspan="1" align="left">tag: ({root} as(transparent) Some).{tag} U8 = 2</td></tr>
+          <tr><td>b</td><td colspan="1" align="left">niche: ({root} as(transparent) Some).{tag} U8 tag: { False = 0, True = 1 } (niches: 3..=255); niche for { None = 2 }</td></tr>
+        </table>
+      >];
+    }
+
+    "###);
+}
+
+#[test]
+fn json() {
+    use crate::json::ToJson;
+
+    let json = |value: &dyn ToJson| value.to_json().to_string();
+    assert_display_snapshot!(json(&layout_of(maybe_bool_ty())), @r###"{"kind":"tagged","tag":{"kind":"niche","path":[{"kind":"variant","repr":"transparent","name":"Some"},{"kind":"tag"}],"ty":"U8","values":{"None":2}},"variants":{"None":{"kind":"aggregate","repr":"padded","fields":[]},"Some":{"kind":"tagged","tag":{"kind":"direct","ty":"U8","values":{"False":0,"True":1},"niches":[[3,255]],"placement":"after"},"variants":{"False":{"kind":"aggregate","repr":"padded","fields":[]},"True":{"kind":"aggregate","repr":"padded","fields":[]}}}}}"###);
+    assert_display_snapshot!(json(&layout_of(multi_dataful_ty())), @r###"{"kind":"tagged","tag":{"kind":"multi_niche","holder":"A","hoisted":"y","field":{"kind":"tagged","tag":{"kind":"direct","ty":"U8","values":{"False":0,"True":1},"niches":[[4,255]],"placement":"after"},"variants":{"False":{"kind":"aggregate","repr":"padded","fields":[]},"True":{"kind":"aggregate","repr":"padded","fields":[]}}},"path":[{"kind":"tag"},{"kind":"tag"}],"ty":"U8","values":{"B":2,"C":3}},"variants":{"A":{"kind":"aggregate","repr":"padded","fields":[{"name":"x","layout":{"kind":"int","ty":"U64","niches":[]}}]},"B":{"kind":"int","ty":"U64","niches":[]},"C":{"kind":"aggregate","repr":"padded","fields":[]}}}"###);
+    assert_display_snapshot!(json(&crate::layout_of::layout_of_with(parse_ty("< A of Box[U64] | B of Box[U64] >"), SPARE_BITS)), @r###"{"kind":"tagged","tag":{"kind":"pointer_tag","bits":1,"values":{"A":0,"B":1}},"variants":{"A":{"kind":"ptr","pointee":{"kind":"int","ty":"U64","niches":[]},"niches":[[0,0]]},"B":{"kind":"ptr","pointee":{"kind":"int","ty":"U64","niches":[]},"niches":[[0,0]]}}}"###);
+    assert_display_snapshot!(json(&layout_of(parse_ty(BOXED_LIST))), @r###"{"kind":"recursive","body":{"kind":"ptr","pointee":{"kind":"tagged","tag":{"kind":"niche","path":[{"kind":"variant","repr":"transparent","name":"Cons"},{"kind":"field","name":"tl"}],"ty":"U64","values":{"Nil":0}},"variants":{"Cons":{"kind":"aggregate","repr":"padded","fields":[{"name":"hd","layout":{"kind":"int","ty":"U64","niches":[]}},{"name":"tl","layout":{"kind":"recur_id","id":0}}]},"Nil":{"kind":"aggregate","repr":"padded","fields":[]}}},"niches":[[0,0]]}}"###);
+    assert_display_snapshot!(json(&lower_layout(layout_of(parse_ty("#[packed] { a : U8, b : U16 }")))), @r###"{"kind":"record","repr":"packed","fields":[{"name":"a","ty":{"kind":"int","ty":"U8"}},{"name":"b","ty":{"kind":"int","ty":"U16"}}]}"###);
+    assert_display_snapshot!(json(&parse_ty("µL. #[repr(u8)] < Nil = 3 of {} | Cons of { hd : U8, tl : Box[L] } >")), @r###"{"kind":"recursive","body":{"kind":"repr","ty":{"kind":"variant","variants":{"Cons":{"kind":"record","fields":{"hd":{"kind":"int","ty":"U8"},"tl":{"kind":"box","ty":{"kind":"recur_id","id":0}}}},"Nil":{"kind":"record","fields":{}}}},"c":false,"tag":"U8","no_niche":false,"packed":false,"discriminants":{"Nil":3},"field_order":[]}}"###);
+
+    let src = "alias MaybeBool = < None of {} | Some of < False of {} | True of {} > > in \
+               <Some = <True = {}> as < False of {} | True of {} >> as MaybeBool";
+    assert_display_snapshot!(json(&parse_and_eval(src)), @r###"{"kind":"record","fields":{"data":{"kind":"record","fields":{}},"tag":{"kind":"int","ty":"U8","value":1}}}"###);
+    assert_display_snapshot!(json(&parse_eval_and_decode(src)), @r###"{"kind":"variant","variant":"Some","field":{"kind":"variant","variant":"True","field":{"kind":"record","fields":{}}}}"###);
+}
+
+/// Check that all values of `ty` round-trip under every layout strategy.
+fn assert_round_trips(ty: &str) -> usize {
+    let counts: Vec<_> = STRATEGIES
+        .iter()
+        .map(|strategy| {
+            check_round_trip(&parse_ty(ty), &mut LayoutCx::new(*strategy))
+                .unwrap_or_else(|err| panic!("{} ({}): {}", ty, strategy.name(), err))
+        })
+        .collect();
+    counts[0]
+}
+
+#[test]
+fn round_trips() {
+    assert_eq!(assert_round_trips("< None of {} | Some of < False of {} | True of {} > >"), 3);
+    assert_eq!(
+        assert_round_trips(
+            "< A of {} | B of < None of {} | Some of < False of {} | True of {} > > >"
+        ),
+        4
+    );
+    assert_eq!(
+        assert_round_trips("{ a : < X of {} | Y of U8 >, b : < P of {} | Q of {} > }"),
+        257 * 2
+    );
+    assert_eq!(
+        assert_round_trips(
+            "< A of {} | B of { x : U8, y : < C of {} | D of {} > } | E of { z : U8 } | F of {} >"
+        ),
+        1 + 512 + 256 + 1
+    );
+    assert_eq!(assert_round_trips("< A of <> | B of { x : U8, y : <> } | C of U8 >"), 256);
+    assert_eq!(assert_round_trips("<>"), 0);
+    assert_eq!(assert_round_trips("#[repr(u8)] < A = 3 of {} | B of U8 | C of {} >"), 258);
+    assert_eq!(assert_round_trips("< None of {} | Some of < A = 5 of {} | B of {} > >"), 3);
+    assert_eq!(assert_round_trips("< N of {} | S of #[no_niche] < A of {} | B of {} > >"), 3);
+    assert_eq!(
+        assert_round_trips(
+            "< A of #[packed] { x : U8, y : < P of {} | Q of {} > } | B of {} | C of U8 >"
+        ),
+        512 + 1 + 256
+    );
+}
+
+#[test]
+fn round_trips_boxes_of_different_sizes() {
+    // Reading `B`'s bytes as `A`'s would also give a valid value.
+    assert_eq!(assert_round_trips("< A of Box[U8] | B of Box[U16] >"), 256 + 65536);
+}
+
+#[test]
+fn pointer_tags_round_trip() {
+    let ty = parse_ty("< A of Box[U16] | B of Box[U16] >");
+    let mut layouts = LayoutCx::new(SPARE_BITS);
+    assert_eq!(check_round_trip(&ty, &mut layouts).unwrap(), 2 * 65536);
+}
+
+#[test]
+fn round_trip_needs_finite_type() {
+    use crate::check::CheckError;
+    use crate::layout_of::DEFAULT;
+
+    for ty in ["U32", "{ a : U16, b : U16 }", BOXED_LIST] {
+        let result = check_round_trip(&parse_ty(ty), &mut LayoutCx::new(DEFAULT));
+        assert!(matches!(result, Err(CheckError::TooManyValues)), "{}", ty);
+    }
+}
+
+/// Evaluate `src`, store the value in memory, and dump it, like `:bytes`.
+fn dump_bytes(strategy: &dyn LayoutStrategy, src: &str) -> String {
+    let expr = parse(src);
+    let layout = crate::layout_of::layout_of_with(expr.ty(), strategy);
+    let value = eval_root_expr(crate::lower::lower_root_expr_with(expr, strategy));
+    let ty = lower_layout(layout.clone());
+    let mut memory = Memory::new();
+    let addr = memory.encode(&value, &ty).unwrap();
+    format!("{}\nread back: {}", memory, memory.decode(addr, &ty, &layout).unwrap())
+}
+
+#[test]
+fn memory_bytes() {
+    use crate::layout_of::DEFAULT;
+
+    for strategy in STRATEGIES {
+        let expr = parse(EXPR_TREE);
+        let layout = crate::layout_of::layout_of_with(expr.ty(), strategy);
+        let value = eval_root_expr(crate::lower::lower_root_expr_with(expr, strategy));
+        let ty = lower_layout(layout.clone());
+        let mut memory = Memory::new();
+        let addr = memory.encode(&value, &ty).unwrap();
+        assert_eq!(memory.decode(addr, &ty, &layout), Ok(value), "{}", strategy.name());
+    }
+    assert_display_snapshot!(dump_bytes(DEFAULT, EXPR_TREE), @r###"
+    0x1000: 16 bytes, align 8 bytes
+      0x1000  10 10 00 00 00 00 00 00  {root}.data.Add = box at 0x1010
+      0x1008  00                       {root}.tag = 0_u8
+      0x1009  00 00 00 00 00 00 00     (unused)
+    0x1010: 32 bytes, align 8 bytes
+      0x1010  30 10 00 00 00 00 00 00  {root}.l.data.Neg = box at 0x1030
+      0x1018  02                       {root}.l.tag = 2_u8
+      0x1019  00 00 00 00 00 00 00     (unused)
+      0x1020  01                       {root}.r.data.Lit = 1_u8
+      0x1021  00 00 00 00 00 00 00     (unused)
+      0x1028  01                       {root}.r.tag = 1_u8
+      0x1029  00 00 00 00 00 00 00     (unused)
+    0x1030: 16 bytes, align 8 bytes
+      0x1030  01                    {root}.data.Lit = 1_u8
+      0x1031  00 00 00 00 00 00 00  (unused)
+      0x1038  01                    {root}.tag = 1_u8
+      0x1039  00 00 00 00 00 00 00  (unused)
+    read back: { data = Box({ l = { data = Box({ data = 1_u8, tag = 1_u8 }), tag = 2_u8 }, r = { data = 1_u8, tag = 1_u8 } }), tag = 0_u8 }
+    "###);
+
+    let list = format!(
+        "alias L = {} in \
+         alias N = < Nil of {{}} | Cons of {{ hd : U64, tl : L }} > in \
+         let nil : L = fold [L] box (<Nil = {{}}> as N) in \
+         fold [L] box (<Cons = {{ hd = 2, tl = nil : L }}> as N)",
+        BOXED_LIST
+    );
+    assert_display_snapshot!(dump_bytes(DEFAULT, &list), @r###"
+    0x1000: 8 bytes, align 8 bytes
+      0x1000  08 10 00 00 00 00 00 00  {root} = box at 0x1008
+    0x1008: 16 bytes, align 8 bytes
+      0x1008  02 00 00 00 00 00 00 00  {root}.hd = 2_u64
+      0x1010  18 10 00 00 00 00 00 00  {root}.tl = box at 0x1018
+    0x1018: 16 bytes, align 8 bytes
+      0x1018  00 00 00 00 00 00 00 00  (unused)
+      0x1020  00 00 00 00 00 00 00 00  {root}.tl = null
+    read back: Box({ hd = 2_u64, tl = Box({ tl = 0_u64 }) })
+    "###);
+
+    let ty = "< A of U8 | B of { x : U8, y : U32 } | C of {} >";
+    assert_display_snapshot!(dump_bytes(DEFAULT, &format!("<B = {{ x = 1_u8, y = 2_u32 }}> as {}", ty)), @r###"
+    0x1000: 12 bytes, align 4 bytes
+      0x1000  01           {root}.data.B.x = 1_u8
+      0x1001  00 00 00     (unused)
+      0x1004  02 00 00 00  {root}.data.B.y = 2_u32
+      0x1008  01           {root}.tag = 1_u8
+      0x1009  00 00 00     (unused)
+    read back: { data = { x = 1_u8, y = 2_u32 }, tag = 1_u8 }
+    "###);
+    // Like a C struct, the `c` strategy puts the `U32` tag before the data.
+    let c = crate::layout_of::strategy_by_name("c").unwrap();
+    assert_display_snapshot!(dump_bytes(c, &format!("<B = {{ x = 1_u8, y = 2_u32 }}> as {}", ty)), @r###"
+    0x1000: 12 bytes, align 4 bytes
+      0x1000  01 00 00 00  {root}.tag = 1_u32
+      0x1004  01           {root}.data.B.x = 1_u8
+      0x1005  00 00 00     (unused)
+      0x1008  02 00 00 00  {root}.data.B.y = 2_u32
+    read back: { data = { x = 1_u8, y = 2_u32 }, tag = 1_u32 }
+    "###);
+
+    let ty = "< A of Box[U64] | B of Box[{ x : U64 }] | C of {} >";
+    assert_display_snapshot!(dump_bytes(SPARE_BITS, &format!("<B = box ({{ x = 2 }})> as {}", ty)), @r###"
+    0x1000: 8 bytes, align 8 bytes
+      0x1000  09 10 00 00 00 00 00 00  {root} = box at 0x1008, tag 1
+    0x1008: 8 bytes, align 8 bytes
+      0x1008  02 00 00 00 00 00 00 00  {root}.B.x = 2_u64
+    read back: (Box({ x = 2_u64 }) | 1)
+    "###);
+    assert_display_snapshot!(dump_bytes(SPARE_BITS, &format!("<C = {{}}> as {}", ty)), @r###"
+    0x1000: 8 bytes, align 8 bytes
+      0x1000  02 00 00 00 00 00 00 00  {root} = null, tag 2
+    read back: 2_u64
+    "###);
+}
+
+/// Evaluate `src` with both the tree-walking evaluator and the VM.
+fn run_both(strategy: &dyn LayoutStrategy, src: &str) -> crate::vm::Differential {
+    let expr = parse(src);
+    let layout = crate::layout_of::layout_of_with(expr.ty(), strategy);
+    crate::vm::differential(crate::lower::lower_root_expr_with(expr, strategy), &layout).unwrap()
+}
+
+#[test]
+fn vm_bytecode() {
+    use crate::layout_of::DEFAULT;
+
+    let src = "alias T = < A of U8 | B of U64 > in \
+               match <B = 7> as T { <A = a : U8> as T => 0, <B = b : U64> as T => b : U64 }";
+    let expr = parse(src);
+    let ty = lower_layout(layout_of(expr.ty()));
+    assert_display_snapshot!(crate::vm::compile(&crate::lower::lower_root_expr(expr), &ty), @r###"
+     0: r0 = alloc 8, align 8
+     1: r1 = alloc 16, align 8
+     2: store64 [r1], 7
+     3: r2 = r1 + 8
+     4: store8 [r2], 1
+     5: r3 = alloc 1, align 1
+     6: r4 = r1 + 8
+     7: copy 1 byte [r3], [r4]
+     8: r5 = load8 [r3]
+     9: if r5 == 0 goto 13
+    10: if r5 == 1 goto 17
+    11: no case for r5
+    12: goto 21
+    13: r6 = alloc 1, align 1
+    14: copy 1 byte [r6], [r1]
+    15: store64 [r0], 0
+    16: goto 21
+    17: r7 = alloc 8, align 8
+    18: copy 8 bytes [r7], [r1]
+    19: copy 8 bytes [r0], [r7]
+    20: goto 21
+    result: [r0] : U64
+    "###);
+    assert_display_snapshot!(run_both(DEFAULT, src), @r###"
+    eval: 7_u64
+    vm:   7_u64 at 0x1000
+    the VM's bytes hold eval's value
+    0x1000: 8 bytes, align 8 bytes
+      0x1000  07 00 00 00 00 00 00 00
+    0x1008: 16 bytes, align 8 bytes
+      0x1008  07 00 00 00 00 00 00 00
+      0x1010  01 00 00 00 00 00 00 00
+    0x1018: 1 byte, align 1 byte
+      0x1018  01
+    0x1020: 8 bytes, align 8 bytes
+      0x1020  07 00 00 00 00 00 00 00
+    "###);
+    assert_display_snapshot!(run_both(DEFAULT, EXPR_TREE), @r###"
+    eval: { data = Box({ l = { data = Box({ data = 1_u8, tag = 1_u8 }), tag = 2_u8 }, r = { data = 1_u8, tag = 1_u8 } }), tag = 0_u8 }
+    vm:   { data = Box({ l = { data = Box({ data = 1_u8, tag = 1_u8 }), tag = 2_u8 }, r = { data = 1_u8, tag = 1_u8 } }), tag = 0_u8 } at 0x1000
+    the VM's bytes hold eval's value
+    0x1000: 16 bytes, align 8 bytes
+      0x1000  40 10 00 00 00 00 00 00
+      0x1008  00 00 00 00 00 00 00 00
+    0x1010: 16 bytes, align 8 bytes
+      0x1010  01 00 00 00 00 00 00 00
+      0x1018  01 00 00 00 00 00 00 00
+    0x1020: 16 bytes, align 8 bytes
+      0x1020  30 10 00 00 00 00 00 00
+      0x1028  02 00 00 00 00 00 00 00
+    0x1030: 16 bytes, align 8 bytes
+      0x1030  01 00 00 00 00 00 00 00
+      0x1038  01 00 00 00 00 00 00 00
+    0x1040: 32 bytes, align 8 bytes
+      0x1040  30 10 00 00 00 00 00 00
+      0x1048  02 00 00 00 00 00 00 00
+      0x1050  01 00 00 00 00 00 00 00
+      0x1058  01 00 00 00 00 00 00 00
+    "###);
+}
+
+#[test]
+fn vm_agrees_with_eval() {
+    let bool_and_t = "alias Bool = < False of {} | True of {} > in \
+                      alias T = < A of { x : U64, y : Bool } | B of U64 | C of {} > in";
+    let unpack = |value: &str| {
+        format!(
+            "{} match {} {{ \
+               <A = a : {{ x : U64, y : Bool }}> as T => a : {{ x : U64, y : Bool }}, \
+               <B = b : U64> as T => {{ x = b : U64, y = <False = {{}}> as Bool }}, \
+               <C = c : {{}}> as T => {{ x = 0, y = <True = {{}}> as Bool }} \
+             }}",
+            bool_and_t, value
+        )
+    };
+    let pointers = "alias T = < A of Box[U64] | B of Box[{ x : U64 }] | C of {} > in";
+    let untag = |value: &str| {
+        format!(
+            "{} match {} as T {{ \
+               <A = a : Box[U64]> as T => {{ a = a : Box[U64], b = box ({{ x = 0 }}), tag = 0 }}, \
+               <B = b : Box[{{ x : U64 }}]> as T => {{ a = box (0), b = b : Box[{{ x : U64 }}], tag = 1 }}, \
+               <C = c : {{}}> as T => {{ a = box (0), b = box ({{ x = 0 }}), tag = 2 }} \
+             }}",
+            pointers, value
+        )
+    };
+    let list = format!(
+        "alias L = {} in \
+         alias N = < Nil of {{}} | Cons of {{ hd : U64, tl : L }} > in \
+         let nil : L = fold [L] box (<Nil = {{}}> as N) in \
+         let one : L = fold [L] box (<Cons = {{ hd = 1, tl = nil : L }}> as N) in \
+         let two : N = <Cons = {{ hd = 2, tl = one : L }}> as N in \
+         match two : N {{ \
+           <Nil = u : {{}}> as N => one : L, \
+           <Cons = c : {{ hd : U64, tl : L }}> as N => \
+             fold [L] box (<Cons = {{ hd = 3, tl = fold [L] box (<Cons = c : {{ hd : U64, tl : L }}> as N) }}> as N) \
+         }}",
+        BOXED_LIST
+    );
+    let tree = "alias T = µT. Box[{ v : U64, kids : µL. < Nil of {} | Cons of { hd : T, tl : Box[L] } > }] in \
+                alias L = µL. < Nil of {} | Cons of { hd : T, tl : Box[L] } > in \
+                alias LN = < Nil of {} | Cons of { hd : T, tl : Box[L] } > in \
+                let nil : L = fold [L] (<Nil = {}> as LN) in \
+                let leaf : T = fold [T] box ({ v = 1, kids = nil : L }) in \
+                let kids : L = fold [L] (<Cons = { hd = leaf : T, tl = box (nil : L) }> as LN) in \
+                fold [T] box ({ v = 0, kids = kids : L })";
+    let sources = [
+        unpack("<A = { x = 5, y = <True = {}> as Bool }> as T"),
+        unpack("<B = 7> as T"),
+        unpack("<C = {}> as T"),
+        untag("<A = box (5)>"),
+        untag("<B = box ({ x = 2 })>"),
+        untag("<C = {}>"),
+        list,
+        tree.to_owned(),
+        EXPR_TREE.to_owned(),
+        "alias P = < P of {} | Q of {} > in \
+         <A = { x = 1_u8, y = <Q = {}> as P }> as \
+           < A of #[packed] { x : U8, y : P } | B of {} | C of U8 >"
+            .to_owned(),
+        "<B = 5_u8> as #[repr(C)] < A of U16 | B of U8 >".to_owned(),
+    ];
+    for src in &sources {
+        for strategy in STRATEGIES {
+            let result = run_both(strategy, src);
+            assert!(result.agrees(), "{} ({}):\n{}", src, strategy.name(), result);
+        }
+    }
+}
+
+#[test]
+fn heap_stats() {
+    use crate::compare::compare_heap_use;
+    use crate::eval::eval_with_stats;
+
+    let stats = |src: &str| {
+        let expr = parse(src);
+        let ty = lower_layout(layout_of(expr.ty()));
+        eval_with_stats(crate::lower::lower_root_expr(expr), &ty).1
+    };
+    // The first box is freed before the second one is allocated.
+    assert_display_snapshot!(stats(
+        "let x : U64 = (let a : Box[U64] = box (1) in 5) in \
+         let y : Box[U64] = box (2) in y : Box[U64]"
+    ), @r###"
+    allocated: 2 boxes, 16 bytes
+    peak live: 8 bytes
+    "###);
+    // The box is as big as the whole variant type, not just the tag.
+    let none =
+        "box (<None = {}> as < None of {} | Some of { a : U64, b : < F of {} | T of {} > } >)";
+    assert_display_snapshot!(stats(none), @r###"
+    allocated: 1 box, 16 bytes
+    peak live: 16 bytes
+    "###);
+    let list = format!(
+        "alias L = {} in \
+         alias N = < Nil of {{}} | Cons of {{ hd : U64, tl : L }} > in \
+         let nil : L = fold [L] box (<Nil = {{}}> as N) in \
+         let one : L = fold [L] box (<Cons = {{ hd = 1, tl = nil : L }}> as N) in \
+         fold [L] box (<Cons = {{ hd = 2, tl = one : L }}> as N)",
+        BOXED_LIST
+    );
+    assert_display_snapshot!(compare_heap_use(parse(&list)), @r###"
+    strategy       size     boxes  allocated  peak live
+    niche          8 bytes  3      48 bytes   48 bytes
+    niche-cheap    8 bytes  3      48 bytes   48 bytes
+    niche-shallow  8 bytes  3      48 bytes   48 bytes
+    rust           8 bytes  3      48 bytes   48 bytes
+    c              8 bytes  3      72 bytes   72 bytes
+    swift          8 bytes  3      48 bytes   48 bytes
+    "###);
+}
+
+#[test]
+fn repr_layouts() {
+    let layout = |src: &str| layout_of(parse_ty(src));
+    assert_display_snapshot!(layout("#[repr(u16)] < None of {} | Some of < False of {} | True of {} > >"), @r###"
+    Variant(Tagged(tag: Direct(ty: U16, values: { None => 0, Some => 1 }, niches: 2..=65535), variants:
+    | None => Aggregate {}
+    | Some => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: 2..=255), variants:
+    | False => Aggregate {}
+    | True => Aggregate {}
+    ))
+    ))
+    "###);
+    assert_display_snapshot!(layout("#[repr(C)] < A of {} | B of U8 >"), @r###"
+    Variant(Tagged(tag: Direct(ty: U32, values: { A => 0, B => 1 }, niches: 2..=4294967295, placement: first), variants:
+    | A => Aggregate {}
+    | B => U8(niches: none)
+    ))
+    "###);
+    assert_display_snapshot!(layout("< A = 5 of {} | B of {} | C = 0 of {} >"), @r###"
+    Variant(Tagged(tag: Direct(ty: U8, values: { A => 5, B => 1, C => 0 }, niches: 2..=4 ∪ 6..=255), variants:
+    | A => Aggregate {}
+    | B => Aggregate {}
+    | C => Aggregate {}
+    ))
+    "###);
+    // Neither the null pointer nor the unused tag values are niches anymore.
+    assert_display_snapshot!(layout("< None of {} | Some of #[no_niche] { p : Box[U64] } >"), @r###"
+    Variant(Tagged(tag: Direct(ty: U8, values: { None => 0, Some => 1 }, niches: 2..=255), variants:
+    | None => Aggregate {}
+    | Some => Aggregate { p => Ptr(pointee: U64(niches: none), niches: none) }
+    ))
+    "###);
+    assert_display_snapshot!(layout("< None of {} | Some of #[no_niche] < False of {} | True of {} > >"), @r###"
+    Variant(Tagged(tag: Direct(ty: U8, values: { None => 0, Some => 1 }, niches: 2..=255), variants:
+    | None => Aggregate {}
+    | Some => Variant(Tagged(tag: Direct(ty: U8, values: { False => 0, True => 1 }, niches: none), variants:
+    | False => Aggregate {}
+    | True => Aggregate {}
+    ))
+    ))
+    "###);
+}
+
+#[test]
+fn repr_shapes() {
+    let c_record = "#[repr(C)] { b : U8, a : U64, c : U8 }";
+    let reordered = crate::layout_of::layout_of_with(parse_ty(c_record), REORDER);
+    assert_display_snapshot!(crate::lower::lower_layout(reordered), @"{ b : U8, a : U64, c : U8 }");
+    assert_display_snapshot!(display_shape(parse_ty(c_record)), @"size: 24 bytes, align: 8 bytes, stride: 24 bytes; offsets: { a => 8 bytes, b => 0 bytes, c => 16 bytes }");
+    // The outer record doesn't reuse the inner one's trailing padding.
+    let nested = "#[repr(C)] { a : #[repr(C)] { x : U64, y : U8 }, b : U8 }";
+    assert_display_snapshot!(display_shape(parse_ty(nested)), @"size: 24 bytes, align: 8 bytes, stride: 24 bytes; offsets: { a => 0 bytes, b => 16 bytes }");
+    let packed = "#[packed] { a : U8, b : U64, c : U16 }";
+    let reordered = crate::layout_of::layout_of_with(parse_ty(packed), REORDER);
+    assert_display_snapshot!(crate::lower::lower_layout(reordered), @"packed { a : U8, b : U64, c : U16 }");
+    assert_display_snapshot!(display_shape(parse_ty(packed)), @"size: 11 bytes, align: 1 byte, stride: 11 bytes; offsets: { a => 0 bytes, b => 1 byte, c => 9 bytes }");
+    assert_display_snapshot!(display_shape(parse_ty("#[repr(C)] < A of {} | B of U8 >")), @"size: 8 bytes, align: 4 bytes, stride: 8 bytes; offsets: { data => 4 bytes, tag => 0 bytes }");
+}
+
+#[test]
+fn repr_display() {
+    let ty = "#[no_niche] #[repr(u8, C)] < A = 7 of {} | B of #[packed] { x : U64 } >";
+    assert_display_snapshot!(parse_ty(ty), @"#[repr(C, u8)] #[no_niche] < A = 7 of {} | B of #[packed] { x : U64 } >");
+    assert_display_snapshot!(parse_ty("#[repr(C)] { b : U8, a : { d : U8, c : U8 } }"), @"#[repr(C)] { b : U8, a : { c : U8, d : U8 } }");
+}
+
+#[test]
+#[should_panic = "syntax error encountered"]
+fn repr_on_int() {
+    parse_ty("#[repr(C)] U8");
+}
+
+#[test]
+#[should_panic = "syntax error encountered"]
+fn packed_variant_type() {
+    parse_ty("#[packed] < A of {} | B of U8 >");
+}
+
+fn rust_decls(src: &str) -> String {
+    let decls = crate::rust::rust_decls(&parse_ty(src));
+    format!("{}\n\nroot: {}", decls.decls.join("\n\n"), decls.root)
+}
+
+#[test]
+fn rust_declarations() {
+    assert_display_snapshot!(rust_decls("µL. #[no_niche] #[repr(u8)] < Nil = 3 of {} | Cons of { type : U8, tl : Box[L] } >"), @r###"
+    #[repr(u8)]
+    // `#[no_niche]` has no stable equivalent in Rust.
+    enum T0 {
+        Cons(T1) = 0,
+        Nil = 3,
+    }
+
+    struct T1 {
+        tl: Box<T0>,
+        r#type: u8,
+    }
+
+    root: T0
+    "###);
+    assert_display_snapshot!(rust_decls(BOXED_TREE), @r###"
+    struct T0(Box<T1>);
+
+    enum T1 {
+        Leaf,
+        Node(T2),
+    }
+
+    struct T2 {
+        l: T0,
+        r: T0,
+        v: u64,
+    }
+
+    root: T0
+    "###);
+    assert_display_snapshot!(rust_decls("{ a : < A = 5 of {} | B of U32 >, b : < >, c : {} }"), @r###"
+    struct T0 {
+        a: T1,
+        b: T2,
+        c: (),
+    }
+
+    #[repr(u8)]
+    enum T1 {
+        A = 5,
+        B(u32) = 0,
+    }
+
+    enum T2 {}
+
+    root: T0
+    "###);
+}
+
+#[test]
+fn rust_program() {
+    let ty = parse_ty("#[repr(C)] < A of {} | B of U8 >");
+    let values = crate::rust::test_values(&ty);
+    assert_display_snapshot!(crate::rust::rust_program(&ty, &values), @r###"
+    #![allow(dead_code, non_camel_case_types)]
+
+    // #[repr(C)] < A of {} | B of U8 >
+
+    #[repr(C)]
+    enum T0 {
+        A,
+        B(u8),
+    }
+
+    /// The bytes of `value`, in hex. Padding is zero, unless the move copies
+    /// whatever was in the padding of `value`.
+    fn bytes<T>(value: T) -> String {
+        let mut slot = std::mem::MaybeUninit::<T>::zeroed();
+        slot.write(value);
+        let size = std::mem::size_of::<T>();
+        let bytes = unsafe { std::slice::from_raw_parts(slot.as_ptr().cast::<u8>(), size) };
+        bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+    }
+
+    fn main() {
+        println!("size {}", std::mem::size_of::<T0>());
+        println!("align {}", std::mem::align_of::<T0>());
+        // <A = {}>
+        println!("value {}", bytes::<T0>(T0::A));
+        // <B = 0_u8>
+        println!("value {}", bytes::<T0>(T0::B(0u8)));
+        // <B = 255_u8>
+        println!("value {}", bytes::<T0>(T0::B(255u8)));
+    }
+    "###);
+}
+
+#[test]
+fn rustc_cross_check() {
+    let sources = [
+        "< A of {} | B of U8 | C of U16 >",
+        "{ a : U8, b : U64, c : U8 }",
+        "< A of { x : U64, y : U8 } | B of U64 | C of {} >",
+        "#[repr(C)] { b : U8, a : U32 }",
+        "#[repr(C)] { a : #[repr(C)] { x : U64, y : U8 }, b : U8 }",
+        "#[repr(C)] < A of { x : U64, y : U8 } | B of U16 >",
+        BOXED_LIST,
+    ];
+    for src in sources {
+        let check = crate::rust::cross_check(&parse_ty(src)).unwrap();
+        assert!(check.layouts_match(), "{}:\n{}", src, check);
+        assert!(!check.values.is_empty());
+    }
+}
+
+fn c_header_with(src: &str, strategy: &'static dyn LayoutStrategy) -> String {
+    crate::c_header::c_header(&parse_ty(src), &mut LayoutCx::new(strategy))
+}
+
+/// Compile `header` and `main` with the C compiler, and run the program if
+/// `main` isn't empty.
+fn compile_c(name: &str, header: &str, main: &str) {
+    let dir = std::env::temp_dir().join(format!("type-layout-c-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("header.h"), header).unwrap();
+    let src = dir.join("main.c");
+    std::fs::write(&src, format!("#include \"header.h\"\n{}\n", main)).unwrap();
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+    let mut command = std::process::Command::new(cc);
+    command.args(["-std=gnu11", "-Wall", "-Wextra", "-Werror", "-o"]).arg(dir.join("main"));
+    if main.is_empty() {
+        command.arg("-fsyntax-only");
+    }
+    let output = command.arg(&src).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}\n{}", header, stderr);
+    if !main.is_empty() {
+        let status = std::process::Command::new(dir.join("main")).status().unwrap();
+        assert!(status.success(), "{}\n{}", header, main);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn c_header() {
+    assert_display_snapshot!(c_header_with(BOXED_LIST, layout_of::DEFAULT), @r###"
+    /* µ. Box[< Cons of { hd : U64, tl : #0 } | Nil of {} >] (layout strategy `niche`) */
+    /* The root type is `t0`, with size: 8 bytes, align: 8 bytes, stride: 8 bytes. */
+
+    #pragma once
+
+    #include <stddef.h>
+    #include <stdint.h>
+    #include <string.h>
+
+    struct t1;
+
+    typedef struct t1 *t0;
+
+    /* size: 16 bytes, align: 8 bytes, stride: 16 bytes */
+    struct __attribute__((aligned(8))) t1 {
+        uint64_t hd;
+        t0 tl;
+    };
+    _Static_assert(sizeof(struct t1) == 16, "size of struct t1");
+    _Static_assert(_Alignof(struct t1) == 8, "alignment of struct t1");
+    _Static_assert(offsetof(struct t1, hd) == 0, "offset of struct t1.hd");
+    _Static_assert(offsetof(struct t1, tl) == 8, "offset of struct t1.tl");
+
+    /* < Cons | Nil > as `struct t1` (tag layout: niche) */
+    enum v0 { V0_Cons, V0_Nil };
+
+    static inline enum v0 v0_variant(struct t1 const *value) {
+        uint64_t tag;
+        memcpy(&tag, &(*value).tl, sizeof tag);
+        switch (tag) {
+        case 0: return V0_Nil;
+        default: return V0_Cons;
+        }
+    }
+
+    static inline struct t1 v0_make_Cons(struct t1 field) {
+        struct t1 value;
+        memset(&value, 0, sizeof value);
+        value = field;
+        return value;
+    }
+
+    static inline struct t1 v0_make_Nil(void) {
+        struct t1 value;
+        memset(&value, 0, sizeof value);
+        uint64_t tag = 0;
+        memcpy(&value.tl, &tag, sizeof tag);
+        return value;
+    }
+
+    "###);
+}
+
+#[test]
+fn c_headers_compile() {
+    let types = [
+        BOXED_LIST,
+        BOXED_TREE,
+        "< None of {} | Some of < None of {} | Some of < F of {} | T of {} > > >",
+        "< A of { x : U64, y : < F of {} | T of {} > } | B of U64 >",
+        "< A of Box[U64] | B of Box[U32] | C of {} >",
+        "#[packed] { a : U8, b : U64, z : { int : U8, c : U16 } }",
+        "#[repr(C)] < A = 7 of {} | B of U16 >",
+        "#[repr(C)] { a : #[repr(C)] { x : U64, y : U8 }, b : < A of U16 | B of { c : U8, d : U8, e : U8 } > }",
+        "µX. Box[µY. < A of { x : Box[X], y : Box[Y] } | B of {} >]",
+        "< >",
+    ];
+    for (i, src) in types.into_iter().enumerate() {
+        for strategy in STRATEGIES {
+            compile_c(&format!("{}-{}", i, strategy.name()), &c_header_with(src, strategy), "");
+        }
+    }
+}
+
+#[test]
+fn c_header_accessors() {
+    let src = "< A of { x : U64, y : < F of {} | T of {} > } | B of U64 >";
+    let main = "int main(void) {
+        struct t0 a = v0_make_A(7, v1_make_T());
+        struct t0 b = v0_make_B(9);
+        return !(v0_variant(&a) == V0_A && v1_variant(&a.tag) == V1_T && a.data.A.x == 7
+            && v0_variant(&b) == V0_B && b.data.B == 9);
+    }";
+    compile_c("accessors", &c_header_with(src, layout_of::DEFAULT), main);
+}
@@ -77,6 +77,20 @@ pub fn display_map_like<K: Display, V: Display>(
     }
 }
 
+/// Left-align the columns of `rows`, separated by two spaces. The first row
+/// is the header.
+pub fn display_table(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<_> = (0..columns)
+        .map(|i| rows.iter().filter_map(|r| r.get(i)).map(|c| c.chars().count()).max().unwrap())
+        .collect();
+    let lines = rows.iter().map(|row| {
+        let cells = row.iter().zip(&widths).map(|(c, &w)| format!("{:w$}", c, w = w));
+        cells.intersperse(String::from("  ")).collect::<String>().trim_end().to_owned()
+    });
+    lines.intersperse(String::from("\n")).collect()
+}
+
 // RANGES ///
 
 pub fn range_values_count(r: RangeInclusive<u64>) -> Option<u64> {
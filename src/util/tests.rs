@@ -1,6 +1,7 @@
 use super::*;
 
 #[test]
+#[allow(clippy::reversed_empty_ranges)]
 fn test_range_values_count() {
     fn t(r: RangeInclusive<u64>, expect: Option<u64>) {
         assert_eq!(range_values_count(r), expect);
@@ -16,3 +17,12 @@ fn test_range_values_count() {
 
     t(0..=u64::MAX, None);
 }
+
+#[test]
+fn test_display_table() {
+    let rows = vec![
+        vec!["a".to_owned(), "bb".to_owned(), "c".to_owned()],
+        vec!["dddd".to_owned(), "e".to_owned(), "".to_owned()],
+    ];
+    assert_eq!(display_table(&rows), "a     bb  c\ndddd  e");
+}
@@ -0,0 +1,491 @@
+//! A second LIR backend, on bytes.
+//!
+//! [`compile()`] turns an expression into bytecode for a register machine
+//! whose registers hold 64-bit integers, mostly addresses in a [`Memory`].
+//! Every value lives in memory, laid out like its type says: records and
+//! unions are built by storing their fields at their offsets, `Select` is
+//! address arithmetic, and `Switch` loads its subject. Unlike in
+//! [`eval`](crate::eval), union members really overlap.
+//!
+//! Expressions are compiled against the type of the place that their value
+//! is stored in, rather than their own [`Expr::ty()`]: the values of
+//! niche-encoded variants only have the fields that hold the tag.
+
+use std::fmt::{Display, Formatter};
+
+use crate::eval::eval_root_expr;
+use crate::layout::Layout;
+use crate::lir::{Expr, Shape, Size, Ty, Value};
+use crate::lower::lower_layout;
+use crate::memory::{Memory, MemoryError};
+use crate::name::Name;
+use crate::util::Map;
+
+const PTR_SIZE: u64 = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Reg(usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instr {
+    /// Allocate zeroed memory for a value of `shape`, and put its address in
+    /// `dst`.
+    Alloc {
+        dst: Reg,
+        shape: Shape,
+    },
+    /// `dst = src + offset`, which must be in the same allocation as `src`.
+    Offset {
+        dst: Reg,
+        src: Reg,
+        offset: u64,
+    },
+    /// Read the `size`-byte integer at address `addr` into `dst`.
+    Load {
+        dst: Reg,
+        addr: Reg,
+        size: u64,
+    },
+    /// Write the low `size` bytes of `src` at address `addr`.
+    Store {
+        addr: Reg,
+        src: Reg,
+        size: u64,
+    },
+    /// Write the low `size` bytes of `value` at address `addr`.
+    StoreConst {
+        addr: Reg,
+        value: u64,
+        size: u64,
+    },
+    /// Copy `size` bytes from address `src` to address `dst`.
+    Copy {
+        dst: Reg,
+        src: Reg,
+        size: u64,
+    },
+    /// `dst = src | bits`.
+    Or {
+        dst: Reg,
+        src: Reg,
+        bits: u64,
+    },
+    /// `dst = src & mask`.
+    And {
+        dst: Reg,
+        src: Reg,
+        mask: u64,
+    },
+    /// Continue at instruction `target` if `src == value`.
+    JumpIfEq {
+        src: Reg,
+        value: u64,
+        target: usize,
+    },
+    Jump {
+        target: usize,
+    },
+    /// A `Switch` without a case for the value in `src`.
+    NoCase {
+        src: Reg,
+    },
+}
+
+/// Compiled bytecode. Running it stores the value of the expression at the
+/// address in `result`.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub code: Vec<Instr>,
+    pub result: Reg,
+    /// The type of the result.
+    pub ty: Ty,
+    registers: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError {
+    Memory(MemoryError),
+    /// A `Switch` had no case for `value`, and no default.
+    NoCase {
+        value: u64,
+    },
+}
+
+/// Compile `expr`, whose value has the closed type `ty`.
+pub fn compile(expr: &Expr, ty: &Ty) -> Program {
+    let mut cx = Compiler { code: vec![], registers: 0, vars: Map::new() };
+    let result = cx.alloc(ty);
+    cx.compile_into(expr, result, ty);
+    Program { code: cx.code, result, ty: ty.clone(), registers: cx.registers }
+}
+
+struct Compiler {
+    code: Vec<Instr>,
+    registers: usize,
+    /// The address of each variable's value, and its type.
+    vars: Map<Name, (Reg, Ty)>,
+}
+
+fn field_offset(ty: &Ty, field: &Name) -> (u64, Ty) {
+    match ty {
+        Ty::Record(fields, repr) => {
+            let shapes = fields.iter().map(|(_, t)| t.shape());
+            let (offsets, _) = Shape::record(shapes, *repr);
+            let idx = fields.iter().position(|(n, _)| n == field);
+            let idx = idx.unwrap_or_else(|| panic!("type {} has no field {}", ty, field));
+            (offsets[idx].bytes(), fields[idx].1.clone())
+        }
+        // Union values are transparent, so fields are selected from the
+        // member that has them. All members start at the start of the union.
+        Ty::UntaggedUnion(members) => members
+            .values()
+            .map(Ty::unfolded)
+            .find(|member| match member {
+                Ty::Record(fields, _) => fields.iter().any(|(n, _)| n == field),
+                _ => false,
+            })
+            .map(|member| field_offset(&member, field))
+            .unwrap_or_else(|| panic!("type {} has no member with field {}", ty, field)),
+        _ => panic!("type {} is not a record", ty),
+    }
+}
+
+fn ptr_tag_mask(bits: u32) -> u64 {
+    (1 << bits) - 1
+}
+
+impl Compiler {
+    fn reg(&mut self) -> Reg {
+        self.registers += 1;
+        Reg(self.registers - 1)
+    }
+
+    fn emit(&mut self, instr: Instr) -> usize {
+        self.code.push(instr);
+        self.code.len() - 1
+    }
+
+    fn alloc(&mut self, ty: &Ty) -> Reg {
+        let dst = self.reg();
+        self.emit(Instr::Alloc { dst, shape: ty.shape() });
+        dst
+    }
+
+    fn offset(&mut self, src: Reg, offset: u64) -> Reg {
+        if offset == 0 {
+            return src;
+        }
+        let dst = self.reg();
+        self.emit(Instr::Offset { dst, src, offset });
+        dst
+    }
+
+    fn load(&mut self, addr: Reg, size: u64) -> Reg {
+        let dst = self.reg();
+        self.emit(Instr::Load { dst, addr, size });
+        dst
+    }
+
+    /// Emit code that stores the value of `expr` at the address in `dst`.
+    fn compile_into(&mut self, expr: &Expr, dst: Reg, ty: &Ty) {
+        // Types are closed, so recursive types are unfolded when reached.
+        let ty = &ty.unfolded();
+        match expr {
+            Expr::Var(_) | Expr::Select { .. } | Expr::Deref(_) => {
+                let (src, src_ty) = self.place(expr);
+                self.copy(src, &src_ty, dst, ty);
+            }
+            // A zero-sized variant's binder can be bound to the untagged
+            // pointer, which doesn't fit in it.
+            Expr::TagPtr { .. } | Expr::UntagPtr { .. } | Expr::PtrTag { .. }
+                if ty.shape().size.bytes() < PTR_SIZE =>
+            {
+                let (src, src_ty) = self.place(expr);
+                self.copy(src, &src_ty, dst, ty);
+            }
+            Expr::Int(int_ty, n) => {
+                let size = int_ty.shape().size.bytes();
+                self.emit(Instr::StoreConst { addr: dst, value: *n, size });
+            }
+            Expr::Record(fields) => {
+                for (name, field) in fields {
+                    let (offset, field_ty) = field_offset(ty, name);
+                    let field_dst = self.offset(dst, offset);
+                    self.compile_into(field, field_dst, &field_ty);
+                }
+            }
+            // All members start at the start of the union.
+            Expr::UntaggedUnion { ty: _, field, value } => match ty {
+                Ty::UntaggedUnion(members) => self.compile_into(value, dst, &members[field]),
+                _ => panic!("type {} is not a union", ty),
+            },
+            Expr::Box(value) => match ty {
+                Ty::Ptr(pointee) => {
+                    let target = self.alloc(pointee);
+                    self.compile_into(value, target, pointee);
+                    self.emit(Instr::Store { addr: dst, src: target, size: PTR_SIZE });
+                }
+                _ => panic!("type {} is not a pointer", ty),
+            },
+            Expr::TagPtr { ptr, bits, tag } => {
+                assert!(*tag <= ptr_tag_mask(*bits), "tag {} does not fit in {} bits", tag, bits);
+                self.compile_into(ptr, dst, ty);
+                let ptr = self.load(dst, PTR_SIZE);
+                self.emit(Instr::Or { dst: ptr, src: ptr, bits: *tag });
+                self.emit(Instr::Store { addr: dst, src: ptr, size: PTR_SIZE });
+            }
+            Expr::PtrTag { ptr, bits } => {
+                let (src, _) = self.place(ptr);
+                let tag = self.load(src, PTR_SIZE);
+                self.emit(Instr::And { dst: tag, src: tag, mask: ptr_tag_mask(*bits) });
+                self.emit(Instr::Store { addr: dst, src: tag, size: PTR_SIZE });
+            }
+            Expr::UntagPtr { ptr, bits } => {
+                self.compile_into(ptr, dst, ty);
+                let ptr = self.load(dst, PTR_SIZE);
+                self.emit(Instr::And { dst: ptr, src: ptr, mask: !ptr_tag_mask(*bits) });
+                self.emit(Instr::Store { addr: dst, src: ptr, size: PTR_SIZE });
+            }
+            Expr::Switch { subj, cases, default } => {
+                let (addr, subj_ty) = self.vars[&subj.name].clone();
+                let subj = self.load(addr, subj_ty.shape().size.bytes());
+                // The jump targets are filled in once the cases are compiled.
+                let mut jumps = vec![];
+                for &value in cases.keys() {
+                    jumps.push(self.emit(Instr::JumpIfEq { src: subj, value, target: 0 }));
+                }
+                let mut ends = vec![];
+                match default {
+                    Some(default) => self.compile_into(default, dst, ty),
+                    None => {
+                        self.emit(Instr::NoCase { src: subj });
+                    }
+                }
+                ends.push(self.emit(Instr::Jump { target: 0 }));
+                for (jump, body) in jumps.into_iter().zip(cases.values()) {
+                    let start = self.code.len();
+                    if let Instr::JumpIfEq { target, .. } = &mut self.code[jump] {
+                        *target = start;
+                    }
+                    self.compile_into(body, dst, ty);
+                    ends.push(self.emit(Instr::Jump { target: 0 }));
+                }
+                let end = self.code.len();
+                for jump in ends {
+                    self.code[jump] = Instr::Jump { target: end };
+                }
+            }
+            Expr::Let { binder, value, body } => {
+                let addr = self.alloc(&binder.ty);
+                self.compile_into(value, addr, &binder.ty);
+                let outer = self.vars.insert(binder.name.clone(), (addr, binder.ty.clone()));
+                self.compile_into(body, dst, ty);
+                match outer {
+                    Some(outer) => self.vars.insert(binder.name.clone(), outer),
+                    None => self.vars.remove(&binder.name),
+                };
+            }
+        }
+    }
+
+    /// The address of the value of `expr`, and its type. Places that are
+    /// already in memory, like variables and their fields, aren't copied.
+    fn place(&mut self, expr: &Expr) -> (Reg, Ty) {
+        match expr {
+            Expr::Var(var) => self.vars[&var.name].clone(),
+            Expr::Select { record, field } => {
+                let (addr, record_ty) = self.place(record);
+                let (offset, field_ty) = field_offset(&record_ty.unfolded(), field);
+                (self.offset(addr, offset), field_ty)
+            }
+            Expr::Deref(ptr) => {
+                let (addr, ptr_ty) = self.place(ptr);
+                match ptr_ty.unfolded() {
+                    Ty::Ptr(pointee) => (self.load(addr, PTR_SIZE), *pointee),
+                    ty => panic!("type {} is not a pointer", ty),
+                }
+            }
+            _ => {
+                let ty = expr.ty();
+                let addr = self.alloc(&ty);
+                self.compile_into(expr, addr, &ty);
+                (addr, ty)
+            }
+        }
+    }
+
+    /// Copy a value of type `src_ty` to a place of type `dst_ty`. The types
+    /// can differ in the fields that records have: the values of
+    /// niche-encoded variants only have some fields, and a zero-sized
+    /// variant's field is the whole value if the tag is in a niche. Then the
+    /// fields that both have are copied one by one.
+    fn copy(&mut self, src: Reg, src_ty: &Ty, dst: Reg, dst_ty: &Ty) {
+        let (src_ty, dst_ty) = (src_ty.unfolded(), dst_ty.unfolded());
+        match (&src_ty, &dst_ty) {
+            (Ty::Record(fields, _), Ty::Record(dst_fields, _)) if src_ty != dst_ty => {
+                for (name, _) in fields {
+                    if !dst_fields.iter().any(|(n, _)| n == name) {
+                        continue;
+                    }
+                    let (src_offset, src_field_ty) = field_offset(&src_ty, name);
+                    let (dst_offset, dst_field_ty) = field_offset(&dst_ty, name);
+                    let (src, dst) = (self.offset(src, src_offset), self.offset(dst, dst_offset));
+                    self.copy(src, &src_field_ty, dst, &dst_field_ty);
+                }
+            }
+            _ => {
+                let size = src_ty.shape().size.min(dst_ty.shape().size).bytes();
+                if size > 0 {
+                    self.emit(Instr::Copy { dst, src, size });
+                }
+            }
+        }
+    }
+}
+
+impl Program {
+    /// Run the program in `memory`, and return the address of the result.
+    pub fn run(&self, memory: &mut Memory) -> Result<u64, VmError> {
+        let mut regs = vec![0; self.registers];
+        let mut pc = 0;
+        while let Some(instr) = self.code.get(pc) {
+            pc += 1;
+            match *instr {
+                Instr::Alloc { dst, shape } => regs[dst.0] = memory.allocate(shape),
+                Instr::Offset { dst, src, offset } => {
+                    regs[dst.0] = memory.offset(regs[src.0], offset)?
+                }
+                Instr::Load { dst, addr, size } => {
+                    regs[dst.0] = memory.read_int(regs[addr.0], size)?
+                }
+                Instr::Store { addr, src, size } => {
+                    memory.write_int(regs[addr.0], size, regs[src.0])?
+                }
+                Instr::StoreConst { addr, value, size } => {
+                    memory.write_int(regs[addr.0], size, value)?
+                }
+                Instr::Copy { dst, src, size } => {
+                    let bytes = memory.read(regs[src.0], size)?.to_vec();
+                    memory.write(regs[dst.0], &bytes)?;
+                }
+                Instr::Or { dst, src, bits } => regs[dst.0] = regs[src.0] | bits,
+                Instr::And { dst, src, mask } => regs[dst.0] = regs[src.0] & mask,
+                Instr::JumpIfEq { src, value, target } => {
+                    if regs[src.0] == value {
+                        pc = target;
+                    }
+                }
+                Instr::Jump { target } => pc = target,
+                Instr::NoCase { src } => return Err(VmError::NoCase { value: regs[src.0] }),
+            }
+        }
+        Ok(regs[self.result.0])
+    }
+}
+
+/// The results of evaluating an expression with both backends.
+#[derive(Debug, Clone)]
+pub struct Differential {
+    /// The result of [`eval`](crate::eval).
+    pub value: Value,
+    /// The memory after running the bytecode, and the address of its result.
+    pub memory: Memory,
+    pub addr: u64,
+    /// The result read back from `memory`, following the tags of the
+    /// layout.
+    pub read_back: Result<Value, MemoryError>,
+}
+
+impl Differential {
+    /// Whether the bytecode stored the value that the tree-walking evaluator
+    /// computed. Variants are told apart by their tags, so storing another
+    /// variant whose bytes also fit the value doesn't count.
+    pub fn agrees(&self) -> bool {
+        self.read_back.as_ref() == Ok(&self.value)
+    }
+}
+
+/// Evaluate `expr`, with the closed `layout`, with both backends.
+pub fn differential(expr: Expr, layout: &Layout) -> Result<Differential, VmError> {
+    let ty = lower_layout(layout.clone());
+    let program = compile(&expr, &ty);
+    let mut memory = Memory::new();
+    let addr = program.run(&mut memory)?;
+    let value = eval_root_expr(expr);
+    let read_back = memory.decode(addr, &ty, layout);
+    Ok(Differential { value, memory, addr, read_back })
+}
+
+impl From<MemoryError> for VmError {
+    fn from(err: MemoryError) -> Self {
+        VmError::Memory(err)
+    }
+}
+
+impl Display for Reg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "r{}", self.0)
+    }
+}
+
+impl Display for Instr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instr::Alloc { dst, shape } => {
+                write!(f, "{} = alloc {}, align {}", dst, shape.size.bytes(), shape.align.bytes())
+            }
+            Instr::Offset { dst, src, offset } => write!(f, "{} = {} + {}", dst, src, offset),
+            Instr::Load { dst, addr, size } => write!(f, "{} = load{} [{}]", dst, size * 8, addr),
+            Instr::Store { addr, src, size } => write!(f, "store{} [{}], {}", size * 8, addr, src),
+            Instr::StoreConst { addr, value, size } => {
+                write!(f, "store{} [{}], {}", size * 8, addr, value)
+            }
+            Instr::Copy { dst, src, size } => {
+                write!(f, "copy {} [{}], [{}]", Size::from_bytes(*size), dst, src)
+            }
+            Instr::Or { dst, src, bits } => write!(f, "{} = {} | {:#x}", dst, src, bits),
+            Instr::And { dst, src, mask } => write!(f, "{} = {} & {:#x}", dst, src, mask),
+            Instr::JumpIfEq { src, value, target } => {
+                write!(f, "if {} == {} goto {}", src, value, target)
+            }
+            Instr::Jump { target } => write!(f, "goto {}", target),
+            Instr::NoCase { src } => write!(f, "no case for {}", src),
+        }
+    }
+}
+
+/// One instruction per line, numbered, then where the result is.
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = self.code.len().saturating_sub(1).to_string().len();
+        for (i, instr) in self.code.iter().enumerate() {
+            writeln!(f, "{:>1$}: {2}", i, width, instr)?;
+        }
+        write!(f, "result: [{}] : {}", self.result, self.ty)
+    }
+}
+
+impl Display for Differential {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "eval: {}", self.value)?;
+        match &self.read_back {
+            Ok(read_back) => writeln!(f, "vm:   {} at {:#x}", read_back, self.addr)?,
+            Err(err) => writeln!(f, "vm:   unreadable result at {:#x}: {}", self.addr, err)?,
+        }
+        if self.agrees() {
+            writeln!(f, "the VM's bytes hold eval's value")?;
+        } else {
+            writeln!(f, "mismatch: the VM's bytes don't hold eval's value")?;
+        }
+        write!(f, "{}", self.memory)
+    }
+}
+
+impl Display for VmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VmError::Memory(err) => err.fmt(f),
+            VmError::NoCase { value } => write!(f, "no matching case found for {}", value),
+        }
+    }
+}